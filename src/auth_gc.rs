@@ -0,0 +1,863 @@
+use crate::circuit::Sha256Circuit;
+use crate::circuit::XorAndGate;
+use crate::gc::GarbledCircuit;
+use crate::gc::WireLabel;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::ops::BitXor;
+
+/// One party's share of an authenticated wire mask λ = r ⊕ s ([WRK17](https://eprint.iacr.org/2017/030)).
+///
+/// The garbler holds `bit = r`, its MAC `mac = M_A[r] = K_B[r] ⊕ r·Δ_B` and the key `key = K_A[s]`
+/// of the evaluator's share. The evaluator holds `bit = s`, `mac = M_B[s] = K_A[s] ⊕ s·Δ_A` and
+/// `key = K_B[r]`. Shares are XOR-homomorphic, so XOR gates are local.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct AuthShare {
+    pub bit: bool,
+    pub mac: WireLabel,
+    pub key: WireLabel,
+}
+
+impl AuthShare {
+    pub fn zero() -> Self {
+        AuthShare {
+            bit: false,
+            mac: WireLabel::zero(),
+            key: WireLabel::zero(),
+        }
+    }
+
+    //c·share
+    fn select(self, c: bool) -> Self {
+        if c {
+            self
+        } else {
+            AuthShare::zero()
+        }
+    }
+
+    //Add a public constant to λ: the garbler flips its bit, the evaluator shifts its key by Δ_B
+    fn add_constant_garbler(mut self, c: bool) -> Self {
+        self.bit ^= c;
+        self
+    }
+
+    fn add_constant_evaluator(mut self, c: bool, delta_b: WireLabel) -> Self {
+        if c {
+            self.key ^= delta_b;
+        }
+        self
+    }
+
+    /// Open this share to the other party, which checks it against its key.
+    pub fn reveal(&self) -> MacedBit {
+        MacedBit {
+            bit: self.bit,
+            mac: self.mac,
+        }
+    }
+
+    /// Check a bit opened by the other party against our key, `delta` is our own global key.
+    pub fn verifies(&self, opened: &MacedBit, delta: &WireLabel) -> bool {
+        let expected = if opened.bit {
+            self.key ^ *delta
+        } else {
+            self.key
+        };
+        opened.mac == expected
+    }
+}
+
+impl BitXor for AuthShare {
+    type Output = AuthShare;
+
+    fn bitxor(self, rhs: AuthShare) -> AuthShare {
+        AuthShare {
+            bit: self.bit ^ rhs.bit,
+            mac: self.mac ^ rhs.mac,
+            key: self.key ^ rhs.key,
+        }
+    }
+}
+
+/// A share opened together with its MAC.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MacedBit {
+    pub bit: bool,
+    pub mac: WireLabel,
+}
+
+/// The evaluator's opening of an output wire to the garbler: its label, which only a correct
+/// evaluation gives, and its share of the output mask.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct OutputOpening {
+    pub label: WireLabel,
+    pub mask: MacedBit,
+}
+
+/// Preprocessing of a single AND gate: shares of λ_α·λ_β and of the output mask λ_γ.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct AndPrep {
+    pub product: AuthShare,
+    pub output: AuthShare,
+}
+
+/// One party's preprocessing of a block: masks of the secret input wires (P0's then P1's wire
+/// for every message bit) and one `AndPrep` per AND gate, in gate order.
+#[derive(Serialize, Deserialize)]
+pub struct AuthPrep {
+    pub inputs: Vec<AuthShare>,
+    pub and_gates: Vec<AndPrep>,
+}
+
+/// An input wire of a block.
+pub enum BlockInput {
+    Secret { p0_wire: usize, p1_wire: usize }, //one message bit, XOR-shared between P0 and P1
+    Public { wire: usize, bit: bool },         //padding bits, λ = 0
+}
+
+/// The extra XOR gates joining both shares of every message bit of block `block`, and the
+/// input wires of that block (the chained state wires excluded).
+#[allow(clippy::needless_range_loop)]
+pub fn block_inputs(
+    circuit: &Sha256Circuit,
+    secret_bits_cnt: usize,
+    overall_bits: &[bool],
+    block: usize,
+) -> (Vec<XorAndGate>, Vec<BlockInput>) {
+    let mut extra_double_gates: Vec<XorAndGate> = Vec::new();
+    let mut inputs: Vec<BlockInput> = Vec::new();
+    let block_bits_len: usize = circuit.block_bits_len();
+    for j in block * block_bits_len..(block + 1) * block_bits_len {
+        let circuit_wire_id: usize = block_bits_len - 1 - (j % block_bits_len);
+        if j < secret_bits_cnt {
+            let gate = XorAndGate {
+                input0: circuit.extra_input_wire + 2 * j,
+                input1: circuit.extra_input_wire + 2 * j + 1,
+                output: circuit_wire_id,
+                input0_flipped: false,
+                input1_flipped: false,
+                gate_type: false,
+            };
+            inputs.push(BlockInput::Secret {
+                p0_wire: gate.input0,
+                p1_wire: gate.input1,
+            });
+            extra_double_gates.push(gate);
+        } else {
+            inputs.push(BlockInput::Public {
+                wire: circuit_wire_id,
+                bit: overall_bits[j],
+            });
+        }
+    }
+    (extra_double_gates, inputs)
+}
+
+//The wire holding output k of a block (before the inverter the circuit may put on it)
+fn output_source(circuit: &Sha256Circuit, k: usize) -> usize {
+    let output_wire = &circuit.output_wire_ids[k];
+    if output_wire.should_trace {
+        output_wire.input_id
+    } else {
+        output_wire.id
+    }
+}
+
+/// A local stand-in for the preprocessing functionality F_pre of WRK17: it knows every wire mask
+/// and deals authenticated shares of them to both parties. A real deployment replaces it with
+/// a two-party protocol (e.g. TinyOT-style authenticated AND triples).
+pub struct TrustedDealer {
+    delta_a: WireLabel,
+    delta_b: WireLabel,
+    rng: StdRng,
+    state: Vec<bool>, //λ of the chained state wires
+}
+
+impl TrustedDealer {
+    pub fn new(circuit: &Sha256Circuit, delta_a: WireLabel) -> Self {
+        let mut rng = StdRng::from_entropy();
+        let delta_b = WireLabel::from_data(rng.gen());
+        TrustedDealer {
+            delta_a,
+            delta_b,
+            rng,
+            state: vec![false; circuit.state_bits_len()], //the initial hash value is public
+        }
+    }
+
+    /// The evaluator's global MAC key
+    pub fn delta_b(&self) -> WireLabel {
+        self.delta_b
+    }
+
+    //Authenticated shares of λ: (garbler's, evaluator's)
+    fn share(&mut self, lambda: bool) -> (AuthShare, AuthShare) {
+        let r: bool = self.rng.gen();
+        let s: bool = lambda ^ r;
+        let key_r = WireLabel::from_data(self.rng.gen());
+        let key_s = WireLabel::from_data(self.rng.gen());
+        let mac_r = if r { key_r ^ self.delta_b } else { key_r };
+        let mac_s = if s { key_s ^ self.delta_a } else { key_s };
+        (
+            AuthShare {
+                bit: r,
+                mac: mac_r,
+                key: key_s,
+            },
+            AuthShare {
+                bit: s,
+                mac: mac_s,
+                key: key_r,
+            },
+        )
+    }
+
+    /// Deal the preprocessing of one block, `circuit` must already hold the block's extra gates.
+    /// Returns the garbler's and the evaluator's part.
+    pub fn deal_block(
+        &mut self,
+        circuit: &Sha256Circuit,
+        inputs: &[BlockInput],
+    ) -> (AuthPrep, AuthPrep) {
+        let mut garbler = AuthPrep {
+            inputs: Vec::new(),
+            and_gates: Vec::new(),
+        };
+        let mut evaluator = AuthPrep {
+            inputs: Vec::new(),
+            and_gates: Vec::new(),
+        };
+        let mut masks: HashMap<usize, bool> = HashMap::new();
+        for input in inputs {
+            match *input {
+                BlockInput::Secret { p0_wire, p1_wire } => {
+                    for wire in [p0_wire, p1_wire] {
+                        let lambda: bool = self.rng.gen();
+                        let (g, e) = self.share(lambda);
+                        garbler.inputs.push(g);
+                        evaluator.inputs.push(e);
+                        masks.insert(wire, lambda);
+                    }
+                }
+                BlockInput::Public { wire, .. } => {
+                    masks.insert(wire, false);
+                }
+            }
+        }
+        for (k, &lambda) in self.state.iter().enumerate() {
+            masks.insert(circuit.block_bits_len() + k, lambda);
+        }
+
+        for gate in &circuit.extra_gates {
+            let a: bool = masks[&gate.input0] ^ gate.input0_flipped;
+            let b: bool = masks[&gate.input1] ^ gate.input1_flipped;
+            if gate.gate_type {
+                let (g_product, e_product) = self.share(a && b);
+                let lambda: bool = self.rng.gen();
+                let (g_output, e_output) = self.share(lambda);
+                garbler.and_gates.push(AndPrep {
+                    product: g_product,
+                    output: g_output,
+                });
+                evaluator.and_gates.push(AndPrep {
+                    product: e_product,
+                    output: e_output,
+                });
+                masks.insert(gate.output, lambda);
+            } else {
+                masks.insert(gate.output, a ^ b);
+            }
+        }
+
+        for k in 0..circuit.state_bits_len() {
+            self.state[k] =
+                masks[&output_source(circuit, k)] ^ circuit.output_wire_ids[k].should_trace;
+        }
+        (garbler, evaluator)
+    }
+}
+
+/// An authenticated garbled row: the evaluator's share r of the output mask, its MAC and the
+/// garbler's part of the output label, all encrypted under the input labels.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct AuthRow {
+    pub bit: bool,
+    pub mac: WireLabel,
+    pub label: WireLabel,
+}
+
+/// The four rows of an authenticated AND gate, indexed by the masked input values 2Λ_α + Λ_β.
+#[derive(Serialize, Deserialize)]
+pub struct AuthGarbledAnd {
+    pub rows: [AuthRow; 4],
+}
+
+//H(L_α, L_β, γ) stretched to one bit and two labels
+fn row_pad(
+    hasher: &GarbledCircuit,
+    la: &WireLabel,
+    lb: &WireLabel,
+    gate: u64,
+) -> (bool, WireLabel, WireLabel) {
+    (
+        hasher.dual_key_hash(la, lb, 3 * gate).check_lsb(),
+        hasher.dual_key_hash(la, lb, 3 * gate + 1),
+        hasher.dual_key_hash(la, lb, 3 * gate + 2),
+    )
+}
+
+//Garbler's view of a wire: the label of masked value 0 and its share of λ
+#[derive(Clone, Copy)]
+struct GarblerWire {
+    label: WireLabel,
+    share: AuthShare,
+}
+
+//Evaluator's view of a wire: the masked value Λ = v ⊕ λ, its label and its share of λ
+#[derive(Clone, Copy)]
+struct EvaluatorWire {
+    label: WireLabel,
+    masked: bool,
+    share: AuthShare,
+}
+
+/// P0's side of authenticated garbling, Δ_A is the free-XOR offset of its labels.
+pub struct AuthGarbler {
+    gc: GarbledCircuit,
+    gate_idx: u64,
+    state: Vec<GarblerWire>,   //inputs of the next block
+    outputs: Vec<GarblerWire>, //outputs of the last garbled block
+}
+
+impl AuthGarbler {
+    pub fn new(circuit: &Sha256Circuit) -> Self {
+        let gc = GarbledCircuit::new();
+        let state = (0..circuit.state_bits_len())
+            .map(|k| GarblerWire {
+                label: if circuit.get_initial_hash(k) {
+                    gc.global_r
+                } else {
+                    WireLabel::zero()
+                },
+                share: AuthShare::zero(),
+            })
+            .collect();
+        AuthGarbler {
+            gc,
+            gate_idx: 0,
+            state,
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn delta(&self) -> WireLabel {
+        self.gc.global_r
+    }
+
+    /// Garble one block, `masked` holds Λ of every secret input wire in `prep.inputs` order.
+    /// Returns the labels of the masked input values and the AND tables.
+    pub fn garble_block(
+        &mut self,
+        circuit: &Sha256Circuit,
+        inputs: &[BlockInput],
+        prep: &AuthPrep,
+        masked: &[bool],
+    ) -> (Vec<WireLabel>, Vec<AuthGarbledAnd>) {
+        let delta = self.gc.global_r;
+        let mut wires: HashMap<usize, GarblerWire> = HashMap::new();
+        let mut input_labels: Vec<WireLabel> = Vec::with_capacity(prep.inputs.len());
+        for input in inputs {
+            match *input {
+                BlockInput::Secret { p0_wire, p1_wire } => {
+                    for wire in [p0_wire, p1_wire] {
+                        let k = input_labels.len();
+                        let label = WireLabel::from_data(self.gc.rng.gen());
+                        input_labels.push(if masked[k] { label ^ delta } else { label });
+                        wires.insert(
+                            wire,
+                            GarblerWire {
+                                label,
+                                share: prep.inputs[k],
+                            },
+                        );
+                    }
+                }
+                BlockInput::Public { wire, bit } => {
+                    let label = if bit { delta } else { WireLabel::zero() };
+                    wires.insert(
+                        wire,
+                        GarblerWire {
+                            label,
+                            share: AuthShare::zero(),
+                        },
+                    );
+                }
+            }
+        }
+        for (k, wire) in self.state.iter().enumerate() {
+            wires.insert(circuit.block_bits_len() + k, *wire);
+        }
+
+        let mut tables: Vec<AuthGarbledAnd> = Vec::with_capacity(prep.and_gates.len());
+        for gate in &circuit.extra_gates {
+            let mut a = wires[&gate.input0];
+            a.share = a.share.add_constant_garbler(gate.input0_flipped);
+            let mut b = wires[&gate.input1];
+            b.share = b.share.add_constant_garbler(gate.input1_flipped);
+            if gate.gate_type {
+                let and_prep = prep.and_gates[tables.len()];
+                let label = WireLabel::from_data(self.gc.rng.gen());
+                self.gate_idx += 1;
+
+                //Row (x, y): shares of λ_γ ⊕ (λ_α ⊕ x)(λ_β ⊕ y), the garbler takes the constant xy
+                let rows = [0, 1, 2, 3].map(|row: usize| {
+                    let (x, y) = (row >> 1 == 1, row & 1 == 1);
+                    let r = (and_prep.output
+                        ^ and_prep.product
+                        ^ b.share.select(x)
+                        ^ a.share.select(y))
+                    .add_constant_garbler(x && y);
+                    let la = if x { a.label ^ delta } else { a.label };
+                    let lb = if y { b.label ^ delta } else { b.label };
+                    let (pad_bit, pad_mac, pad_label) = row_pad(&self.gc, &la, &lb, self.gate_idx);
+                    let masked_label = if r.bit { label ^ delta } else { label };
+                    AuthRow {
+                        bit: r.bit ^ pad_bit,
+                        mac: r.mac ^ pad_mac,
+                        label: masked_label ^ r.key ^ pad_label,
+                    }
+                });
+                tables.push(AuthGarbledAnd { rows });
+                wires.insert(
+                    gate.output,
+                    GarblerWire {
+                        label,
+                        share: and_prep.output,
+                    },
+                );
+            } else {
+                wires.insert(
+                    gate.output,
+                    GarblerWire {
+                        label: a.label ^ b.label,
+                        share: a.share ^ b.share,
+                    },
+                );
+            }
+        }
+
+        self.outputs = (0..circuit.state_bits_len())
+            .map(|k| wires[&output_source(circuit, k)])
+            .collect();
+        for (k, wire) in self.outputs.iter().enumerate() {
+            self.state[k] = GarblerWire {
+                label: wire.label,
+                share: wire
+                    .share
+                    .add_constant_garbler(circuit.output_wire_ids[k].should_trace),
+            };
+        }
+        (input_labels, tables)
+    }
+
+    /// Open the garbler's shares of the output masks so that the evaluator can decode the digest.
+    pub fn output_masks(&self, circuit: &Sha256Circuit) -> Vec<MacedBit> {
+        self.outputs[circuit.digest_outputs()]
+            .iter()
+            .map(|wire| wire.share.reveal())
+            .collect()
+    }
+
+    /// Decode the digest outputs of the last block from the evaluator's openings: every label
+    /// must be one of the two labels of its wire, and every mask share must carry a valid MAC.
+    pub fn decode_outputs(
+        &self,
+        circuit: &Sha256Circuit,
+        openings: &[OutputOpening],
+    ) -> io::Result<Vec<bool>> {
+        let outputs = &self.outputs[circuit.digest_outputs()];
+        if openings.len() != outputs.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Expected {} output openings, received {}",
+                    outputs.len(),
+                    openings.len()
+                ),
+            ));
+        }
+        outputs
+            .iter()
+            .zip(openings)
+            .enumerate()
+            .map(|(k, (wire, opening))| {
+                let masked = if opening.label == wire.label {
+                    false
+                } else if opening.label == wire.label ^ self.gc.global_r {
+                    true
+                } else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Output label {} is not a valid label of its wire", k),
+                    ));
+                };
+                if !wire.share.verifies(&opening.mask, &self.gc.global_r) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "MAC check failed on an output mask",
+                    ));
+                }
+                Ok(masked ^ wire.share.bit ^ opening.mask.bit)
+            })
+            .collect()
+    }
+}
+
+/// P1's side of authenticated garbling, Δ_B is its global MAC key.
+pub struct AuthEvaluator {
+    gc: GarbledCircuit,
+    delta_b: WireLabel,
+    gate_idx: u64,
+    state: Vec<EvaluatorWire>,
+    outputs: Vec<EvaluatorWire>,
+}
+
+impl AuthEvaluator {
+    pub fn new(circuit: &Sha256Circuit, delta_b: WireLabel) -> Self {
+        let state = (0..circuit.state_bits_len())
+            .map(|k| EvaluatorWire {
+                label: WireLabel::zero(),
+                masked: circuit.get_initial_hash(k),
+                share: AuthShare::zero(),
+            })
+            .collect();
+        AuthEvaluator {
+            gc: GarbledCircuit::new(),
+            delta_b,
+            gate_idx: 0,
+            state,
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn delta(&self) -> WireLabel {
+        self.delta_b
+    }
+
+    /// Evaluate one block, aborting as soon as a garbled row carries a wrong MAC.
+    pub fn evaluate_block(
+        &mut self,
+        circuit: &Sha256Circuit,
+        inputs: &[BlockInput],
+        prep: &AuthPrep,
+        input_labels: &[WireLabel],
+        masked: &[bool],
+        tables: &[AuthGarbledAnd],
+    ) -> io::Result<()> {
+        let mut wires: HashMap<usize, EvaluatorWire> = HashMap::new();
+        let mut k: usize = 0;
+        for input in inputs {
+            match *input {
+                BlockInput::Secret { p0_wire, p1_wire } => {
+                    for wire in [p0_wire, p1_wire] {
+                        wires.insert(
+                            wire,
+                            EvaluatorWire {
+                                label: input_labels[k],
+                                masked: masked[k],
+                                share: prep.inputs[k],
+                            },
+                        );
+                        k += 1;
+                    }
+                }
+                BlockInput::Public { wire, bit } => {
+                    wires.insert(
+                        wire,
+                        EvaluatorWire {
+                            label: WireLabel::zero(),
+                            masked: bit,
+                            share: AuthShare::zero(),
+                        },
+                    );
+                }
+            }
+        }
+        for (k, wire) in self.state.iter().enumerate() {
+            wires.insert(circuit.block_bits_len() + k, *wire);
+        }
+
+        let mut and_idx: usize = 0;
+        for gate in &circuit.extra_gates {
+            let mut a = wires[&gate.input0];
+            a.share = a
+                .share
+                .add_constant_evaluator(gate.input0_flipped, self.delta_b);
+            let mut b = wires[&gate.input1];
+            b.share = b
+                .share
+                .add_constant_evaluator(gate.input1_flipped, self.delta_b);
+            if gate.gate_type {
+                let and_prep = prep.and_gates[and_idx];
+                let row = tables[and_idx].rows[2 * a.masked as usize + b.masked as usize];
+                and_idx += 1;
+                self.gate_idx += 1;
+
+                let (pad_bit, pad_mac, pad_label) =
+                    row_pad(&self.gc, &a.label, &b.label, self.gate_idx);
+                let r_bit = row.bit ^ pad_bit;
+                let r_mac = row.mac ^ pad_mac;
+                let s = and_prep.output
+                    ^ and_prep.product
+                    ^ b.share.select(a.masked)
+                    ^ a.share.select(b.masked);
+                let key = s
+                    .add_constant_evaluator(a.masked && b.masked, self.delta_b)
+                    .key;
+                if !AuthShare::verifies(
+                    &AuthShare { key, ..s },
+                    &MacedBit {
+                        bit: r_bit,
+                        mac: r_mac,
+                    },
+                    &self.delta_b,
+                ) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("MAC check failed on AND gate {}", and_idx - 1),
+                    ));
+                }
+                wires.insert(
+                    gate.output,
+                    EvaluatorWire {
+                        label: row.label ^ pad_label ^ s.mac,
+                        masked: r_bit ^ s.bit,
+                        share: and_prep.output,
+                    },
+                );
+            } else {
+                wires.insert(
+                    gate.output,
+                    EvaluatorWire {
+                        label: a.label ^ b.label,
+                        masked: a.masked ^ b.masked,
+                        share: a.share ^ b.share,
+                    },
+                );
+            }
+        }
+
+        self.outputs = (0..circuit.state_bits_len())
+            .map(|k| wires[&output_source(circuit, k)])
+            .collect();
+        for (k, wire) in self.outputs.iter().enumerate() {
+            self.state[k] = EvaluatorWire {
+                share: wire
+                    .share
+                    .add_constant_evaluator(circuit.output_wire_ids[k].should_trace, self.delta_b),
+                ..*wire
+            };
+        }
+        Ok(())
+    }
+
+    /// Decode the digest outputs of the last block with the garbler's opened output masks.
+    pub fn decode_outputs(
+        &self,
+        circuit: &Sha256Circuit,
+        output_masks: &[MacedBit],
+    ) -> io::Result<Vec<bool>> {
+        let outputs = &self.outputs[circuit.digest_outputs()];
+        if output_masks.len() != outputs.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Expected {} output masks, received {}",
+                    outputs.len(),
+                    output_masks.len()
+                ),
+            ));
+        }
+        outputs
+            .iter()
+            .zip(output_masks)
+            .map(|(wire, mask)| {
+                if wire.share.verifies(mask, &self.delta_b) {
+                    Ok(wire.masked ^ wire.share.bit ^ mask.bit)
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "MAC check failed on an output mask",
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    /// Open the digest outputs of the last block to the garbler, see
+    /// [`AuthGarbler::decode_outputs`].
+    pub fn output_openings(&self, circuit: &Sha256Circuit) -> Vec<OutputOpening> {
+        self.outputs[circuit.digest_outputs()]
+            .iter()
+            .map(|wire| OutputOpening {
+                label: wire.label,
+                mask: wire.share.reveal(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::MemoryChannel;
+    use crate::circuit::HashFunction;
+    use crate::party::Party;
+    use crate::utils::{convert_bits2_bytes, convert_bytes2_bits, pad_message_bits};
+    use sha2::{Digest, Sha256};
+    use std::thread;
+
+    //Garble and evaluate the single block of SHA-256(x0 ⊕ x1) locally, `tamper` may change the
+    //AND tables and the output masks on their way to the evaluator. Returns the digest the
+    //evaluator decodes and the garbler's decoding of the evaluator's openings.
+    fn run_block(
+        x0: &[u8],
+        x1: &[u8],
+        tamper: impl FnOnce(&mut [AuthGarbledAnd], &mut [MacedBit]),
+    ) -> io::Result<(Vec<u8>, io::Result<Vec<u8>>)> {
+        run_block_with_openings(x0, x1, tamper, |_| {})
+    }
+
+    fn run_block_with_openings(
+        x0: &[u8],
+        x1: &[u8],
+        tamper: impl FnOnce(&mut [AuthGarbledAnd], &mut [MacedBit]),
+        tamper_openings: impl FnOnce(&mut [OutputOpening]),
+    ) -> io::Result<(Vec<u8>, io::Result<Vec<u8>>)> {
+        let mut circuit = Sha256Circuit::new().unwrap();
+        let (x0, x1) = (convert_bytes2_bits(x0), convert_bytes2_bits(x1));
+        let overall_bits = pad_message_bits(x0.len(), HashFunction::Sha256);
+        let mut garbler = AuthGarbler::new(&circuit);
+        let mut dealer = TrustedDealer::new(&circuit, garbler.delta());
+        let mut evaluator = AuthEvaluator::new(&circuit, dealer.delta_b());
+
+        let (extra_double_gates, inputs) = block_inputs(&circuit, x0.len(), &overall_bits, 0);
+        circuit.update_extra_circuit(&extra_double_gates);
+        let (garbler_prep, evaluator_prep) = dealer.deal_block(&circuit, &inputs);
+        //Λ = v ⊕ λ, both shares of λ opened as the parties would after the MAC checks
+        let masked: Vec<bool> = x0
+            .iter()
+            .zip(&x1)
+            .flat_map(|(&b0, &b1)| [b0, b1])
+            .enumerate()
+            .map(|(k, bit)| bit ^ garbler_prep.inputs[k].bit ^ evaluator_prep.inputs[k].bit)
+            .collect();
+
+        let (input_labels, mut tables) =
+            garbler.garble_block(&circuit, &inputs, &garbler_prep, &masked);
+        let mut output_masks = garbler.output_masks(&circuit);
+        tamper(&mut tables, &mut output_masks);
+        evaluator.evaluate_block(
+            &circuit,
+            &inputs,
+            &evaluator_prep,
+            &input_labels,
+            &masked,
+            &tables,
+        )?;
+        let to_digest = |mut bits: Vec<bool>| {
+            bits.reverse();
+            convert_bits2_bytes(&bits)
+        };
+        let output_bits = evaluator.decode_outputs(&circuit, &output_masks)?;
+        let mut openings = evaluator.output_openings(&circuit);
+        tamper_openings(&mut openings);
+        let garbler_output = garbler.decode_outputs(&circuit, &openings).map(to_digest);
+        Ok((to_digest(output_bits), garbler_output))
+    }
+
+    #[test]
+    fn honest_block_gives_the_digest() {
+        let (digest, garbler_digest) = run_block(b"abc", b"xyz", |_, _| {}).unwrap();
+        let message: Vec<u8> = b"abc".iter().zip(b"xyz").map(|(a, b)| a ^ b).collect();
+        assert_eq!(digest, Sha256::digest(&message).to_vec());
+        assert_eq!(garbler_digest.unwrap(), digest);
+    }
+
+    #[test]
+    fn forged_output_openings_are_rejected() {
+        let forged_label = run_block_with_openings(
+            b"abc",
+            b"xyz",
+            |_, _| {},
+            |openings| openings[5].label ^= WireLabel::from_data([1; 16]),
+        );
+        let err = forged_label.unwrap().1.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Output label 5 is not a valid label of its wire"
+        );
+
+        let flipped_mask = run_block_with_openings(
+            b"abc",
+            b"xyz",
+            |_, _| {},
+            |openings| openings[9].mask.bit = !openings[9].mask.bit,
+        );
+        let err = flipped_mask.unwrap().1.unwrap_err();
+        assert_eq!(err.to_string(), "MAC check failed on an output mask");
+    }
+
+    #[test]
+    fn protocol_gives_both_parties_the_digest() {
+        let (mut c0, mut c1) = MemoryChannel::pair();
+        let p0 = thread::spawn(move || {
+            let mut p0 = Party::new(0, b"abc").unwrap();
+            p0.run_auth_garbler(&mut c0).unwrap()
+        });
+        let mut p1 = Party::new(1, b"xyz").unwrap();
+        let digest = p1.run_auth_evaluator(&mut c1).unwrap();
+        let message: Vec<u8> = b"abc".iter().zip(b"xyz").map(|(a, b)| a ^ b).collect();
+        assert_eq!(digest, Sha256::digest(&message).to_vec());
+        assert_eq!(p0.join().unwrap(), digest);
+    }
+
+    #[test]
+    fn corrupted_row_mac_aborts() {
+        let err = run_block(b"abc", b"xyz", |tables, _| {
+            for row in tables[100].rows.iter_mut() {
+                row.mac ^= WireLabel::from_data([0x80; 16]);
+            }
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "MAC check failed on AND gate 100");
+    }
+
+    #[test]
+    fn corrupted_row_bit_aborts() {
+        let err = run_block(b"abc", b"xyz", |tables, _| {
+            for row in tables[7].rows.iter_mut() {
+                row.bit = !row.bit;
+            }
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "MAC check failed on AND gate 7");
+    }
+
+    #[test]
+    fn corrupted_output_mask_aborts() {
+        let err = run_block(b"abc", b"xyz", |_, output_masks| {
+            output_masks[3].bit = !output_masks[3].bit;
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "MAC check failed on an output mask");
+    }
+}
@@ -0,0 +1,8 @@
+use sha256_gc::channel::NetChannel;
+use sha256_gc::cli;
+use std::process::ExitCode;
+
+// P1: holds x1, connects to the garbler, fetches its labels over OT and evaluates
+fn main() -> ExitCode {
+    cli::run_party(1, NetChannel::connect)
+}
@@ -0,0 +1,11 @@
+use sha256_gc::channel::NetChannel;
+use sha256_gc::cli;
+use std::process::ExitCode;
+
+// P0: holds x0, garbles the circuit and waits for the evaluator to connect
+fn main() -> ExitCode {
+    cli::run_party(0, |address| {
+        println!("Garbler: listening on {}", address);
+        NetChannel::listen(address)
+    })
+}
@@ -0,0 +1,371 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+//Attempts of `NetChannel::connect`, 200ms apart
+const CONNECT_RETRIES: usize = 50;
+//Frames a `MemoryChannel` queues before the sender blocks
+const MEMORY_CHANNEL_BOUND: usize = 16;
+
+/// A reliable, ordered, message-oriented link between the garbler and the evaluator.
+///
+/// Transports only move byte frames, typed messages are serialized with bincode on top.
+pub trait Channel {
+    fn send_bytes(&mut self, data: &[u8]) -> io::Result<()>;
+    fn recv_bytes(&mut self) -> io::Result<Vec<u8>>;
+
+    fn send<T: Serialize>(&mut self, msg: &T) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        let data =
+            bincode::serialize(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.send_bytes(&data)
+    }
+
+    fn recv<T: DeserializeOwned>(&mut self) -> io::Result<T>
+    where
+        Self: Sized,
+    {
+        let data = self.recv_bytes()?;
+        bincode::deserialize(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Both endpoints live in the same process, frames move through a pair of bounded mpsc queues.
+/// A sender that runs ahead of its peer blocks, as it would on a socket, so streamed garbled
+/// tables never pile up in memory.
+pub struct MemoryChannel {
+    tx: mpsc::SyncSender<Vec<u8>>,
+    rx: mpsc::Receiver<Vec<u8>>,
+}
+
+impl MemoryChannel {
+    pub fn pair() -> (Self, Self) {
+        let (tx0, rx1) = mpsc::sync_channel(MEMORY_CHANNEL_BOUND);
+        let (tx1, rx0) = mpsc::sync_channel(MEMORY_CHANNEL_BOUND);
+        (
+            MemoryChannel { tx: tx0, rx: rx0 },
+            MemoryChannel { tx: tx1, rx: rx1 },
+        )
+    }
+}
+
+impl Channel for MemoryChannel {
+    fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        self.tx
+            .send(data.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "peer endpoint dropped"))
+    }
+
+    fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+        self.rx
+            .recv()
+            .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "peer endpoint dropped"))
+    }
+}
+
+/// Length-prefixed frames over any byte stream (u64 little-endian length, then the payload).
+/// The payload buffer grows with the bytes that actually arrive, a forged length cannot make the
+/// receiver allocate more than the peer sends.
+pub struct StreamChannel<S: Read + Write> {
+    reader: BufReader<S>,
+    writer: BufWriter<S>,
+}
+
+impl<S: Read + Write> Channel for StreamChannel<S> {
+    fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(&(data.len() as u64).to_le_bytes())?;
+        self.writer.write_all(data)?;
+        self.writer.flush()
+    }
+
+    fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+        let mut len = [0u8; 8];
+        self.reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        let mut data: Vec<u8> = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut data)?;
+        if data.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Frame of {} bytes ended after {}", len, data.len()),
+            ));
+        }
+        Ok(data)
+    }
+}
+
+pub type TcpChannel = StreamChannel<TcpStream>;
+pub type UnixChannel = StreamChannel<UnixStream>;
+
+impl TcpChannel {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(StreamChannel {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+}
+
+impl UnixChannel {
+    pub fn new(stream: UnixStream) -> io::Result<Self> {
+        Ok(StreamChannel {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+}
+
+/// A channel to a remote endpoint: `unix:<path>` selects a Unix-domain socket, anything else
+/// is a TCP address such as `127.0.0.1:7878`.
+pub enum NetChannel {
+    Tcp(TcpChannel),
+    Unix(UnixChannel),
+}
+
+impl NetChannel {
+    /// Bind the address and wait for the peer to connect. A stale Unix socket left at the path is
+    /// replaced, any other file there is an error.
+    pub fn listen(addr: &str) -> io::Result<Self> {
+        match addr.strip_prefix("unix:") {
+            Some(path) => {
+                match fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AddrInUse,
+                            format!("{} exists and is not a socket", path),
+                        ))
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                let listener = UnixListener::bind(path)?;
+                let (stream, _) = listener.accept()?;
+                let _ = fs::remove_file(path);
+                Ok(NetChannel::Unix(UnixChannel::new(stream)?))
+            }
+            None => {
+                let listener = TcpListener::bind(addr)?;
+                let (stream, _) = listener.accept()?;
+                Ok(NetChannel::Tcp(TcpChannel::new(stream)?))
+            }
+        }
+    }
+
+    /// Connect to a listening peer, retrying for a while so both sides can be started in any order.
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        let mut attempt: usize = 0;
+        loop {
+            let connected = match addr.strip_prefix("unix:") {
+                Some(path) => UnixStream::connect(path)
+                    .and_then(UnixChannel::new)
+                    .map(NetChannel::Unix),
+                None => TcpStream::connect(addr)
+                    .and_then(TcpChannel::new)
+                    .map(NetChannel::Tcp),
+            };
+            match connected {
+                Err(_) if attempt < CONNECT_RETRIES => {
+                    attempt += 1;
+                    thread::sleep(Duration::from_millis(200));
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+impl Channel for NetChannel {
+    fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            NetChannel::Tcp(channel) => channel.send_bytes(data),
+            NetChannel::Unix(channel) => channel.send_bytes(data),
+        }
+    }
+
+    fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+        match self {
+            NetChannel::Tcp(channel) => channel.recv_bytes(),
+            NetChannel::Unix(channel) => channel.recv_bytes(),
+        }
+    }
+}
+
+/// Hashes every frame passing through it, so that a party can compare a stream of messages with
+/// an earlier commitment. A tap forwards the frames to an inner channel, a sink only hashes what
+/// is sent and cannot receive.
+pub struct TranscriptChannel<'a> {
+    inner: Option<&'a mut dyn Channel>,
+    hasher: Sha256,
+}
+
+impl<'a> TranscriptChannel<'a> {
+    pub fn sink() -> Self {
+        TranscriptChannel {
+            inner: None,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn tap(inner: &'a mut dyn Channel) -> Self {
+        TranscriptChannel {
+            inner: Some(inner),
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn digest(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+
+    fn absorb(&mut self, data: &[u8]) {
+        self.hasher.update((data.len() as u64).to_le_bytes());
+        self.hasher.update(data);
+    }
+}
+
+impl Channel for TranscriptChannel<'_> {
+    fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        self.absorb(data);
+        match &mut self.inner {
+            Some(inner) => inner.send_bytes(data),
+            None => Ok(()),
+        }
+    }
+
+    fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+        let data = match &mut self.inner {
+            Some(inner) => inner.recv_bytes()?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "a transcript sink cannot receive",
+                ))
+            }
+        };
+        self.absorb(&data);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    //One side sends a few frames, the other echoes each of them back
+    fn round_trip(mut local: impl Channel, remote: impl Channel + Send + 'static) {
+        let echo = thread::spawn(move || {
+            let mut remote = remote;
+            for _ in 0..3 {
+                let data = remote.recv_bytes().unwrap();
+                remote.send_bytes(&data).unwrap();
+            }
+        });
+
+        let message: (u32, Vec<u8>, String) = (7, vec![0xab; 3], "label".to_string());
+        local.send(&message).unwrap();
+        assert_eq!(local.recv::<(u32, Vec<u8>, String)>().unwrap(), message);
+        local.send_bytes(&[]).unwrap();
+        assert!(local.recv_bytes().unwrap().is_empty());
+        let large: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        local.send_bytes(&large).unwrap();
+        assert_eq!(local.recv_bytes().unwrap(), large);
+
+        echo.join().unwrap();
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        let (local, remote) = MemoryChannel::pair();
+        round_trip(local, remote);
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let connecting = thread::spawn(move || NetChannel::connect(&addr).unwrap());
+        let (stream, _) = listener.accept().unwrap();
+        round_trip(TcpChannel::new(stream).unwrap(), connecting.join().unwrap());
+    }
+
+    #[test]
+    fn unix_round_trip() {
+        let dir = std::env::temp_dir().join(format!("sha256_gc-channel-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let addr = format!("unix:{}", dir.join("peer.sock").display());
+        let listen_addr = addr.clone();
+        let listening = thread::spawn(move || NetChannel::listen(&listen_addr).unwrap());
+        let local = NetChannel::connect(&addr).unwrap();
+        round_trip(local, listening.join().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn listen_keeps_a_file_that_is_not_a_socket() {
+        let dir = std::env::temp_dir().join(format!("sha256_gc-listen-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.txt");
+        fs::write(&path, b"keep me").unwrap();
+        let err = match NetChannel::listen(&format!("unix:{}", path.display())) {
+            Err(err) => err,
+            Ok(_) => panic!("listening on a regular file"),
+        };
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(fs::read(&path).unwrap(), b"keep me");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forged_frame_length_is_an_error() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let mut local = UnixChannel::new(local).unwrap();
+        remote.write_all(&u64::MAX.to_le_bytes()).unwrap();
+        remote.write_all(b"short").unwrap();
+        drop(remote);
+        let err = local.recv_bytes().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            err.to_string(),
+            format!("Frame of {} bytes ended after 5", u64::MAX)
+        );
+    }
+
+    #[test]
+    fn memory_endpoint_reports_a_dropped_peer() {
+        let (mut local, remote) = MemoryChannel::pair();
+        drop(remote);
+        assert_eq!(
+            local.recv_bytes().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn memory_sender_blocks_on_a_full_queue() {
+        let (mut local, mut remote) = MemoryChannel::pair();
+        let sender = thread::spawn(move || {
+            for i in 0..MEMORY_CHANNEL_BOUND + 1 {
+                local.send_bytes(&[i as u8]).unwrap();
+            }
+        });
+        thread::sleep(Duration::from_millis(100));
+        assert!(!sender.is_finished());
+        for i in 0..MEMORY_CHANNEL_BOUND + 1 {
+            assert_eq!(remote.recv_bytes().unwrap(), vec![i as u8]);
+        }
+        sender.join().unwrap();
+    }
+}
@@ -1,7 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::Range;
 
+use crate::sha512;
 use crate::utils::convert_bytes2_bits;
 
 pub const INITIAL_HASH_VALUES: [u8; 32] = [
@@ -15,11 +19,135 @@ pub const INITIAL_HASH_VALUES: [u8; 32] = [
     0x5b, 0xe0, 0xcd, 0x19, // H[7]
 ];
 
+pub const SHA224_INITIAL_HASH_VALUES: [u8; 32] = [
+    0xc1, 0x05, 0x9e, 0xd8, // H[0]
+    0x36, 0x7c, 0xd5, 0x07, // H[1]
+    0x30, 0x70, 0xdd, 0x17, // H[2]
+    0xf7, 0x0e, 0x59, 0x39, // H[3]
+    0xff, 0xc0, 0x0b, 0x31, // H[4]
+    0x68, 0x58, 0x15, 0x11, // H[5]
+    0x64, 0xf9, 0x8f, 0xa7, // H[6]
+    0xbe, 0xfa, 0x4f, 0xa4, // H[7]
+];
+
+pub const SHA512_INITIAL_HASH_VALUES: [u8; 64] = [
+    0x6a, 0x09, 0xe6, 0x67, 0xf3, 0xbc, 0xc9, 0x08, // H[0]
+    0xbb, 0x67, 0xae, 0x85, 0x84, 0xca, 0xa7, 0x3b, // H[1]
+    0x3c, 0x6e, 0xf3, 0x72, 0xfe, 0x94, 0xf8, 0x2b, // H[2]
+    0xa5, 0x4f, 0xf5, 0x3a, 0x5f, 0x1d, 0x36, 0xf1, // H[3]
+    0x51, 0x0e, 0x52, 0x7f, 0xad, 0xe6, 0x82, 0xd1, // H[4]
+    0x9b, 0x05, 0x68, 0x8c, 0x2b, 0x3e, 0x6c, 0x1f, // H[5]
+    0x1f, 0x83, 0xd9, 0xab, 0xfb, 0x41, 0xbd, 0x6b, // H[6]
+    0x5b, 0xe0, 0xcd, 0x19, 0x13, 0x7e, 0x21, 0x79, // H[7]
+];
+
+pub const SHA384_INITIAL_HASH_VALUES: [u8; 64] = [
+    0xcb, 0xbb, 0x9d, 0x5d, 0xc1, 0x05, 0x9e, 0xd8, // H[0]
+    0x62, 0x9a, 0x29, 0x2a, 0x36, 0x7c, 0xd5, 0x07, // H[1]
+    0x91, 0x59, 0x01, 0x5a, 0x30, 0x70, 0xdd, 0x17, // H[2]
+    0x15, 0x2f, 0xec, 0xd8, 0xf7, 0x0e, 0x59, 0x39, // H[3]
+    0x67, 0x33, 0x26, 0x67, 0xff, 0xc0, 0x0b, 0x31, // H[4]
+    0x8e, 0xb4, 0x4a, 0x87, 0x68, 0x58, 0x15, 0x11, // H[5]
+    0xdb, 0x0c, 0x2e, 0x0d, 0x64, 0xf9, 0x8f, 0xa7, // H[6]
+    0x47, 0xb5, 0x48, 0x1d, 0xbe, 0xfa, 0x4f, 0xa4, // H[7]
+];
+
+//SHA-256 (and SHA-224) block, chaining value and length field
 pub const SINGLE_BLOCK_BITS_LEN: usize = 512;
 pub const STATE_INFO_BITS_LEN: usize = 256;
 pub const OUTPUT_BITS_LEN: usize = 256;
+pub const LENGTH_FIELD_BITS_LEN: usize = 64;
 pub const AND_GATES_CNT: usize = 22573;
 
+//SHA-512 (and SHA-384) block, chaining value and length field
+pub const SHA512_BLOCK_BITS_LEN: usize = 1024;
+pub const SHA512_STATE_BITS_LEN: usize = 512;
+pub const SHA512_LENGTH_FIELD_BITS_LEN: usize = 128;
+
+//Which hash the circuit computes, both parties must agree on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashFunction {
+    Sha256,
+    Sha224, //SHA-256 from other initial hash values, the digest is cut to 224 bits
+    Sha512, //64-bit words, 1024-bit blocks
+    Sha384, //SHA-512 from other initial hash values, the digest is cut to 384 bits
+}
+
+impl HashFunction {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "sha256" => Ok(HashFunction::Sha256),
+            "sha224" => Ok(HashFunction::Sha224),
+            "sha512" => Ok(HashFunction::Sha512),
+            "sha384" => Ok(HashFunction::Sha384),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown hash function {}, use sha256, sha224, sha512 or sha384",
+                    other
+                ),
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashFunction::Sha256 => "sha256",
+            HashFunction::Sha224 => "sha224",
+            HashFunction::Sha512 => "sha512",
+            HashFunction::Sha384 => "sha384",
+        }
+    }
+
+    //true for SHA-512 and SHA-384, which share the SHA-512 compression function
+    pub fn is_sha512_family(&self) -> bool {
+        matches!(self, HashFunction::Sha512 | HashFunction::Sha384)
+    }
+
+    pub fn initial_hash_values(&self) -> &'static [u8] {
+        match self {
+            HashFunction::Sha256 => &INITIAL_HASH_VALUES,
+            HashFunction::Sha224 => &SHA224_INITIAL_HASH_VALUES,
+            HashFunction::Sha512 => &SHA512_INITIAL_HASH_VALUES,
+            HashFunction::Sha384 => &SHA384_INITIAL_HASH_VALUES,
+        }
+    }
+
+    pub fn block_bits_len(&self) -> usize {
+        if self.is_sha512_family() {
+            SHA512_BLOCK_BITS_LEN
+        } else {
+            SINGLE_BLOCK_BITS_LEN
+        }
+    }
+
+    pub fn state_bits_len(&self) -> usize {
+        if self.is_sha512_family() {
+            SHA512_STATE_BITS_LEN
+        } else {
+            STATE_INFO_BITS_LEN
+        }
+    }
+
+    //the message length in bits closing the padding
+    pub fn length_field_bits_len(&self) -> usize {
+        if self.is_sha512_family() {
+            SHA512_LENGTH_FIELD_BITS_LEN
+        } else {
+            LENGTH_FIELD_BITS_LEN
+        }
+    }
+
+    pub fn digest_bits_len(&self) -> usize {
+        match self {
+            HashFunction::Sha256 => OUTPUT_BITS_LEN,
+            HashFunction::Sha224 => 224,
+            HashFunction::Sha512 => SHA512_STATE_BITS_LEN,
+            HashFunction::Sha384 => 384,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct XorAndGate {
     pub input0: usize,
@@ -39,14 +167,9 @@ pub struct OutputWire {
     pub should_trace: bool,
 }
 
-// #[derive(Debug)]
-// pub struct PlainEvalWire {
-//     pub val: bool,
-//     pub flipped: bool,
-// }
-
 #[derive(Debug)]
 pub struct Sha256Circuit {
+    pub hash_function: HashFunction,
     pub initial_hash_vec: Vec<bool>,
     pub extra_input_wire: usize,
     pub gates: Vec<XorAndGate>,
@@ -63,7 +186,24 @@ pub struct Sha256Circuit {
 impl Sha256Circuit {
     pub fn new() -> io::Result<Self> {
         let file = File::open("data/sha256-bristol-basic.txt")?;
-        let reader = io::BufReader::new(file);
+        Self::from_bristol(io::BufReader::new(file), HashFunction::Sha256)
+    }
+
+    //the SHA-256 circuit, or the generated SHA-512 one
+    pub fn with_hash_function(hash_function: HashFunction) -> io::Result<Self> {
+        if hash_function.is_sha512_family() {
+            let bristol = sha512::bristol_circuit();
+            Self::from_bristol(io::Cursor::new(bristol), hash_function)
+        } else {
+            let mut circuit = Self::new()?;
+            circuit.set_hash_function(hash_function);
+            Ok(circuit)
+        }
+    }
+
+    //Parse a single block circuit in Bristol format: the message block then the chaining value as
+    //inputs, the updated chaining value on the last wires
+    fn from_bristol(reader: impl BufRead, hash_function: HashFunction) -> io::Result<Self> {
         //Initialize the vector to hold all (XorAndGate gates) (including NOT gate)
         let mut xor_and_gates: Vec<XorAndGate> = Vec::new();
         //Stores all (not gates) but in reverse order {w_out,w_in}
@@ -74,6 +214,7 @@ impl Sha256Circuit {
         let mut and_cnt: usize = 0;
         let mut inv_cnt: usize = 0;
         let mut extra_input_wire: usize = 0;
+        let mut output_bits_len: usize = 0;
 
         let mut line_number: usize = 0;
         for line_result in reader.lines() {
@@ -82,6 +223,8 @@ impl Sha256Circuit {
             let parts: Vec<&str> = line.split_whitespace().collect();
             if line_number == 1 {
                 extra_input_wire = parts[1].parse().unwrap();
+            } else if line_number == 3 {
+                output_bits_len = parts[1].parse().unwrap();
             } else if line_number >= 5 {
                 if parts.len() == 6 {
                     //XorAndGate type
@@ -110,9 +253,9 @@ impl Sha256Circuit {
         }
 
         let mut fina_output_wires: Vec<OutputWire> = Vec::new();
-        for i in 0..256 {
+        for i in 0..output_bits_len {
             fina_output_wires.push(OutputWire {
-                id: extra_input_wire - OUTPUT_BITS_LEN + i,
+                id: extra_input_wire - output_bits_len + i,
                 input_id: 0,
                 should_trace: false,
             });
@@ -120,9 +263,10 @@ impl Sha256Circuit {
 
         Self::mark_double_gates(&inv_gates_map, &mut xor_and_gates, &mut fina_output_wires);
 
-        let mut reversed_hash_vec = convert_bytes2_bits(INITIAL_HASH_VALUES.as_ref());
+        let mut reversed_hash_vec = convert_bytes2_bits(hash_function.initial_hash_values());
         reversed_hash_vec.reverse();
         Ok(Sha256Circuit {
+            hash_function,
             initial_hash_vec: reversed_hash_vec,
             extra_input_wire,
             gates: xor_and_gates,
@@ -137,13 +281,42 @@ impl Sha256Circuit {
 
     pub fn get_initial_hash(&self, idx: usize) -> bool {
         assert!(
-            (0..=STATE_INFO_BITS_LEN - 1).contains(&idx),
-            "Input index is not in range [0,256)"
+            (0..self.state_bits_len()).contains(&idx),
+            "Input index is not in range [0,{})",
+            self.state_bits_len()
         );
 
         self.initial_hash_vec[idx]
     }
 
+    pub fn block_bits_len(&self) -> usize {
+        self.hash_function.block_bits_len()
+    }
+
+    pub fn state_bits_len(&self) -> usize {
+        self.hash_function.state_bits_len()
+    }
+
+    //switch to the initial hash values of another hash sharing the compression function
+    pub fn set_hash_function(&mut self, hash_function: HashFunction) {
+        assert_eq!(
+            hash_function.is_sha512_family(),
+            self.hash_function.is_sha512_family(),
+            "{:?} needs another compression function",
+            hash_function
+        );
+        self.hash_function = hash_function;
+        self.initial_hash_vec = convert_bytes2_bits(hash_function.initial_hash_values());
+        self.initial_hash_vec.reverse();
+    }
+
+    //Indices of the output wires decoded after the last block. The bits are reversed, so a
+    //truncated digest (SHA-224, SHA-384) drops the first ones: the chaining value is still fully
+    //computed
+    pub fn digest_outputs(&self) -> Range<usize> {
+        self.state_bits_len() - self.hash_function.digest_bits_len()..self.state_bits_len()
+    }
+
     pub fn update_extra_circuit(&mut self, extra_gates: &[XorAndGate]) {
         self.extra_gates = Vec::new();
         if !extra_gates.is_empty() {
@@ -152,6 +325,11 @@ impl Sha256Circuit {
         self.extra_gates.extend(self.gates.iter().cloned());
     }
 
+    //gates running after the single block circuit, on its output wires
+    pub fn append_extra_circuit(&mut self, extra_gates: &[XorAndGate]) {
+        self.extra_gates.extend(extra_gates.iter().cloned());
+    }
+
     //from not Gates mapping, add not marks onto associated double gates
     //process every input wire, if it's the output of an And gate, mark flip bit as true
     fn mark_double_gates(
@@ -183,29 +361,31 @@ impl Sha256Circuit {
         //Why need to process output_ids? they may come after a not gate, these wires are never processed (as last layers, never as input to gate) move the output ids directly to xor_and_gates, this is to remove last layer outputs
         //this specially marked output wire that is deriving from a not gate, because they are currently ignored by gate evaluation
         for output_wire in output_wire_ids {
-            match inv_gates.get(&output_wire.id) {
-                //If output id is the output wire of a not gate
-                Some(&input_id) => {
-                    //find the specified gate in self.gates and change the internal data
-                    for gate in xor_and_gates.iter_mut().rev() {
-                        //Reverse iterate using into_iter() and rev()
-                        if input_id == gate.output {
-                            output_wire.input_id = input_id;
-                            output_wire.should_trace = true; //should flip this output wire when reconstructing
-                                                             // println!("output_wire changed; {} --> {}",output_wire.id, gate.output);
-                            break;
-                        }
+            //If output id is the output wire of a not gate
+            if let Some(&input_id) = inv_gates.get(&output_wire.id) {
+                //find the specified gate in self.gates and change the internal data
+                for gate in xor_and_gates.iter_mut().rev() {
+                    //Reverse iterate using into_iter() and rev()
+                    if input_id == gate.output {
+                        output_wire.input_id = input_id;
+                        output_wire.should_trace = true; //should flip this output wire when reconstructing
+                        break;
                     }
                 }
-                None => {}
             }
         }
     }
+}
 
-    pub fn display(&self) {
-        println!("The single block sha256 Boolean circuit has:\n");
-        println!(" {} XOR gates", self.xor_cnt);
-        println!(" {} AND gates", self.and_cnt);
-        println!(" {} INV gates", self.inv_cnt);
+impl fmt::Display for Sha256Circuit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "The single block {} Boolean circuit has:",
+            self.hash_function.name()
+        )?;
+        writeln!(f, " {} XOR gates", self.xor_cnt)?;
+        writeln!(f, " {} AND gates", self.and_cnt)?;
+        write!(f, " {} INV gates", self.inv_cnt)
     }
 }
@@ -0,0 +1,357 @@
+use crate::channel::Channel;
+use crate::channel::NetChannel;
+use crate::circuit::HashFunction;
+use crate::cut_and_choose::CUT_AND_CHOOSE_COPIES;
+use crate::gc::GarblingMode;
+use crate::party::InputMode;
+use crate::party::OtProvider;
+use crate::party::OutputPolicy;
+use crate::party::Party;
+use crate::party::SecurityMode;
+
+use std::env;
+use std::io;
+use std::process::ExitCode;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+//Name of each role in messages, and of its input in the usage
+const ROLES: [(&str, &str); 2] = [("Garbler", "x0"), ("Evaluator", "x1")];
+
+/// Command line shared by the garbler and the evaluator: the hex-encoded input of the party
+/// followed by named flags, each written `--flag value` or `--flag=value`.
+#[derive(Debug)]
+pub struct Options {
+    pub input: Vec<u8>,
+    pub address: String,
+    pub ot_provider: OtProvider,
+    pub garbling_mode: GarblingMode,
+    pub security_mode: SecurityMode,
+    pub output_policy: OutputPolicy,
+    pub input_mode: InputMode,
+    pub public_prefix: Vec<u8>,
+    pub length_share: Option<usize>,
+    pub message_bits_cnt: Option<usize>,
+    pub hash_function: HashFunction,
+}
+
+pub fn usage(program: &str, input_name: &str) -> String {
+    format!(
+        "Usage: {} <{} in hex> [options]\n\
+         \x20 --address <addr>          TCP address or unix:<path>, default {}\n\
+         \x20 --ot <provider>           iknp (default) or silent\n\
+         \x20 --garbling <mode>         halfgates (default), threehalves, pointpermute or grr3\n\
+         \x20 --security <mode>         semihonest (default), dualex, cutandchoose or authenticated\n\
+         \x20                           (authenticated needs --features insecure, P1 gets no privacy)\n\
+         \x20 --output <policy>         both (default), evaluator, garbler or shared\n\
+         \x20 --input <mode>            xor (default), concat:<peer length in bytes>, arith8 or arith32\n\
+         \x20 --prefix <hex>            public prefix hashed before the message\n\
+         \x20 --length-share <n>        this party's XOR share of the hidden message length\n\
+         \x20 --bits <n>                message length in bits\n\
+         \x20 --hash <function>         sha256 (default), sha224, sha512 or sha384",
+        program, input_name, DEFAULT_ADDRESS
+    )
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn parse_number(flag: &str, value: &str) -> io::Result<usize> {
+    value
+        .parse()
+        .map_err(|e| invalid(format!("--{} is not a valid number: {}", flag, e)))
+}
+
+fn parse_hex(what: &str, value: &str) -> io::Result<Vec<u8>> {
+    hex::decode(value).map_err(|e| invalid(format!("{} is not a valid hex string: {}", what, e)))
+}
+
+impl Options {
+    /// Parse the arguments that follow the program name.
+    pub fn parse(args: &[String]) -> io::Result<Self> {
+        let mut options = Options {
+            input: Vec::new(),
+            address: DEFAULT_ADDRESS.to_string(),
+            ot_provider: OtProvider::Iknp,
+            garbling_mode: GarblingMode::HalfGates,
+            security_mode: SecurityMode::SemiHonest,
+            output_policy: OutputPolicy::Both,
+            input_mode: InputMode::Xor,
+            public_prefix: Vec::new(),
+            length_share: None,
+            message_bits_cnt: None,
+            hash_function: HashFunction::Sha256,
+        };
+        let mut input: Option<Vec<u8>> = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                if input.is_some() {
+                    return Err(invalid(format!("Unexpected argument {}", arg)));
+                }
+                input = Some(parse_hex("The input", arg)?);
+                continue;
+            };
+            let (flag, value) = match flag.split_once('=') {
+                Some((flag, value)) => (flag, value.to_string()),
+                None => match args.next() {
+                    Some(value) => (flag, value.clone()),
+                    None => return Err(invalid(format!("--{} needs a value", flag))),
+                },
+            };
+            match flag {
+                "address" => options.address = value,
+                "ot" => options.ot_provider = OtProvider::parse(&value)?,
+                "garbling" => options.garbling_mode = GarblingMode::parse(&value)?,
+                "security" => options.security_mode = SecurityMode::parse(&value)?,
+                "output" => options.output_policy = OutputPolicy::parse(&value)?,
+                "input" => options.input_mode = InputMode::parse(&value)?,
+                "prefix" => options.public_prefix = parse_hex("The public prefix", &value)?,
+                "length-share" => options.length_share = Some(parse_number(flag, &value)?),
+                "bits" => options.message_bits_cnt = Some(parse_number(flag, &value)?),
+                "hash" => options.hash_function = HashFunction::parse(&value)?,
+                other => return Err(invalid(format!("Unknown option --{}", other))),
+            }
+        }
+
+        options.input =
+            input.ok_or_else(|| invalid("Missing the hex-encoded input".to_string()))?;
+        Ok(options)
+    }
+
+    /// Create the party of the given role and apply every option to it.
+    pub fn build_party(&self, role: usize) -> io::Result<Party> {
+        let mut party = Party::new(role, &self.input)?;
+        party.set_ot_provider(self.ot_provider);
+        party.set_garbling_mode(self.garbling_mode);
+        party.set_output_policy(self.output_policy);
+        party.set_input_mode(self.input_mode);
+        party.set_hash_function(self.hash_function)?;
+        if let Some(bits_cnt) = self.message_bits_cnt {
+            party.set_message_bits_cnt(bits_cnt)?;
+        }
+        party.set_public_prefix(&self.public_prefix);
+        if let Some(share) = self.length_share {
+            party.set_length_share(share);
+        }
+        Ok(party)
+    }
+
+    /// Run the protocol of the selected security mode as the garbler (role 0) or the evaluator
+    /// (role 1). No digest is returned when the output policy reveals it to the peer only.
+    pub fn run_protocol(
+        &self,
+        party: &mut Party,
+        role: usize,
+        channel: &mut impl Channel,
+    ) -> io::Result<Option<Vec<u8>>> {
+        match (self.security_mode, role) {
+            (SecurityMode::SemiHonest, 0) => party.run_garbler(channel),
+            (SecurityMode::SemiHonest, _) => party.run_evaluator(channel),
+            (SecurityMode::DualExecution, _) => party.run_dual_execution(channel).map(Some),
+            (SecurityMode::CutAndChoose, 0) => party
+                .run_cut_and_choose_garbler(CUT_AND_CHOOSE_COPIES, channel)
+                .map(Some),
+            (SecurityMode::CutAndChoose, _) => party
+                .run_cut_and_choose_evaluator(CUT_AND_CHOOSE_COPIES, channel)
+                .map(Some),
+            #[cfg(feature = "insecure")]
+            (SecurityMode::Authenticated, 0) => party.run_auth_garbler(channel).map(Some),
+            #[cfg(feature = "insecure")]
+            (SecurityMode::Authenticated, _) => party.run_auth_evaluator(channel).map(Some),
+            #[cfg(not(feature = "insecure"))]
+            (SecurityMode::Authenticated, _) => Err(invalid(
+                "The authenticated mode is only built with the insecure feature".to_string(),
+            )),
+        }
+    }
+
+    /// What a party prints once the protocol succeeded, `name` is its role.
+    pub fn output_line(&self, name: &str, digest: Option<&[u8]>) -> String {
+        let hash_name = self.hash_function.name();
+        match digest {
+            Some(digest)
+                if self.security_mode == SecurityMode::SemiHonest
+                    && self.output_policy == OutputPolicy::Shared =>
+            {
+                format!("{}: {} share = {}", name, hash_name, hex::encode(digest))
+            }
+            Some(digest) => format!("{}: {} = {}", name, hash_name, hex::encode(digest)),
+            None => format!(
+                "{}: done, the digest was revealed to the other party only",
+                name
+            ),
+        }
+    }
+}
+
+/// Body of the garbler (role 0) and evaluator (role 1) binaries, which only differ in how they
+/// open the channel to the peer: `open_channel` gets the address to listen on or connect to.
+pub fn run_party(
+    role: usize,
+    open_channel: impl FnOnce(&str) -> io::Result<NetChannel>,
+) -> ExitCode {
+    let (name, input_name) = ROLES[role];
+    let program = name.to_lowercase();
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args[1] == "--help" {
+        eprintln!("{}", usage(&program, input_name));
+        return ExitCode::FAILURE;
+    }
+    let options = match Options::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", usage(&program, input_name));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut party = match options.build_party(role) {
+        Ok(party) => party,
+        Err(e) => {
+            eprintln!("Failed to create circuit: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let output = open_channel(&options.address)
+        .and_then(|mut channel| options.run_protocol(&mut party, role, &mut channel));
+    match output {
+        Ok(digest) => {
+            println!("{}", options.output_line(name, digest.as_deref()));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{} failed: {}", name, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::MemoryChannel;
+    use sha2::{Digest, Sha512};
+    use std::thread;
+
+    fn parse(args: &[&str]) -> io::Result<Options> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse(&["00ff"]).unwrap();
+        assert_eq!(options.input, vec![0x00, 0xff]);
+        assert_eq!(options.address, DEFAULT_ADDRESS);
+        assert_eq!(options.ot_provider, OtProvider::Iknp);
+        assert_eq!(options.garbling_mode, GarblingMode::HalfGates);
+        assert_eq!(options.security_mode, SecurityMode::SemiHonest);
+        assert_eq!(options.output_policy, OutputPolicy::Both);
+        assert_eq!(options.input_mode, InputMode::Xor);
+        assert!(options.public_prefix.is_empty());
+        assert_eq!(options.length_share, None);
+        assert_eq!(options.message_bits_cnt, None);
+        assert_eq!(options.hash_function, HashFunction::Sha256);
+    }
+
+    #[test]
+    fn named_flags_in_any_order() {
+        let options = parse(&[
+            "--hash",
+            "sha384",
+            "--input=concat:3",
+            "abcd",
+            "--address",
+            "unix:/tmp/gc.sock",
+            "--ot=silent",
+            "--garbling",
+            "grr3",
+            "--security",
+            "dualex",
+            "--output",
+            "shared",
+            "--prefix",
+            "6869",
+            "--length-share",
+            "5",
+            "--bits=12",
+        ])
+        .unwrap();
+        assert_eq!(options.input, vec![0xab, 0xcd]);
+        assert_eq!(options.address, "unix:/tmp/gc.sock");
+        assert_eq!(options.ot_provider, OtProvider::Silent);
+        assert_eq!(options.garbling_mode, GarblingMode::Grr3);
+        assert_eq!(options.security_mode, SecurityMode::DualExecution);
+        assert_eq!(options.output_policy, OutputPolicy::Shared);
+        assert_eq!(options.input_mode, InputMode::Concat { peer_len: 3 });
+        assert_eq!(options.public_prefix, b"hi".to_vec());
+        assert_eq!(options.length_share, Some(5));
+        assert_eq!(options.message_bits_cnt, Some(12));
+        assert_eq!(options.hash_function, HashFunction::Sha384);
+    }
+
+    #[test]
+    fn empty_input_is_allowed() {
+        assert!(parse(&["", "--hash", "sha512"]).unwrap().input.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_command_lines() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["zz"]).is_err());
+        assert!(parse(&["00", "11"]).is_err());
+        assert!(parse(&["00", "--unknown", "1"]).is_err());
+        assert!(parse(&["00", "--bits"]).is_err());
+        assert!(parse(&["00", "--bits", "twelve"]).is_err());
+        assert!(parse(&["00", "--ot", "base"]).is_err());
+    }
+
+    #[test]
+    fn both_roles_run_the_selected_protocol() {
+        let options = parse(&["616263", "--security", "dualex", "--hash", "sha512"]).unwrap();
+        let (mut c0, mut c1) = MemoryChannel::pair();
+        let evaluator = {
+            let options = parse(&["000000", "--security", "dualex", "--hash", "sha512"]).unwrap();
+            thread::spawn(move || {
+                let mut p1 = options.build_party(1).unwrap();
+                options.run_protocol(&mut p1, 1, &mut c1).unwrap()
+            })
+        };
+        let mut p0 = options.build_party(0).unwrap();
+        let digest = options.run_protocol(&mut p0, 0, &mut c0).unwrap();
+        assert_eq!(evaluator.join().unwrap(), digest);
+        assert_eq!(
+            options.output_line("Garbler", digest.as_deref()),
+            format!("Garbler: sha512 = {}", hex::encode(Sha512::digest(b"abc")))
+        );
+    }
+
+    #[test]
+    fn output_lines() {
+        let shared = parse(&["00", "--output", "shared"]).unwrap();
+        assert_eq!(
+            shared.output_line("Evaluator", Some(&[0xab])),
+            "Evaluator: sha256 share = ab"
+        );
+        assert_eq!(
+            shared.output_line("Garbler", None),
+            "Garbler: done, the digest was revealed to the other party only"
+        );
+    }
+
+    #[test]
+    fn more_message_bits_than_the_input_is_an_error() {
+        let options = parse(&["00ff", "--bits", "17"]).unwrap();
+        let err = options.build_party(0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(parse(&["00ff", "--bits", "16"])
+            .unwrap()
+            .build_party(0)
+            .is_ok());
+    }
+}
@@ -0,0 +1,401 @@
+use crate::gc::GarbledCircuit;
+use crate::gc::WireLabel;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// Garbled copies of the circuit produced by default. With 3/5 of them checked, this is the
+/// parameter of [sS11](https://eprint.iacr.org/2011/272) for a cheating probability of 2^-40:
+/// the garbler must corrupt more than half of the 50 evaluated copies and none of the 75 checked
+/// ones to choose the output. Fewer copies weaken the bound quickly, with 10 copies (4 evaluated)
+/// a garbler corrupting 3 of them wins with probability C(4,3)/C(10,3) = 1/30.
+pub const CUT_AND_CHOOSE_COPIES: usize = 125;
+/// Output bits of the input-consistency hash, a garbler using different x0 in two evaluated
+/// copies passes the check with probability 2^-CONSISTENCY_HASH_BITS
+pub const CONSISTENCY_HASH_BITS: usize = 40;
+/// Random bits appended to x0 so that the consistency hash reveals nothing about x0
+pub const CONSISTENCY_PAD_BITS: usize = 2 * CONSISTENCY_HASH_BITS;
+/// P1 feeds every input bit as this many random bits XORing to it, the probe-resistant encoding
+/// of [LP07](https://eprint.iacr.org/2008/049). A garbler corrupting OT keys to probe P1's input
+/// sees an abort that depends on it only if it corrupts one key of every share of a bit, and
+/// then the abort tells the bit apart with probability 2^-(P1_INPUT_SHARES - 1).
+pub const P1_INPUT_SHARES: usize = 40;
+
+//First garbler message, one per copy, sent before the evaluator chooses anything
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CopyCommitment {
+    pub seed: [u8; 32],      //H(seed), the seed determines the whole copy
+    pub p0_inputs: [u8; 32], //H(labels of x0 || r), binds the garbler's input before the hash is chosen
+    pub p1_inputs: [u8; 32], //H(encrypted label pairs of P1)
+    pub tables: [u8; 32],    //transcript hash of the garbled blocks and output permutation bits
+}
+
+//A checked copy is opened entirely, the evaluator regarbles it from the seed
+#[derive(Serialize, Deserialize)]
+pub struct CheckedCopy {
+    pub seed: [u8; 32],
+    pub p1_inputs: Vec<[WireLabel; 2]>,
+}
+
+//An evaluated copy reveals the garbler's input labels and is then streamed block by block
+#[derive(Serialize, Deserialize)]
+pub struct EvaluatedCopy {
+    pub p0_inputs: Vec<WireLabel>,
+    pub p1_inputs: Vec<[WireLabel; 2]>,
+}
+
+pub fn hash_seed(seed: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(seed).into()
+}
+
+pub fn hash_labels<'a>(labels: impl IntoIterator<Item = &'a WireLabel>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for label in labels {
+        hasher.update(label.to_generic_array());
+    }
+    hasher.finalize().into()
+}
+
+/// The random binary matrix M of the consistency hash M·(x0 || r), one row per output bit.
+/// Being linear, it is computed on labels with free XOR only.
+pub fn consistency_matrix(seed: [u8; 32], columns: usize) -> Vec<Vec<bool>> {
+    let mut rng = StdRng::from_seed(seed);
+    (0..CONSISTENCY_HASH_BITS)
+        .map(|_| (0..columns).map(|_| rng.gen()).collect())
+        .collect()
+}
+
+/// XOR of the labels selected by each row of M: the garbler applies it to its zero labels to get
+/// the decoding bits, the evaluator to the labels it holds.
+pub fn apply_matrix(matrix: &[Vec<bool>], labels: &[WireLabel]) -> Vec<WireLabel> {
+    matrix
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(labels)
+                .filter(|(&selected, _)| selected)
+                .fold(WireLabel::zero(), |acc, (_, &label)| acc ^ label)
+        })
+        .collect()
+}
+
+/// Split each bit of P1 into [`P1_INPUT_SHARES`] random bits that XOR to it, the choice bits of
+/// the OT.
+pub fn encode_p1_bits(bits: &[bool], rng: &mut impl Rng) -> Vec<bool> {
+    let mut shares: Vec<bool> = Vec::with_capacity(bits.len() * P1_INPUT_SHARES);
+    for &bit in bits {
+        let mut last = bit;
+        for _ in 1..P1_INPUT_SHARES {
+            let share: bool = rng.gen();
+            last ^= share;
+            shares.push(share);
+        }
+        shares.push(last);
+    }
+    shares
+}
+
+/// Zero labels of the shares of P1's bits: all random but the last, which makes them XOR to the
+/// zero label of the bit. The label of a share bit b is its zero label XOR b·R as usual.
+pub fn encode_p1_zero_labels(rng: &mut impl Rng, zero_labels: &[WireLabel]) -> Vec<WireLabel> {
+    let mut shares: Vec<WireLabel> = Vec::with_capacity(zero_labels.len() * P1_INPUT_SHARES);
+    for &zero in zero_labels {
+        let mut last = zero;
+        for _ in 1..P1_INPUT_SHARES {
+            let share = WireLabel::from_data(rng.gen());
+            last ^= share;
+            shares.push(share);
+        }
+        shares.push(last);
+    }
+    shares
+}
+
+/// The labels of P1's bits, XOR of the labels of their shares (free XOR).
+pub fn decode_p1_labels(share_labels: &[WireLabel]) -> Vec<WireLabel> {
+    share_labels
+        .chunks(P1_INPUT_SHARES)
+        .map(|shares| {
+            shares
+                .iter()
+                .fold(WireLabel::zero(), |acc, &label| acc ^ label)
+        })
+        .collect()
+}
+
+/// P1's labels are transferred once for all copies: the OT carries a key pair per share of an
+/// input bit of P1, and every copy encrypts the label pairs of its shares under these keys.
+pub fn encrypt_p1_labels(
+    garbler: &GarbledCircuit,
+    keys: &[(WireLabel, WireLabel)],
+    zero_labels: &[WireLabel],
+    copy: usize,
+) -> Vec<[WireLabel; 2]> {
+    keys.iter()
+        .zip(zero_labels)
+        .enumerate()
+        .map(|(j, (key, &zero))| {
+            let tweak = p1_tweak(copy, j);
+            [
+                zero ^ garbler.tccr_hash(&key.0, tweak),
+                zero ^ garbler.global_r ^ garbler.tccr_hash(&key.1, tweak),
+            ]
+        })
+        .collect()
+}
+
+/// Decrypt the share labels matching P1's encoded bits with the keys received through OT.
+pub fn decrypt_p1_labels(
+    hasher: &GarbledCircuit,
+    keys: &[WireLabel],
+    bits: &[bool],
+    encrypted: &[[WireLabel; 2]],
+    copy: usize,
+) -> Vec<WireLabel> {
+    keys.iter()
+        .zip(bits)
+        .zip(encrypted)
+        .enumerate()
+        .map(|(j, ((key, &bit), pair))| {
+            pair[bit as usize] ^ hasher.tccr_hash(key, p1_tweak(copy, j))
+        })
+        .collect()
+}
+
+fn p1_tweak(copy: usize, j: usize) -> u64 {
+    ((copy as u64) << 32) | j as u64
+}
+
+/// Pick the copies to check, 3/5 of them as suggested by [sS11](https://eprint.iacr.org/2011/272).
+pub fn choose_checked_copies(copies: usize) -> Vec<bool> {
+    let mut rng = rand::thread_rng();
+    let mut checked = vec![false; copies];
+    for i in rand::seq::index::sample(&mut rng, copies, copies * 3 / 5) {
+        checked[i] = true;
+    }
+    checked
+}
+
+/// The output of more than half of the evaluated copies, none if no output has a strict majority
+/// (a tie is never broken in favour of a copy the garbler may have corrupted).
+pub fn majority_output(outputs: &[Vec<u8>]) -> Option<Vec<u8>> {
+    outputs
+        .iter()
+        .find(|candidate| {
+            let votes = outputs.iter().filter(|other| other == candidate).count();
+            2 * votes > outputs.len()
+        })
+        .cloned()
+}
+
+pub fn cheating_detected(reason: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Cut-and-choose check failed: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{Channel, MemoryChannel};
+    use crate::party::Party;
+    use std::thread;
+
+    //Enough for two evaluated copies, whose outputs and consistency hashes are compared
+    const COPIES: usize = 5;
+
+    #[test]
+    fn majority_needs_more_than_half_the_copies() {
+        let (a, b, c) = (vec![1u8], vec![2u8], vec![3u8]);
+        assert_eq!(majority_output(&[]), None);
+        assert_eq!(
+            majority_output(&[a.clone(), b.clone(), a.clone()]),
+            Some(a.clone())
+        );
+        assert_eq!(majority_output(&[a.clone(), b.clone()]), None);
+        assert_eq!(
+            majority_output(&[a.clone(), b.clone(), b.clone(), a.clone()]),
+            None
+        );
+        assert_eq!(
+            majority_output(&[c.clone(), a.clone(), b.clone(), b.clone()]),
+            None
+        );
+        assert_eq!(
+            majority_output(&[c.clone(), b.clone(), b.clone(), b.clone()]),
+            Some(b)
+        );
+    }
+
+    #[test]
+    fn p1_shares_decode_to_the_input() {
+        let mut rng = rand::thread_rng();
+        let bits: Vec<bool> = (0..70).map(|_| rng.gen()).collect();
+        let shares = encode_p1_bits(&bits, &mut rng);
+        assert_eq!(shares.len(), bits.len() * P1_INPUT_SHARES);
+        for (chunk, &bit) in shares.chunks(P1_INPUT_SHARES).zip(&bits) {
+            assert_eq!(chunk.iter().fold(false, |acc, &share| acc ^ share), bit);
+        }
+        //a fresh encoding of the same input draws other shares
+        assert_ne!(encode_p1_bits(&bits, &mut rng), shares);
+
+        let garbler = GarbledCircuit::new();
+        let zero_labels: Vec<WireLabel> =
+            (0..70).map(|_| WireLabel::from_data(rng.gen())).collect();
+        let share_zero_labels = encode_p1_zero_labels(&mut rng, &zero_labels);
+        let share_labels: Vec<WireLabel> = share_zero_labels
+            .iter()
+            .zip(&shares)
+            .map(|(&zero, &share)| if share { zero ^ garbler.global_r } else { zero })
+            .collect();
+        let expected: Vec<WireLabel> = zero_labels
+            .iter()
+            .zip(&bits)
+            .map(|(&zero, &bit)| if bit { zero ^ garbler.global_r } else { zero })
+            .collect();
+        assert_eq!(decode_p1_labels(&share_labels), expected);
+    }
+
+    #[test]
+    fn consistency_hash_is_linear() {
+        let mut rng = rand::thread_rng();
+        let seed: [u8; 32] = rng.gen();
+        let matrix = consistency_matrix(seed, 300);
+        assert_eq!(matrix.len(), CONSISTENCY_HASH_BITS);
+        assert!(matrix.iter().all(|row| row.len() == 300));
+        assert_eq!(matrix, consistency_matrix(seed, 300));
+
+        let random_labels = |rng: &mut rand::rngs::ThreadRng| -> Vec<WireLabel> {
+            (0..300).map(|_| WireLabel::from_data(rng.gen())).collect()
+        };
+        let x = random_labels(&mut rng);
+        let y = random_labels(&mut rng);
+        let sum: Vec<WireLabel> = x.iter().zip(&y).map(|(&a, &b)| a ^ b).collect();
+        let expected: Vec<WireLabel> = apply_matrix(&matrix, &x)
+            .iter()
+            .zip(apply_matrix(&matrix, &y))
+            .map(|(&a, b)| a ^ b)
+            .collect();
+        assert_eq!(apply_matrix(&matrix, &sum), expected);
+        assert!(apply_matrix(&matrix, &vec![WireLabel::zero(); 300])
+            .iter()
+            .all(|label| *label == WireLabel::zero()));
+    }
+
+    //Swaps the first label pair of every checked copy the garbler opens
+    struct TamperCheckedCopies(MemoryChannel);
+
+    impl Channel for TamperCheckedCopies {
+        fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+            let opened = bincode::deserialize::<CheckedCopy>(data)
+                .ok()
+                .filter(|opened| bincode::serialize(opened).ok().as_deref() == Some(data));
+            match opened {
+                Some(mut opened) => {
+                    opened.p1_inputs[0].swap(0, 1);
+                    self.0.send_bytes(&bincode::serialize(&opened).unwrap())
+                }
+                None => self.0.send_bytes(data),
+            }
+        }
+
+        fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+            self.0.recv_bytes()
+        }
+    }
+
+    #[test]
+    fn tampered_checked_copy_aborts() {
+        let (c0, mut c1) = MemoryChannel::pair();
+        let p0 = thread::spawn(move || {
+            let mut p0 = Party::new(0, b"abc").unwrap();
+            p0.run_cut_and_choose_garbler(COPIES, &mut TamperCheckedCopies(c0))
+        });
+        let mut p1 = Party::new(1, b"xyz").unwrap();
+        let aborted = p1
+            .run_cut_and_choose_evaluator(COPIES, &mut c1)
+            .unwrap_err();
+        assert_eq!(aborted.kind(), io::ErrorKind::InvalidData);
+        assert!(aborted.to_string().contains("was not garbled honestly"));
+        drop(c1);
+        assert!(p0.join().unwrap().is_err());
+    }
+
+    //Flips a bit of the output labels the evaluator returns, the only frame of 256 labels it sends
+    struct TamperOutputLabels(MemoryChannel);
+
+    impl Channel for TamperOutputLabels {
+        fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+            let labels = bincode::deserialize::<Vec<WireLabel>>(data)
+                .ok()
+                .filter(|labels| labels.len() == 256);
+            match labels {
+                Some(mut labels) => {
+                    labels[0] ^= WireLabel::from_data([1; 16]);
+                    self.0.send_bytes(&bincode::serialize(&labels).unwrap())
+                }
+                None => self.0.send_bytes(data),
+            }
+        }
+
+        fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+            self.0.recv_bytes()
+        }
+    }
+
+    #[test]
+    fn forged_output_labels_are_rejected() {
+        let (mut c0, c1) = MemoryChannel::pair();
+        let p1 = thread::spawn(move || {
+            let mut p1 = Party::new(1, b"xyz").unwrap();
+            p1.run_cut_and_choose_evaluator(COPIES, &mut TamperOutputLabels(c1))
+        });
+        let mut p0 = Party::new(0, b"abc").unwrap();
+        let rejected = p0.run_cut_and_choose_garbler(COPIES, &mut c0).unwrap_err();
+        assert_eq!(rejected.kind(), io::ErrorKind::InvalidData);
+        assert!(rejected.to_string().contains("is not a valid label"));
+        assert!(p1.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn inconsistent_garbler_input_fails_the_consistency_hash() {
+        let x0 = b"abc".to_vec();
+        let x0_bits = crate::utils::convert_bytes2_bits(&x0);
+        let (mut c0, mut c1) = MemoryChannel::pair();
+        let p1 = thread::spawn(move || {
+            let mut p1 = Party::new(1, b"xyz").unwrap();
+            p1.run_cut_and_choose_evaluator(COPIES, &mut c1)
+        });
+        //copy c flips bit c of x0, so any two evaluated copies disagree while every checked
+        //copy opens honestly
+        let mut p0 = Party::new(0, &x0).unwrap();
+        let copy_x0 = |c: usize| {
+            let mut bits = x0_bits.clone();
+            bits[c] ^= c != 0;
+            bits
+        };
+        let garbled = p0.cut_and_choose_garbler(COPIES, &copy_x0, &mut c0);
+
+        let aborted = p1.join().unwrap().unwrap_err();
+        assert_eq!(aborted.kind(), io::ErrorKind::InvalidData);
+        assert!(aborted.to_string().contains("was fed a different x0"));
+        assert!(garbled.is_err());
+    }
+
+    #[test]
+    fn honest_run() {
+        let (mut c0, mut c1) = MemoryChannel::pair();
+        let p1 = thread::spawn(move || {
+            let mut p1 = Party::new(1, b"xyz").unwrap();
+            p1.run_cut_and_choose_evaluator(COPIES, &mut c1)
+        });
+        let mut p0 = Party::new(0, b"abc").unwrap();
+        let digest = p0.run_cut_and_choose_garbler(COPIES, &mut c0).unwrap();
+        let message: Vec<u8> = b"abc".iter().zip(b"xyz").map(|(a, b)| a ^ b).collect();
+        assert_eq!(digest, Sha256::digest(&message).to_vec());
+        assert_eq!(p1.join().unwrap().unwrap(), digest);
+    }
+}
@@ -1,4 +1,5 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use std::ops::BitXor;
 use std::ops::BitXorAssign;
@@ -6,21 +7,43 @@ use std::ops::Index;
 
 use crate::circuit::Sha256Circuit;
 use std::collections::HashMap;
-use std::collections::VecDeque;
 
 use crate::circuit::XorAndGate;
 use aes::cipher::generic_array::GenericArray;
 use aes::{Aes128, BlockEncrypt, NewBlockCipher};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::io;
 use typenum::U16;
 
 /// A 128-bit wire label (same size as an AES block).
 pub const LABEL_SECURITY_LEVEL: usize = 16;
-
-// #[derive(Clone, Debug, Copy, PartialEq, Eq)]
-// pub struct WireLabel([u8; LABEL_SECURITY_LEVEL]);
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+const HALF_LABEL_LEN: usize = LABEL_SECURITY_LEVEL / 2;
+
+// Three-halves decoding: the evaluator holding colors (i, j) with control bits r XORs the halves
+// (A_L, A_R, B_L, B_R) selected by bit 0..3 of BASE[2i+j] ^ r0*CONTROL0 ^ r1*CONTROL1 into (C_L, C_R)
+const THREE_HALVES_BASE: [[u8; 2]; 4] = [
+    [0b0000, 0b0000],
+    [0b0000, 0b0010],
+    [0b0100, 0b0000],
+    [0b0100, 0b0010],
+];
+const THREE_HALVES_CONTROL0: [u8; 2] = [0b0110, 0b1101];
+const THREE_HALVES_CONTROL1: [u8; 2] = [0b1011, 0b0110];
+// How the permute bits (p_a, p_b) of the inputs flip each evaluator's control bits
+const THREE_HALVES_PERMUTE: [(u8, u8); 4] =
+    [(0b00, 0b00), (0b10, 0b11), (0b11, 0b01), (0b01, 0b10)];
+
+//Key of the fixed-key AES permutation π, the SHA-256 initial hash words H[3..7]
+const FIXED_AES_KEY: [u8; 16] = [
+    0xa5, 0x4f, 0xf5, 0x3a, // H[3]
+    0x51, 0x0e, 0x52, 0x7f, // H[4]
+    0x9b, 0x05, 0x68, 0x8c, // H[5]
+    0x1f, 0x83, 0xd9, 0xab, // H[6]
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WireLabel([u8; LABEL_SECURITY_LEVEL]);
 
 impl WireLabel {
@@ -47,6 +70,34 @@ impl WireLabel {
     pub fn check_lsb(&self) -> bool {
         (self.0[LABEL_SECURITY_LEVEL - 1] & 1) == 1
     }
+
+    //The left and right halves of the label, three-halves garbling slices every label this way
+    fn halves(&self) -> [u64; 2] {
+        let mut left = [0u8; HALF_LABEL_LEN];
+        let mut right = [0u8; HALF_LABEL_LEN];
+        left.copy_from_slice(&self.0[..HALF_LABEL_LEN]);
+        right.copy_from_slice(&self.0[HALF_LABEL_LEN..]);
+        [u64::from_le_bytes(left), u64::from_le_bytes(right)]
+    }
+
+    fn from_halves(halves: [u64; 2]) -> Self {
+        let mut label = [0u8; LABEL_SECURITY_LEVEL];
+        label[..HALF_LABEL_LEN].copy_from_slice(&halves[0].to_le_bytes());
+        label[HALF_LABEL_LEN..].copy_from_slice(&halves[1].to_le_bytes());
+        WireLabel(label)
+    }
+
+    //Multiplication by x in GF(2^128) modulo x^128 + x^7 + x^2 + x + 1, little endian
+    fn gf_double(&self) -> WireLabel {
+        let value = u128::from_le_bytes(self.0);
+        let reduce: u128 = if value >> 127 == 1 { 0x87 } else { 0 };
+        WireLabel(((value << 1) ^ reduce).to_le_bytes())
+    }
+
+    //Two spare hash bits that encrypt a three-halves control entry
+    fn control_mask(&self) -> u8 {
+        self.0[HALF_LABEL_LEN] & 0b11
+    }
 }
 
 impl Index<usize> for WireLabel {
@@ -91,42 +142,303 @@ pub struct EvalWire {
 pub struct GarbledCircuit {
     pub prf: Aes128,
     pub cur_idx: usize,
-    pub rng: rand::rngs::ThreadRng,
+    pub rng: StdRng,
     pub global_r: WireLabel,
+    pub mode: GarblingMode,
+}
+
+/// How AND gates are garbled, both parties must agree on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GarblingMode {
+    HalfGates,       //ZRE15, two ciphertexts (2κ bits) per AND gate
+    ThreeHalves,     //RR21, three half ciphertexts and 4 control bits (1.5κ + 4 bits) per AND gate
+    PointAndPermute, //classical Yao with point-and-permute, four ciphertexts (4κ bits) per AND gate
+    Grr3,            //NPS99 row reduction, three ciphertexts (3κ bits) per AND gate
+}
+
+impl GarblingMode {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "halfgates" => Ok(GarblingMode::HalfGates),
+            "threehalves" => Ok(GarblingMode::ThreeHalves),
+            "pointpermute" => Ok(GarblingMode::PointAndPermute),
+            "grr3" => Ok(GarblingMode::Grr3),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown garbling mode {}, use halfgates, threehalves, pointpermute or grr3",
+                    other
+                ),
+            )),
+        }
+    }
+
+    /// Size of one AND table garbled in this mode, in bytes
+    pub fn table_bytes(&self) -> usize {
+        match self {
+            GarblingMode::HalfGates => HalfGatesScheme::TABLE_BYTES,
+            GarblingMode::ThreeHalves => ThreeHalvesScheme::TABLE_BYTES,
+            GarblingMode::PointAndPermute => PointAndPermuteScheme::TABLE_BYTES,
+            GarblingMode::Grr3 => Grr3Scheme::TABLE_BYTES,
+        }
+    }
 }
 
 /// The garble table of each AND gate:
+#[derive(Serialize, Deserialize)]
 pub struct GarbleAnd {
     pub t_g: WireLabel,
     pub t_e: WireLabel,
 }
 
+/// The three-halves garble table of an AND gate: three half-size ciphertexts, plus the control
+/// bits of the evaluators holding colors (0,1) (bits 0-1) and (1,0) (bits 2-3) encrypted under
+/// their input labels. The (0,0) entry is implicitly zero and the (1,1) entry is the XOR of both.
+#[derive(Serialize, Deserialize)]
+pub struct GarbleThreeHalves {
+    pub g: [[u8; HALF_LABEL_LEN]; 3],
+    pub control: u8,
+}
+
+/// The point-and-permute table of an AND gate: one ciphertext per color pair (i, j), at row 2i+j.
+#[derive(Serialize, Deserialize)]
+pub struct GarbleFourRow {
+    pub rows: [WireLabel; 4],
+}
+
+/// The GRR3 table of an AND gate: the rows of color pairs (0,1), (1,0) and (1,1), the (0,0) row
+/// is the zero string because the output labels are derived from its hash.
+#[derive(Serialize, Deserialize)]
+pub struct GarbleGrr3 {
+    pub rows: [WireLabel; 3],
+}
+
+/// A free-XOR compatible way of garbling a single AND gate. `GarbledCircuit` handles the wiring
+/// (XOR/INV gates, label maps, gate order), a scheme only turns input labels into a table.
+pub trait GarblingScheme {
+    type Table: Serialize + DeserializeOwned;
+
+    /// Size of one serialized table in bytes
+    const TABLE_BYTES: usize;
+
+    /// Garble an AND gate from the false labels of its inputs, returns the table and the false
+    /// label of the output.
+    fn garble_and(
+        gc: &mut GarbledCircuit,
+        wa_0: WireLabel,
+        wb_0: WireLabel,
+    ) -> (Self::Table, WireLabel);
+
+    /// Recover the output label from the held input labels.
+    fn evaluate_and(
+        gc: &mut GarbledCircuit,
+        wa: WireLabel,
+        wb: WireLabel,
+        table: &Self::Table,
+    ) -> WireLabel;
+}
+
+pub struct HalfGatesScheme;
+pub struct ThreeHalvesScheme;
+pub struct PointAndPermuteScheme;
+pub struct Grr3Scheme;
+
+impl GarblingScheme for HalfGatesScheme {
+    type Table = GarbleAnd;
+    const TABLE_BYTES: usize = 2 * LABEL_SECURITY_LEVEL;
+
+    fn garble_and(
+        gc: &mut GarbledCircuit,
+        wa_0: WireLabel,
+        wb_0: WireLabel,
+    ) -> (GarbleAnd, WireLabel) {
+        let delta = gc.global_r;
+        gc.garble_half_gates(wa_0, wa_0 ^ delta, wb_0, wb_0 ^ delta)
+    }
+
+    fn evaluate_and(
+        gc: &mut GarbledCircuit,
+        wa: WireLabel,
+        wb: WireLabel,
+        table: &GarbleAnd,
+    ) -> WireLabel {
+        gc.evaluate_half_gates(wa, wb, table)
+    }
+}
+
+impl GarblingScheme for ThreeHalvesScheme {
+    type Table = GarbleThreeHalves;
+    const TABLE_BYTES: usize = 3 * HALF_LABEL_LEN + 1;
+
+    fn garble_and(
+        gc: &mut GarbledCircuit,
+        wa_0: WireLabel,
+        wb_0: WireLabel,
+    ) -> (GarbleThreeHalves, WireLabel) {
+        gc.garble_three_halves(wa_0, wb_0)
+    }
+
+    fn evaluate_and(
+        gc: &mut GarbledCircuit,
+        wa: WireLabel,
+        wb: WireLabel,
+        table: &GarbleThreeHalves,
+    ) -> WireLabel {
+        gc.evaluate_three_halves(wa, wb, table)
+    }
+}
+
+impl GarblingScheme for PointAndPermuteScheme {
+    type Table = GarbleFourRow;
+    const TABLE_BYTES: usize = 4 * LABEL_SECURITY_LEVEL;
+
+    fn garble_and(
+        gc: &mut GarbledCircuit,
+        wa_0: WireLabel,
+        wb_0: WireLabel,
+    ) -> (GarbleFourRow, WireLabel) {
+        let hashes = gc.color_row_hashes(wa_0, wb_0);
+        let p_a: bool = wa_0.check_lsb();
+        let p_b: bool = wb_0.check_lsb();
+
+        //The output labels are fresh, the table carries both of them
+        let mut out = [0u8; LABEL_SECURITY_LEVEL];
+        gc.rng.fill(&mut out);
+        let c_0 = WireLabel(out);
+
+        let mut rows = [WireLabel::zero(); 4];
+        for (k, row) in rows.iter_mut().enumerate() {
+            let (i, j) = (k >> 1 == 1, k & 1 == 1);
+            *row = hashes[k] ^ c_0;
+            if (i ^ p_a) && (j ^ p_b) {
+                *row ^= gc.global_r;
+            }
+        }
+        (GarbleFourRow { rows }, c_0)
+    }
+
+    fn evaluate_and(
+        gc: &mut GarbledCircuit,
+        wa: WireLabel,
+        wb: WireLabel,
+        table: &GarbleFourRow,
+    ) -> WireLabel {
+        let colors: usize = 2 * wa.check_lsb() as usize + wb.check_lsb() as usize;
+        table.rows[colors] ^ gc.row_hash(wa, wb)
+    }
+}
+
+impl GarblingScheme for Grr3Scheme {
+    type Table = GarbleGrr3;
+    const TABLE_BYTES: usize = 3 * LABEL_SECURITY_LEVEL;
+
+    fn garble_and(
+        gc: &mut GarbledCircuit,
+        wa_0: WireLabel,
+        wb_0: WireLabel,
+    ) -> (GarbleGrr3, WireLabel) {
+        let hashes = gc.color_row_hashes(wa_0, wb_0);
+        let p_a: bool = wa_0.check_lsb();
+        let p_b: bool = wb_0.check_lsb();
+
+        //The color (0,0) row decrypts to its own hash, which fixes the output labels
+        let mut c_0 = hashes[0];
+        if p_a && p_b {
+            c_0 ^= gc.global_r;
+        }
+
+        let mut rows = [WireLabel::zero(); 3];
+        for (k, row) in rows.iter_mut().enumerate() {
+            let (i, j) = ((k + 1) >> 1 == 1, (k + 1) & 1 == 1);
+            *row = hashes[k + 1] ^ c_0;
+            if (i ^ p_a) && (j ^ p_b) {
+                *row ^= gc.global_r;
+            }
+        }
+        (GarbleGrr3 { rows }, c_0)
+    }
+
+    fn evaluate_and(
+        gc: &mut GarbledCircuit,
+        wa: WireLabel,
+        wb: WireLabel,
+        table: &GarbleGrr3,
+    ) -> WireLabel {
+        let colors: usize = 2 * wa.check_lsb() as usize + wb.check_lsb() as usize;
+        let hash = gc.row_hash(wa, wb);
+        match colors {
+            0 => hash,
+            _ => table.rows[colors - 1] ^ hash,
+        }
+    }
+}
+
+/// The AND tables of a circuit (or block), all garbled with the same mode.
+#[derive(Serialize, Deserialize)]
+pub enum GarbledTables {
+    HalfGates(Vec<GarbleAnd>),
+    ThreeHalves(Vec<GarbleThreeHalves>),
+    PointAndPermute(Vec<GarbleFourRow>),
+    Grr3(Vec<GarbleGrr3>),
+}
+
+impl GarbledTables {
+    pub fn len(&self) -> usize {
+        match self {
+            GarbledTables::HalfGates(tables) => tables.len(),
+            GarbledTables::ThreeHalves(tables) => tables.len(),
+            GarbledTables::PointAndPermute(tables) => tables.len(),
+            GarbledTables::Grr3(tables) => tables.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn mode(&self) -> GarblingMode {
+        match self {
+            GarbledTables::HalfGates(_) => GarblingMode::HalfGates,
+            GarbledTables::ThreeHalves(_) => GarblingMode::ThreeHalves,
+            GarbledTables::PointAndPermute(_) => GarblingMode::PointAndPermute,
+            GarbledTables::Grr3(_) => GarblingMode::Grr3,
+        }
+    }
+}
+
+impl Default for GarbledCircuit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GarbledCircuit {
     pub fn new() -> Self {
-        // let seed = b"I-am-a-random-seed-a-random-seed";
-        // [0u8; 32]; // 32 bytes for a 256-bit seed (you can choose any seed bytes)
-        // let mut rng = StdRng::from_seed(*seed);
-
-        const FIXED_AES_KEY: [u8; 16] = [
-            0xa5, 0x4f, 0xf5, 0x3a, // H[3]
-            0x51, 0x0e, 0x52, 0x7f, // H[4]
-            0x9b, 0x05, 0x68, 0x8c, // H[5]
-            0x1f, 0x83, 0xd9, 0xab, // H[6]
-        ];
+        Self::with_mode(GarblingMode::HalfGates)
+    }
+
+    pub fn with_mode(mode: GarblingMode) -> Self {
+        Self::with_rng(mode, StdRng::from_entropy())
+    }
 
-        let mut m_rng = rand::thread_rng();
+    /// A garbler whose global R, input labels and tables are all derived from `seed`, so that
+    /// anyone holding the seed can regarble the very same circuit (cut-and-choose).
+    pub fn from_seed(mode: GarblingMode, seed: [u8; 32]) -> Self {
+        Self::with_rng(mode, StdRng::from_seed(seed))
+    }
+
+    fn with_rng(mode: GarblingMode, mut m_rng: StdRng) -> Self {
         let mut empty_global_r = [0u8; LABEL_SECURITY_LEVEL];
         m_rng.fill(&mut empty_global_r);
         let mut global: WireLabel = WireLabel(empty_global_r);
         global.reset_lsb(); // guarantee the LSB (least significant bit)to be 1
 
-        // println!("Debug: global R is {:?}", global);
-
         GarbledCircuit {
             prf: Aes128::new(GenericArray::from_slice(&FIXED_AES_KEY)),
             cur_idx: 0,
             rng: m_rng,
             global_r: global,
+            mode,
         }
     }
 
@@ -135,46 +447,307 @@ impl GarbledCircuit {
         self.cur_idx
     }
 
-    //A PRF update procedure.
-    fn prf_update(&self, label: &WireLabel, tweak: u64) -> WireLabel {
-        // Prepare a mutable block for encryption
-        let mut block = label.to_generic_array();
-        // XOR the tweak into the last 8 bytes (very naive):
-        let tweak_bytes = tweak.to_le_bytes();
-        for i in 0..8 {
-            block[8 + i] ^= tweak_bytes[i];
+    /// The tweakable circular correlation-robust hash of [GKWY20](https://eprint.iacr.org/2019/074),
+    /// H(x, i) = π(σ(x) ⊕ i) ⊕ σ(x), with π the fixed-key AES permutation and the linear
+    /// orthomorphism σ(xL ‖ xR) = (xL ⊕ xR) ‖ xL over the two 64-bit halves of the label.
+    /// The tweak i occupies the last 8 bytes of the block (little endian).
+    ///
+    /// Known answers under the fixed key, computed with OpenSSL's AES-128 and σ written out by hand:
+    /// ```
+    /// use sha256_gc::gc::{GarbledCircuit, WireLabel};
+    ///
+    /// let gc = GarbledCircuit::new();
+    /// let zero = gc.tccr_hash(&WireLabel::zero(), 0);
+    /// assert_eq!(hex::encode(zero.to_generic_array()), "292ce0ad36976ff8c4d48ab24399445e");
+    ///
+    /// let label = WireLabel::from_data(*b"0123456789abcdef");
+    /// let hashed = gc.tccr_hash(&label, 1);
+    /// assert_eq!(hex::encode(hashed.to_generic_array()), "69e55ce1da25f4e6918b72fd22349d14");
+    /// let hashed = gc.tccr_hash(&label, 0xfedc_ba98_7654_3210);
+    /// assert_eq!(hex::encode(hashed.to_generic_array()), "b6c7fc4cadd0c71978a64b35cea8e749");
+    /// ```
+    pub fn tccr_hash(&self, label: &WireLabel, tweak: u64) -> WireLabel {
+        let half: usize = LABEL_SECURITY_LEVEL / 2;
+        let mut sigma = [0u8; LABEL_SECURITY_LEVEL];
+        for i in 0..half {
+            sigma[i] = label.0[i] ^ label.0[half + i];
+            sigma[half + i] = label.0[i];
+        }
+
+        let mut block = GenericArray::from(sigma);
+        for (b, t) in block[half..].iter_mut().zip(tweak.to_le_bytes()) {
+            *b ^= t;
         }
         self.prf.encrypt_block(&mut block);
 
-        WireLabel(block.into())
+        WireLabel(block.into()) ^ WireLabel(sigma)
     }
 
-    fn evaluate_xor_gate(&self, zero_label_map: &mut HashMap<usize, EvalWire>, gate: &XorAndGate) {
-        //I merged the (out-in wire) two flipped bits
-        match zero_label_map.get(&gate.input0) {
-            Some(eval_wire) => {
-                let mut new_label = eval_wire.label;
-                let mut new_flipped = eval_wire.flipped ^ gate.input0_flipped; //merge output-input flipped states
-                match zero_label_map.get(&gate.input1) {
-                    Some(eval_wire1) => {
-                        new_label ^= eval_wire1.label;
-                        new_flipped ^= eval_wire1.flipped ^ gate.input1_flipped;
-                        zero_label_map.insert(
-                            gate.output,
-                            EvalWire {
-                                label: new_label,
-                                flipped: new_flipped,
-                            },
-                        );
-                    }
-                    None => {
-                        println!("Key {} not found when fetch XOR gate input1", gate.input1);
-                    }
+    /// Hash of a pair of labels, H(A, B, i) = H(2A ⊕ 4B, i) with the doubling in GF(2^128) as
+    /// in [BHKR13](https://eprint.iacr.org/2013/426). Hashing H(A) ⊕ H(B) instead would let the
+    /// four rows of a table cancel out under free XOR and reveal R.
+    pub fn dual_key_hash(&self, wa: &WireLabel, wb: &WireLabel, tweak: u64) -> WireLabel {
+        self.tccr_hash(&(wa.gf_double() ^ wb.gf_double().gf_double()), tweak)
+    }
+
+    //Row key of the classical schemes, one gate index per AND gate
+    fn row_hash(&mut self, wa: WireLabel, wb: WireLabel) -> WireLabel {
+        let j: u64 = self.next_index() as u64;
+        self.dual_key_hash(&wa, &wb, j)
+    }
+
+    //The row keys of all four color pairs (i, j) at index 2i+j, from the false input labels
+    fn color_row_hashes(&mut self, wa_0: WireLabel, wb_0: WireLabel) -> [WireLabel; 4] {
+        let j: u64 = self.next_index() as u64;
+        let by_color = |label: WireLabel, color: usize| -> WireLabel {
+            if label.check_lsb() == (color == 1) {
+                label
+            } else {
+                label ^ self.global_r
+            }
+        };
+        let mut hashes = [WireLabel::zero(); 4];
+        for (k, hash) in hashes.iter_mut().enumerate() {
+            let a = by_color(wa_0, k >> 1);
+            let b = by_color(wb_0, k & 1);
+            *hash = self.dual_key_hash(&a, &b, j);
+        }
+        hashes
+    }
+
+    //Half gates: garble an AND gate given both labels of each input wire (index = truth value)
+    fn garble_half_gates(
+        &mut self,
+        wa_0: WireLabel,
+        wa_1: WireLabel,
+        wb_0: WireLabel,
+        wb_1: WireLabel,
+    ) -> (GarbleAnd, WireLabel) {
+        let j: u64 = self.next_index() as u64;
+        let j_prime: u64 = self.next_index() as u64;
+
+        //make garbled table from here, firstly update zero_label depending on flipped state
+        let p_a: bool = wa_0.check_lsb(); //input wire 0 permutation bit
+        let p_b: bool = wb_0.check_lsb(); //input wire 1 permutation bit
+
+        //step-0: First half gate
+        let wa_0_enc = self.tccr_hash(&wa_0, j);
+        let mut t_g: WireLabel = wa_0_enc ^ self.tccr_hash(&wa_1, j);
+        if p_b {
+            t_g ^= self.global_r;
+        }
+        // Step 1: Calculate W_G
+        let mut wg_0: WireLabel = wa_0_enc;
+        if p_a {
+            wg_0 ^= t_g;
+        }
+
+        // Step 2: Second half gate
+        let wb_0_enc = self.tccr_hash(&wb_0, j_prime);
+        let t_e: WireLabel = wb_0_enc ^ self.tccr_hash(&wb_1, j_prime) ^ wa_0;
+        let mut we_0: WireLabel = wb_0_enc;
+        if p_b {
+            we_0 ^= wa_0 ^ t_e;
+        }
+
+        (GarbleAnd { t_g, t_e }, wg_0 ^ we_0)
+    }
+
+    fn evaluate_half_gates(
+        &mut self,
+        wa: WireLabel,
+        wb: WireLabel,
+        garbled: &GarbleAnd,
+    ) -> WireLabel {
+        let s_a: bool = wa.check_lsb(); //input wire 0 permutation bit
+        let s_b: bool = wb.check_lsb(); //input wire 1 permutation bit
+
+        //Step 1: Calculate W_G
+        let j = self.next_index() as u64;
+        let j_prime = self.next_index() as u64;
+        let mut wg: WireLabel = self.tccr_hash(&wa, j);
+        if s_a {
+            wg ^= garbled.t_g;
+        }
+        // Step 2: Second half gate
+        let mut we: WireLabel = self.tccr_hash(&wb, j_prime);
+        if s_b {
+            we ^= wa ^ garbled.t_e;
+        }
+        wg ^ we
+    }
+
+    //The part of a three-halves output label the evaluator computes without the ciphertexts:
+    //C_L = H(A)_L ^ H(A^B)_L ^ row0 and C_R = H(B)_L ^ H(A^B)_L ^ row1, each row a XOR of
+    //input label halves picked by the evaluator's colors and control bits
+    fn three_halves_decode(
+        wa: &WireLabel,
+        wb: &WireLabel,
+        hashes: [&WireLabel; 3],
+        colors: usize,
+        control: u8,
+    ) -> [u64; 2] {
+        let inputs = [
+            wa.halves()[0],
+            wa.halves()[1],
+            wb.halves()[0],
+            wb.halves()[1],
+        ];
+        let h_ab = hashes[2].halves()[0];
+        let mut out = [hashes[0].halves()[0] ^ h_ab, hashes[1].halves()[0] ^ h_ab];
+        for (k, half) in out.iter_mut().enumerate() {
+            let mut select = THREE_HALVES_BASE[colors][k];
+            if control & 1 == 1 {
+                select ^= THREE_HALVES_CONTROL0[k];
+            }
+            if control & 2 == 2 {
+                select ^= THREE_HALVES_CONTROL1[k];
+            }
+            for (t, input) in inputs.iter().enumerate() {
+                if (select >> t) & 1 == 1 {
+                    *half ^= input;
                 }
             }
-            None => {
-                println!("Key {} not found when fetch XOR gate input0", gate.input0);
+        }
+        out
+    }
+
+    //Three halves (Rosulek-Roy 2021): labels are sliced into halves, the evaluator holding colors
+    //(i, j) hashes A, B and A^B and combines them with the ciphertexts and a linear function of
+    //the input halves. That function depends on two random control bits per evaluator so that
+    //none of them learns the permute bits, the garbler solves for the ciphertexts accordingly.
+    fn garble_three_halves(
+        &mut self,
+        wa_0: WireLabel,
+        wb_0: WireLabel,
+    ) -> (GarbleThreeHalves, WireLabel) {
+        let delta = self.global_r.halves();
+        let p_a: bool = wa_0.check_lsb(); //input wire 0 permutation bit
+        let p_b: bool = wb_0.check_lsb(); //input wire 1 permutation bit
+
+        //labels indexed by color (lsb) rather than truth value
+        let a_0 = if p_a { wa_0 ^ self.global_r } else { wa_0 };
+        let b_0 = if p_b { wb_0 ^ self.global_r } else { wb_0 };
+        let a = [a_0, a_0 ^ self.global_r];
+        let b = [b_0, b_0 ^ self.global_r];
+
+        let j_a: u64 = self.next_index() as u64;
+        let j_b: u64 = self.next_index() as u64;
+        let j_ab: u64 = self.next_index() as u64;
+        let h_a = [self.tccr_hash(&a[0], j_a), self.tccr_hash(&a[1], j_a)];
+        let h_b = [self.tccr_hash(&b[0], j_b), self.tccr_hash(&b[1], j_b)];
+        //A_i ^ B_j only depends on i ^ j
+        let h_ab = [
+            self.tccr_hash(&(a[0] ^ b[0]), j_ab),
+            self.tccr_hash(&(a[0] ^ b[1]), j_ab),
+        ];
+
+        //Control bits: uniform for every single evaluator, and the (0,0) entry is chosen to be
+        //its own mask so it never has to be sent
+        let random_bits = h_a[0].control_mask() ^ h_b[0].control_mask();
+        let control = |i: usize, j: usize| -> u8 {
+            let (p_mask, q_mask) = THREE_HALVES_PERMUTE[2 * i + j];
+            random_bits ^ if p_a { p_mask } else { 0 } ^ if p_b { q_mask } else { 0 }
+        };
+        let partial = |i: usize, j: usize| -> [u64; 2] {
+            Self::three_halves_decode(
+                &a[i],
+                &b[j],
+                [&h_a[i], &h_b[j], &h_ab[i ^ j]],
+                2 * i + j,
+                control(i, j),
+            )
+        };
+
+        //The evaluator with colors (0,0) uses no ciphertext, which fixes the output zero label
+        let mut c_0 = partial(0, 0);
+        if p_a && p_b {
+            c_0 = [c_0[0] ^ delta[0], c_0[1] ^ delta[1]];
+        }
+        //What the ciphertexts must contribute for colors (i, j)
+        let needed = |i: usize, j: usize| -> [u64; 2] {
+            let rest = partial(i, j);
+            let mut target = [c_0[0] ^ rest[0], c_0[1] ^ rest[1]];
+            if ((i == 1) ^ p_a) && ((j == 1) ^ p_b) {
+                target = [target[0] ^ delta[0], target[1] ^ delta[1]];
             }
+            target
+        };
+        //(1,1) uses (G0, G1), (0,1) uses (G2, G1^G2) and (1,0) uses (G0^G2, G2)
+        let needed_11 = needed(1, 1);
+        let g = [needed_11[0], needed_11[1], needed(0, 1)[0]];
+
+        let z_01 = control(0, 1) ^ h_a[0].control_mask() ^ h_b[1].control_mask();
+        let z_10 = control(1, 0) ^ h_a[1].control_mask() ^ h_b[0].control_mask();
+        (
+            GarbleThreeHalves {
+                g: [g[0].to_le_bytes(), g[1].to_le_bytes(), g[2].to_le_bytes()],
+                control: z_01 | (z_10 << 2),
+            },
+            WireLabel::from_halves(c_0),
+        )
+    }
+
+    fn evaluate_three_halves(
+        &mut self,
+        wa: WireLabel,
+        wb: WireLabel,
+        garbled: &GarbleThreeHalves,
+    ) -> WireLabel {
+        let i: usize = wa.check_lsb() as usize;
+        let j: usize = wb.check_lsb() as usize;
+
+        let j_a: u64 = self.next_index() as u64;
+        let j_b: u64 = self.next_index() as u64;
+        let j_ab: u64 = self.next_index() as u64;
+        let h_a = self.tccr_hash(&wa, j_a);
+        let h_b = self.tccr_hash(&wb, j_b);
+        let h_ab = self.tccr_hash(&(wa ^ wb), j_ab);
+
+        let z_01 = garbled.control & 0b11;
+        let z_10 = (garbled.control >> 2) & 0b11;
+        let z = [0, z_01, z_10, z_01 ^ z_10][2 * i + j];
+        let control = z ^ h_a.control_mask() ^ h_b.control_mask();
+
+        let g = garbled.g.map(u64::from_le_bytes);
+        let cipher = match (i, j) {
+            (0, 0) => [0, 0],
+            (0, 1) => [g[2], g[1] ^ g[2]],
+            (1, 0) => [g[0] ^ g[2], g[2]],
+            _ => [g[0], g[1]],
+        };
+        let out = Self::three_halves_decode(&wa, &wb, [&h_a, &h_b, &h_ab], 2 * i + j, control);
+        WireLabel::from_halves([out[0] ^ cipher[0], out[1] ^ cipher[1]])
+    }
+
+    fn evaluate_xor_gate(
+        &self,
+        zero_label_map: &mut HashMap<usize, EvalWire>,
+        gate: &XorAndGate,
+    ) -> io::Result<()> {
+        let wire0 = input_wire(zero_label_map, gate.input0, "XOR")?;
+        let wire1 = input_wire(zero_label_map, gate.input1, "XOR")?;
+        //I merged the (out-in wire) two flipped bits
+        let eval_wire = EvalWire {
+            label: wire0.label ^ wire1.label,
+            flipped: wire0.flipped ^ gate.input0_flipped ^ wire1.flipped ^ gate.input1_flipped,
+        };
+        zero_label_map.insert(gate.output, eval_wire);
+        Ok(())
+    }
+
+    //Zero label of an AND gate input, with the flip of a folded NOT gate applied
+    fn and_input_zero_label(
+        &self,
+        zero_label_map: &HashMap<usize, EvalWire>,
+        wire: usize,
+        flipped: bool,
+    ) -> io::Result<WireLabel> {
+        let eval_wire = input_wire(zero_label_map, wire, "AND")?;
+        if eval_wire.flipped ^ flipped {
+            Ok(eval_wire.label ^ self.global_r)
+        } else {
+            Ok(eval_wire.label)
         }
     }
 
@@ -184,103 +757,54 @@ impl GarbledCircuit {
         &mut self,
         circuit: &Sha256Circuit,
         zero_label_map: &mut HashMap<usize, EvalWire>,
-    ) -> Vec<GarbleAnd> {
-        let mut garbled_vec: Vec<GarbleAnd> = Vec::new();
+    ) -> io::Result<GarbledTables> {
+        Ok(match self.mode {
+            GarblingMode::HalfGates => GarbledTables::HalfGates(
+                self.garble_with::<HalfGatesScheme>(circuit, zero_label_map)?,
+            ),
+            GarblingMode::ThreeHalves => GarbledTables::ThreeHalves(
+                self.garble_with::<ThreeHalvesScheme>(circuit, zero_label_map)?,
+            ),
+            GarblingMode::PointAndPermute => GarbledTables::PointAndPermute(
+                self.garble_with::<PointAndPermuteScheme>(circuit, zero_label_map)?,
+            ),
+            GarblingMode::Grr3 => {
+                GarbledTables::Grr3(self.garble_with::<Grr3Scheme>(circuit, zero_label_map)?)
+            }
+        })
+    }
+
+    /// Garble the circuit with the AND gates of scheme `S`, the tables follow the gate order.
+    pub fn garble_with<S: GarblingScheme>(
+        &mut self,
+        circuit: &Sha256Circuit,
+        zero_label_map: &mut HashMap<usize, EvalWire>,
+    ) -> io::Result<Vec<S::Table>> {
+        let mut garbled_tables: Vec<S::Table> = Vec::new();
         for gate in &circuit.extra_gates {
             if gate.gate_type {
                 //AND
-
-                match zero_label_map.get(&gate.input0) {
-                    Some(eval_wire) => {
-                        #[allow(unused_assignments)]
-                        let mut wa_0 = WireLabel::zero();
-                        #[allow(unused_assignments)]
-                        let mut wa_1 = WireLabel::zero();
-                        let final_flipped: bool = eval_wire.flipped ^ gate.input0_flipped;
-                        if final_flipped {
-                            wa_1 = eval_wire.label;
-                            wa_0 = wa_1 ^ self.global_r;
-                        } else {
-                            wa_0 = eval_wire.label;
-                            wa_1 = wa_0 ^ self.global_r;
-                        }
-
-                        match zero_label_map.get(&gate.input1) {
-                            Some(eval_wire) => {
-                                #[allow(unused_assignments)]
-                                let mut wb_0 = WireLabel::zero();
-                                #[allow(unused_assignments)]
-                                let mut wb_1 = WireLabel::zero();
-
-                                let final_flipped: bool = eval_wire.flipped ^ gate.input1_flipped;
-                                if final_flipped {
-                                    wb_1 = eval_wire.label;
-                                    wb_0 = wb_1 ^ self.global_r;
-                                } else {
-                                    wb_0 = eval_wire.label;
-                                    wb_1 = wb_0 ^ self.global_r;
-                                }
-
-                                let j: u64 = self.next_index() as u64;
-                                let j_prime: u64 = self.next_index() as u64;
-
-                                //make garbled table from here, firstly update zero_label depending on flipped state
-                                let p_a: bool = wa_0.check_lsb(); //input wire 0 permutation bit
-                                let p_b: bool = wb_0.check_lsb(); //input wire 1 permutation bit
-
-                                //step-0: First half gate
-                                let wa_0_enc = self.prf_update(&wa_0, j);
-                                let mut t_g: WireLabel = wa_0_enc ^ self.prf_update(&wa_1, j);
-                                if p_b {
-                                    t_g ^= self.global_r;
-                                }
-                                // Step 1: Calculate W_G
-                                let mut wg_0: WireLabel = wa_0_enc;
-                                if p_a {
-                                    wg_0 ^= t_g;
-                                }
-
-                                // Step 2: Second half gate
-                                let wb_0_enc = self.prf_update(&wb_0, j_prime);
-                                let t_e: WireLabel =
-                                    wb_0_enc ^ self.prf_update(&wb_1, j_prime) ^ wa_0;
-                                let mut we_0: WireLabel = wb_0_enc;
-                                if p_b {
-                                    we_0 ^= wa_0 ^ t_e;
-                                }
-
-                                //Returning the output values
-                                garbled_vec.push(GarbleAnd { t_g, t_e });
-
-                                //I Should set the flipped bit here as false, because AND gate renews everything
-                                zero_label_map.insert(
-                                    gate.output,
-                                    EvalWire {
-                                        label: wg_0 ^ we_0,
-                                        flipped: false,
-                                    },
-                                );
-                            }
-                            None => {
-                                println!(
-                                    "Key {} not found when fetch  AND gate input1",
-                                    gate.input1
-                                );
-                                break;
-                            }
-                        }
-                    }
-                    None => {
-                        println!("Key {} not found when fetch AND gate input0", gate.input0);
-                        break;
-                    }
-                }
+                let wa_0 =
+                    self.and_input_zero_label(zero_label_map, gate.input0, gate.input0_flipped)?;
+                let wb_0 =
+                    self.and_input_zero_label(zero_label_map, gate.input1, gate.input1_flipped)?;
+                let (table, label) = S::garble_and(self, wa_0, wb_0);
+                garbled_tables.push(table);
+
+                //I Should set the flipped bit here as false, because AND gate renews everything
+                zero_label_map.insert(
+                    gate.output,
+                    EvalWire {
+                        label,
+                        flipped: false,
+                    },
+                );
             } else {
                 //FREE XOR, need to compute the output zero label
-                self.evaluate_xor_gate(zero_label_map, gate);
+                self.evaluate_xor_gate(zero_label_map, gate)?;
             }
         }
-        garbled_vec
+        Ok(garbled_tables)
     }
 
     //  Assume party 1 as the evaluator
@@ -289,76 +813,111 @@ impl GarbledCircuit {
     pub fn evaluate(
         &mut self,
         circuit: &Sha256Circuit,
-        garbled_gates: &mut VecDeque<GarbleAnd>,
+        garbled_gates: &GarbledTables,
+        evaluate_label_map: &mut HashMap<usize, WireLabel>,
+    ) -> io::Result<()> {
+        match garbled_gates {
+            GarbledTables::HalfGates(tables) => {
+                self.evaluate_with::<HalfGatesScheme>(circuit, tables, evaluate_label_map)
+            }
+            GarbledTables::ThreeHalves(tables) => {
+                self.evaluate_with::<ThreeHalvesScheme>(circuit, tables, evaluate_label_map)
+            }
+            GarbledTables::PointAndPermute(tables) => {
+                self.evaluate_with::<PointAndPermuteScheme>(circuit, tables, evaluate_label_map)
+            }
+            GarbledTables::Grr3(tables) => {
+                self.evaluate_with::<Grr3Scheme>(circuit, tables, evaluate_label_map)
+            }
+        }
+    }
+
+    /// Evaluate the circuit with AND tables of scheme `S`, in gate order.
+    pub fn evaluate_with<S: GarblingScheme>(
+        &mut self,
+        circuit: &Sha256Circuit,
+        garbled_gates: &[S::Table],
         evaluate_label_map: &mut HashMap<usize, WireLabel>,
-    ) {
+    ) -> io::Result<()> {
+        let mut garbled_gates = garbled_gates.iter();
         for gate in &circuit.extra_gates {
-            if gate.gate_type {
-                //AND
-                //a random evaluated label for input wrie 0,1
-                match evaluate_label_map.get(&gate.input0) {
-                    Some(eval_wire) => {
-                        let wa: WireLabel = *eval_wire;
-                        match evaluate_label_map.get(&gate.input1) {
-                            Some(eval_wire1) => {
-                                let wb: WireLabel = *eval_wire1;
-                                //decrypt garbled table from here
-                                let s_a: bool = wa.check_lsb(); //input wire 0 permutation bit
-                                let s_b: bool = wb.check_lsb(); //input wire 1 permutation bit
-                                                                //step-0: First half gate
-                                if let Some(garbled) = garbled_gates.pop_front() {
-                                    let t_g: WireLabel = garbled.t_g;
-                                    let t_e: WireLabel = garbled.t_e;
-                                    //Step 1: Calculate W_G
-                                    let j = self.next_index() as u64;
-                                    let j_prime = self.next_index() as u64;
-                                    let mut wg: WireLabel = self.prf_update(&wa, j);
-                                    if s_a {
-                                        wg ^= t_g;
-                                    }
-                                    // Step 2: Second half gate
-                                    let mut we: WireLabel = self.prf_update(&wb, j_prime);
-                                    if s_b {
-                                        we ^= wa ^ t_e;
-                                    }
-                                    evaluate_label_map.insert(gate.output, wg ^ we);
-                                }
-                            }
-                            None => {
-                                println!(
-                                    "Key {} not found when fetch  AND gate input1",
-                                    gate.input1
-                                );
-                            }
-                        }
-                    }
-                    None => {
-                        println!("Key {} not found when fetch AND gate input0", gate.input0);
-                    }
-                }
+            //a random evaluated label for input wrie 0,1
+            let wa: WireLabel = *input_wire(evaluate_label_map, gate.input0, "AND or XOR")?;
+            let wb: WireLabel = *input_wire(evaluate_label_map, gate.input1, "AND or XOR")?;
+            let label = if gate.gate_type {
+                //AND, decrypt garbled table from here
+                let garbled = garbled_gates.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Fewer garbled tables than AND gates",
+                    )
+                })?;
+                S::evaluate_and(self, wa, wb, garbled)
             } else {
                 //FREE XOR, need to evaluate the output label
-                match evaluate_label_map.get(&gate.input0) {
-                    Some(eval_wire) => {
-                        let mut new_label = *eval_wire;
-                        match evaluate_label_map.get(&gate.input1) {
-                            Some(eval_wire1) => {
-                                new_label ^= *eval_wire1;
-                                evaluate_label_map.insert(gate.output, new_label);
-                            }
-                            None => {
-                                println!(
-                                    "Key {} not found when fetch XOR gate input1",
-                                    gate.input1
-                                );
-                            }
-                        }
-                    }
-                    None => {
-                        println!("Key {} not found when fetch XOR gate input0", gate.input0);
-                    }
-                }
-            }
+                wa ^ wb
+            };
+            evaluate_label_map.insert(gate.output, label);
         }
+        Ok(())
+    }
+}
+
+//A gate input without a label means the circuit or the input labels are malformed
+fn input_wire<'a, T>(map: &'a HashMap<usize, T>, wire: usize, gate: &str) -> io::Result<&'a T> {
+    map.get(&wire).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Input wire {} of an {} gate has no label", wire, gate),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //σ(xL ‖ xR) = (xL ⊕ xR) ‖ xL on the two 64-bit halves, written independently of tccr_hash
+    fn sigma(x: u128) -> u128 {
+        let (left, right) = ((x >> 64) as u64, x as u64);
+        (u128::from(left ^ right) << 64) | u128::from(left)
+    }
+
+    #[test]
+    fn tccr_hash_is_aes_of_sigma_and_tweak() {
+        let aes = Aes128::new(GenericArray::from_slice(&FIXED_AES_KEY));
+        let gc = GarbledCircuit::new();
+        let mut rng = rand::thread_rng();
+        for _ in 0..64 {
+            let x: u128 = rng.gen();
+            let tweak: u64 = rng.gen();
+            //labels are big endian here, the tweak fills the last 8 bytes in little endian
+            let input = sigma(x) ^ u128::from(u64::from_be_bytes(tweak.to_le_bytes()));
+            let mut block = GenericArray::from(input.to_be_bytes());
+            aes.encrypt_block(&mut block);
+            let expected = u128::from_be_bytes(block.into()) ^ sigma(x);
+
+            let hashed = gc.tccr_hash(&WireLabel::from_data(x.to_be_bytes()), tweak);
+            assert_eq!(hashed, WireLabel::from_data(expected.to_be_bytes()));
+        }
+    }
+
+    #[test]
+    fn missing_input_labels_are_an_error() {
+        let mut circuit = Sha256Circuit::new().unwrap();
+        circuit.update_extra_circuit(&[]);
+        let mut garbler = GarbledCircuit::new();
+        let err = garbler
+            .garble_circuit(&circuit, &mut HashMap::new())
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut evaluator = GarbledCircuit::new();
+        let tables = GarbledTables::HalfGates(Vec::new());
+        let err = evaluator
+            .evaluate(&circuit, &tables, &mut HashMap::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 }
@@ -0,0 +1,149 @@
+use crate::circuit::HashFunction;
+use crate::circuit::OUTPUT_BITS_LEN;
+use crate::circuit::SINGLE_BLOCK_BITS_LEN;
+use crate::utils::convert_bytes2_bits;
+use crate::utils::get_padded_bits;
+
+/// Block size of SHA-256 in bytes, keys are padded (or hashed) to it
+pub const HMAC_BLOCK_BYTES: usize = SINGLE_BLOCK_BITS_LEN / 8;
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5c;
+
+/// Where one bit of a message hashed inside the garbled circuit comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageBit {
+    Public(bool),
+    Shared { input: usize, flip: bool }, //input bit of P0 XOR the same input bit of P1, XOR flip
+    Private { party: usize, input: usize }, //input bit of a single party
+    Hashed { hash: usize, bit: usize, flip: bool }, //bit of the digest of an earlier hash, XOR flip
+}
+
+/// The message authenticated by HMAC, as seen by one party.
+pub enum HmacMessage {
+    Public(Vec<u8>),
+    Own(Vec<u8>), //known to this party only
+    Peer(usize),  //known to the other party only, its length in bytes is public
+}
+
+/// The message of HMAC from both parties' point of view.
+pub enum HmacInput {
+    Public(Vec<u8>),
+    Private { owner: usize, len: usize },
+}
+
+impl HmacInput {
+    /// `role` is the party holding `message`.
+    pub fn new(role: usize, message: &HmacMessage) -> Self {
+        match message {
+            HmacMessage::Public(data) => HmacInput::Public(data.clone()),
+            HmacMessage::Own(data) => HmacInput::Private {
+                owner: role,
+                len: data.len(),
+            },
+            HmacMessage::Peer(len) => HmacInput::Private {
+                owner: 1 - role,
+                len: *len,
+            },
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            HmacInput::Public(data) => data.len(),
+            HmacInput::Private { len, .. } => *len,
+        }
+    }
+}
+
+//Append the SHA-256 padding of a `bytes_len` bytes message
+fn pad(mut bits: Vec<MessageBit>, bytes_len: usize) -> Vec<MessageBit> {
+    let padded_bits = get_padded_bits(bytes_len, HashFunction::Sha256);
+    bits.extend(
+        padded_bits[bytes_len * 8..]
+            .iter()
+            .map(|&b| MessageBit::Public(b)),
+    );
+    bits
+}
+
+/// The padded messages of the chained hashes computing `HMAC(k0 XOR k1, m)`
+/// ([RFC 2104](https://www.rfc-editor.org/rfc/rfc2104)) for a key shared in `key_len` bytes.
+/// The input bits of each party are its key share, followed by `m` for the party holding it.
+///
+/// A key longer than a block is hashed first, then `H((K ⊕ ipad) ‖ m)` and
+/// `H((K ⊕ opad) ‖ inner digest)` follow. The digest of the last hash is the MAC.
+pub fn hmac_hashes(key_len: usize, message: &HmacInput) -> Vec<Vec<MessageBit>> {
+    let mut hashes: Vec<Vec<MessageBit>> = Vec::new();
+    let key_bits_cnt: usize = key_len * 8;
+    if key_len > HMAC_BLOCK_BYTES {
+        let key_hash: Vec<MessageBit> = (0..key_bits_cnt)
+            .map(|input| MessageBit::Shared { input, flip: false })
+            .collect();
+        hashes.push(pad(key_hash, key_len));
+    }
+    //Bit i of the block-sized key XOR the pad byte
+    let key_block = |pad_byte: u8| -> Vec<MessageBit> {
+        (0..SINGLE_BLOCK_BITS_LEN)
+            .map(|i| {
+                let flip = (pad_byte >> (7 - i % 8)) & 1 == 1;
+                if key_len > HMAC_BLOCK_BYTES {
+                    if i < OUTPUT_BITS_LEN {
+                        MessageBit::Hashed {
+                            hash: 0,
+                            bit: i,
+                            flip,
+                        }
+                    } else {
+                        MessageBit::Public(flip)
+                    }
+                } else if i < key_bits_cnt {
+                    MessageBit::Shared { input: i, flip }
+                } else {
+                    MessageBit::Public(flip)
+                }
+            })
+            .collect()
+    };
+
+    let mut inner = key_block(IPAD);
+    match message {
+        HmacInput::Public(data) => {
+            inner.extend(
+                convert_bytes2_bits(data)
+                    .into_iter()
+                    .map(MessageBit::Public),
+            );
+        }
+        HmacInput::Private { owner, len } => {
+            inner.extend((0..len * 8).map(|i| MessageBit::Private {
+                party: *owner,
+                input: key_bits_cnt + i,
+            }));
+        }
+    }
+    let inner_hash: usize = hashes.len();
+    hashes.push(pad(inner, HMAC_BLOCK_BYTES + message.len()));
+
+    let mut outer = key_block(OPAD);
+    outer.extend((0..OUTPUT_BITS_LEN).map(|bit| MessageBit::Hashed {
+        hash: inner_hash,
+        bit,
+        flip: false,
+    }));
+    hashes.push(pad(outer, HMAC_BLOCK_BYTES + OUTPUT_BITS_LEN / 8));
+    hashes
+}
+
+/// Number of input bits of `party` in the hashes.
+pub fn input_bits_cnt(hashes: &[Vec<MessageBit>], party: usize) -> usize {
+    hashes
+        .iter()
+        .flatten()
+        .filter_map(|bit| match *bit {
+            MessageBit::Shared { input, .. } => Some(input + 1),
+            MessageBit::Private { party: p, input } if p == party => Some(input + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,240 @@
+use crate::circuit::HashFunction;
+use crate::circuit::OutputWire;
+use crate::circuit::XorAndGate;
+
+use std::collections::HashMap;
+
+/// Number of bits of a length share, enough for any length up to `max_len` bytes.
+pub fn length_bits_cnt(max_len: usize) -> usize {
+    (usize::BITS - max_len.leading_zeros()) as usize
+}
+
+/// Gates padding a message of secret length ℓ ≤ `max_len` bytes inside the garbled circuit.
+///
+/// Both parties input the message zero-extended to `max_len` bytes, followed by their share of
+/// ℓ = ℓ0 XOR ℓ1 (MSB first). Every block of the longest padded message is garbled: its bits are
+/// computed from the comparisons [ℓ ≥ x] against public positions x, so message bits past ℓ are
+/// cleared, the 0x80 byte lands at ℓ and the length field is written into the block that ends
+/// the padded message. After each block, the chaining value is multiplexed into an accumulator
+/// when that block is the final one, so the output does not depend on where the message ended.
+/// No block is final when ℓ exceeds `max_len`: the accumulator keeps its initial zero, and the
+/// parties decoding the digest abort on it.
+#[derive(Debug, Clone)]
+pub struct HiddenLength {
+    max_len: usize,
+    length_bits_cnt: usize,
+    extra_input_wire: usize,
+    hash_function: HashFunction,
+}
+
+//Gate list with fresh output wires
+struct GateBuilder {
+    gates: Vec<XorAndGate>,
+    next_wire: usize,
+    at_least: HashMap<usize, usize>, //x -> wire of [ℓ >= x]
+}
+
+impl GateBuilder {
+    fn push(&mut self, input0: usize, input1: usize, output: usize, gate_type: bool) {
+        self.gates.push(XorAndGate {
+            input0,
+            input1,
+            output,
+            input0_flipped: false,
+            input1_flipped: false,
+            gate_type,
+        });
+    }
+
+    fn gate(&mut self, input0: usize, input1: usize, gate_type: bool) -> usize {
+        let output: usize = self.next_wire;
+        self.next_wire += 1;
+        self.push(input0, input1, output, gate_type);
+        output
+    }
+
+    fn xor(&mut self, input0: usize, input1: usize) -> usize {
+        self.gate(input0, input1, false)
+    }
+
+    fn and(&mut self, input0: usize, input1: usize) -> usize {
+        self.gate(input0, input1, true)
+    }
+
+    //a OR b = a XOR b XOR (a AND b)
+    fn or(&mut self, input0: usize, input1: usize) -> usize {
+        let both: usize = self.and(input0, input1);
+        let either: usize = self.xor(input0, input1);
+        self.xor(both, either)
+    }
+}
+
+impl HiddenLength {
+    pub fn new(extra_input_wire: usize, max_len: usize, hash_function: HashFunction) -> Self {
+        HiddenLength {
+            max_len,
+            length_bits_cnt: length_bits_cnt(max_len),
+            extra_input_wire,
+            hash_function,
+        }
+    }
+
+    pub fn length_bits_cnt(&self) -> usize {
+        self.length_bits_cnt
+    }
+
+    //Wires following the message input wires (two per message bit)
+    fn base_wire(&self) -> usize {
+        self.extra_input_wire + 2 * self.max_len * 8
+    }
+
+    /// Input wire of bit `k` (MSB first) of `party`'s length share.
+    pub fn length_wire(&self, k: usize, party: usize) -> usize {
+        self.base_wire() + 2 * k + party
+    }
+
+    /// Public wire holding 0, and 1 right after it.
+    pub fn zero_wire(&self) -> usize {
+        self.base_wire() + 2 * self.length_bits_cnt
+    }
+
+    pub fn one_wire(&self) -> usize {
+        self.zero_wire() + 1
+    }
+
+    /// Accumulator bit `k` entering a block, the chaining value selected so far (0 before the
+    /// first block).
+    pub fn accumulator_wire(&self, k: usize) -> usize {
+        self.one_wire() + 1 + k
+    }
+
+    /// Accumulator bit `k` leaving a block.
+    pub fn selected_wire(&self, k: usize) -> usize {
+        self.accumulator_wire(self.hash_function.state_bits_len()) + k
+    }
+
+    /// The gates of `block`: those computing its message wires, to run before the compression
+    /// function, and those selecting its chaining value (`output_wires`), to run after it.
+    pub fn block_gates(
+        &self,
+        block: usize,
+        output_wires: &[OutputWire],
+    ) -> (Vec<XorAndGate>, Vec<XorAndGate>) {
+        let block_bits_len: usize = self.hash_function.block_bits_len();
+        let length_field_bits_len: usize = self.hash_function.length_field_bits_len();
+        let mut builder = GateBuilder {
+            gates: Vec::new(),
+            next_wire: self.selected_wire(self.hash_function.state_bits_len()),
+            at_least: HashMap::new(),
+        };
+        //ℓ bit of weight 2^w
+        let length: Vec<usize> = (0..self.length_bits_cnt)
+            .map(|w| {
+                let k: usize = self.length_bits_cnt - 1 - w;
+                builder.xor(self.length_wire(k, 0), self.length_wire(k, 1))
+            })
+            .collect();
+
+        //The padded message ends in this block iff B·block - F <= ℓ < B·block + B - F,
+        //for blocks of B bytes and a length field of F bytes (64 and 8 for SHA-256)
+        let block_bytes: usize = block_bits_len / 8;
+        let field_bytes: usize = length_field_bits_len / 8;
+        let start: usize = block * block_bytes;
+        let lower = self.at_least(&mut builder, &length, start.saturating_sub(field_bytes));
+        let upper = self.at_least(&mut builder, &length, start + block_bytes - field_bytes);
+        let ends_here: usize = builder.xor(lower, upper);
+        let too_long = self.at_least(&mut builder, &length, self.max_len + 1);
+        let within_bound: usize = builder.xor(too_long, self.one_wire());
+        let is_final: usize = builder.and(ends_here, within_bound);
+
+        for j in 0..block_bits_len {
+            let (p, i) = (start + j / 8, j % 8);
+            let mut terms: Vec<usize> = Vec::new();
+            if p < self.max_len {
+                //message bit, kept when p < ℓ
+                let s: usize = 8 * p + i;
+                let bit = builder.xor(
+                    self.extra_input_wire + 2 * s,
+                    self.extra_input_wire + 2 * s + 1,
+                );
+                let kept = self.at_least(&mut builder, &length, p + 1);
+                terms.push(builder.and(bit, kept));
+            }
+            if i == 0 && p <= self.max_len {
+                //the 0x80 byte, when p == ℓ
+                let here = self.at_least(&mut builder, &length, p);
+                let past = self.at_least(&mut builder, &length, p + 1);
+                terms.push(builder.xor(here, past));
+            }
+            if let Some(q) = (j + length_field_bits_len).checked_sub(block_bits_len) {
+                //8·ℓ big-endian: field bit q (MSB first) is the ℓ bit of weight 2^(F·8 - 4 - q)
+                let top: usize = length_field_bits_len - 4;
+                if let Some(&bit) = top.checked_sub(q).and_then(|w| length.get(w)) {
+                    terms.push(builder.and(is_final, bit));
+                }
+            }
+
+            //the terms are exclusive, XOR them into the message wire
+            let circuit_wire_id: usize = block_bits_len - 1 - j;
+            let zero: usize = self.zero_wire();
+            let (last, rest) = terms.split_last().unwrap_or((&zero, &[]));
+            let sum: usize = rest.iter().fold(zero, |sum, &term| builder.xor(sum, term));
+            builder.push(sum, *last, circuit_wire_id, false);
+        }
+        let message_gates = std::mem::take(&mut builder.gates);
+
+        for (k, output_wire) in output_wires.iter().enumerate() {
+            let (real_id, flipped) = if output_wire.should_trace {
+                (output_wire.input_id, true) //A not gate is applied here
+            } else {
+                (output_wire.id, false)
+            };
+            let selected: usize = builder.next_wire;
+            builder.next_wire += 1;
+            builder.gates.push(XorAndGate {
+                input0: is_final,
+                input1: real_id,
+                output: selected,
+                input0_flipped: false,
+                input1_flipped: flipped,
+                gate_type: true,
+            });
+            builder.push(
+                self.accumulator_wire(k),
+                selected,
+                self.selected_wire(k),
+                false,
+            );
+        }
+        (message_gates, builder.gates)
+    }
+
+    //Wire of [ℓ >= x], comparing ℓ > x - 1 from the least significant bit up
+    fn at_least(&self, builder: &mut GateBuilder, length: &[usize], x: usize) -> usize {
+        if x == 0 {
+            return self.one_wire();
+        }
+        if let Some(&wire) = builder.at_least.get(&x) {
+            return wire;
+        }
+        let bound: usize = x - 1;
+        if bound >> self.length_bits_cnt != 0 {
+            return self.zero_wire();
+        }
+        //[ℓ mod 2^(w+1) > bound mod 2^(w+1)], None while it is constant 0
+        let mut greater: Option<usize> = None;
+        for (w, &bit) in length.iter().enumerate() {
+            greater = if (bound >> w) & 1 == 0 {
+                Some(match greater {
+                    None => bit,
+                    Some(lower) => builder.or(bit, lower),
+                })
+            } else {
+                greater.map(|lower| builder.and(bit, lower))
+            };
+        }
+        let wire: usize = greater.unwrap_or(self.zero_wire());
+        builder.at_least.insert(x, wire);
+        wire
+    }
+}
@@ -0,0 +1,14 @@
+pub mod auth_gc;
+pub mod channel;
+pub mod circuit;
+pub mod cli;
+pub mod cut_and_choose;
+pub mod gc;
+pub mod hmac;
+pub mod length_hiding;
+pub mod ot;
+pub mod ot_ext;
+pub mod party;
+pub mod sha512;
+pub mod silent_ot;
+pub mod utils;
@@ -0,0 +1,217 @@
+use crate::gc::WireLabel;
+use crate::gc::LABEL_SECURITY_LEVEL;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// 1-out-of-2 base oblivious transfer, the "simplest OT" protocol of Chou and Orlandi
+/// (LATINCRYPT 2015) instantiated over the Ristretto255 prime-order group.
+///
+/// The protocol has three flows:
+/// 1. the sender picks `a` and publishes `A = aG` (`OtSetup`);
+/// 2. for every choice bit `c` the receiver picks `b` and answers `B = bG + cA` (`OtChoices`);
+/// 3. the sender encrypts `m0` under `H(aB)` and `m1` under `H(a(B - A))` (`OtTransfer`),
+///    the receiver can only derive `H(bA)`, i.e. the key of the message it chose.
+#[derive(Debug)]
+pub struct OtSender {
+    secret: Scalar,
+    point: RistrettoPoint,
+}
+
+/// First flow: the sender's public point A.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OtSetup {
+    pub point: CompressedRistretto,
+}
+
+/// Second flow: one receiver point B per transferred message pair.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OtChoices {
+    pub points: Vec<CompressedRistretto>,
+}
+
+/// Third flow: the pair of encrypted messages for every OT instance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OtTransfer {
+    pub ciphertexts: Vec<(WireLabel, WireLabel)>,
+}
+
+#[derive(Debug)]
+pub struct OtReceiver {
+    choice_bits: Vec<bool>,
+    keys: Vec<WireLabel>,
+}
+
+fn invalid_point(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("OT: invalid group element in {}", what),
+    )
+}
+
+//Derive a 128-bit pad from the shared group element, bound to the transcript and the OT index
+fn derive_key(
+    setup: &CompressedRistretto,
+    choice: &CompressedRistretto,
+    shared: &RistrettoPoint,
+    index: usize,
+) -> WireLabel {
+    let mut hasher = Sha256::new();
+    hasher.update(b"sha256_gc/base-ot");
+    hasher.update(setup.as_bytes());
+    hasher.update(choice.as_bytes());
+    hasher.update(shared.compress().as_bytes());
+    hasher.update((index as u64).to_le_bytes());
+    let digest = hasher.finalize();
+
+    let mut key = [0u8; LABEL_SECURITY_LEVEL];
+    key.copy_from_slice(&digest[..LABEL_SECURITY_LEVEL]);
+    WireLabel::from_data(key)
+}
+
+impl OtSender {
+    pub fn new() -> (Self, OtSetup) {
+        let mut rng = rand::thread_rng();
+        let secret = Scalar::random(&mut rng);
+        let point = RISTRETTO_BASEPOINT_POINT * secret;
+        (
+            OtSender { secret, point },
+            OtSetup {
+                point: point.compress(),
+            },
+        )
+    }
+
+    /// Encrypt every message pair under the two keys implied by the receiver's point.
+    pub fn transfer(
+        &self,
+        choices: &OtChoices,
+        messages: &[(WireLabel, WireLabel)],
+    ) -> io::Result<OtTransfer> {
+        if choices.points.len() != messages.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "OT: receiver asked for {} messages, sender holds {}",
+                    choices.points.len(),
+                    messages.len()
+                ),
+            ));
+        }
+
+        let setup = self.point.compress();
+        let mut ciphertexts: Vec<(WireLabel, WireLabel)> = Vec::with_capacity(messages.len());
+        for (i, (compressed, (m0, m1))) in choices.points.iter().zip(messages).enumerate() {
+            let b_point = compressed
+                .decompress()
+                .ok_or_else(|| invalid_point("receiver choices"))?;
+            let k0 = derive_key(&setup, compressed, &(b_point * self.secret), i);
+            let k1 = derive_key(
+                &setup,
+                compressed,
+                &((b_point - self.point) * self.secret),
+                i,
+            );
+            ciphertexts.push((*m0 ^ k0, *m1 ^ k1));
+        }
+        Ok(OtTransfer { ciphertexts })
+    }
+}
+
+impl OtReceiver {
+    pub fn new(setup: &OtSetup, choice_bits: &[bool]) -> io::Result<(Self, OtChoices)> {
+        let a_point = setup
+            .point
+            .decompress()
+            .ok_or_else(|| invalid_point("sender setup"))?;
+
+        let mut rng = rand::thread_rng();
+        let mut points: Vec<CompressedRistretto> = Vec::with_capacity(choice_bits.len());
+        let mut keys: Vec<WireLabel> = Vec::with_capacity(choice_bits.len());
+        for (i, &bit) in choice_bits.iter().enumerate() {
+            let b = Scalar::random(&mut rng);
+            let mut b_point = RISTRETTO_BASEPOINT_POINT * b;
+            if bit {
+                b_point += a_point;
+            }
+            let compressed = b_point.compress();
+            keys.push(derive_key(&setup.point, &compressed, &(a_point * b), i));
+            points.push(compressed);
+        }
+
+        Ok((
+            OtReceiver {
+                choice_bits: choice_bits.to_vec(),
+                keys,
+            },
+            OtChoices { points },
+        ))
+    }
+
+    /// Decrypt the chosen message of every pair.
+    pub fn receive(&self, transfer: &OtTransfer) -> io::Result<Vec<WireLabel>> {
+        if transfer.ciphertexts.len() != self.keys.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "OT: expected {} ciphertext pairs, received {}",
+                    self.keys.len(),
+                    transfer.ciphertexts.len()
+                ),
+            ));
+        }
+
+        Ok(transfer
+            .ciphertexts
+            .iter()
+            .zip(self.keys.iter().zip(&self.choice_bits))
+            .map(|((e0, e1), (key, &bit))| if bit { *e1 ^ *key } else { *e0 ^ *key })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn random_pairs(cnt: usize) -> Vec<(WireLabel, WireLabel)> {
+        let mut rng = rand::thread_rng();
+        (0..cnt)
+            .map(|_| {
+                (
+                    WireLabel::from_data(rng.gen()),
+                    WireLabel::from_data(rng.gen()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn receiver_gets_the_chosen_messages() {
+        let mut rng = rand::thread_rng();
+        let messages = random_pairs(64);
+        let choice_bits: Vec<bool> = (0..messages.len()).map(|_| rng.gen()).collect();
+
+        let (sender, setup) = OtSender::new();
+        let (receiver, choices) = OtReceiver::new(&setup, &choice_bits).unwrap();
+        let transfer = sender.transfer(&choices, &messages).unwrap();
+        let received = receiver.receive(&transfer).unwrap();
+
+        for ((label, (m0, m1)), &bit) in received.iter().zip(&messages).zip(&choice_bits) {
+            assert_eq!(label, if bit { m1 } else { m0 });
+            assert_ne!(label, if bit { m0 } else { m1 });
+        }
+    }
+
+    #[test]
+    fn sender_rejects_a_wrong_number_of_choices() {
+        let (sender, setup) = OtSender::new();
+        let (_, choices) = OtReceiver::new(&setup, &[false, true]).unwrap();
+        assert!(sender.transfer(&choices, &random_pairs(3)).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+use crate::gc::WireLabel;
+use crate::gc::LABEL_SECURITY_LEVEL;
+use crate::ot::OtChoices;
+use crate::ot::OtReceiver;
+use crate::ot::OtSender;
+use crate::ot::OtSetup;
+use crate::ot::OtTransfer;
+
+use aes::cipher::generic_array::GenericArray;
+use aes::{Aes128, BlockEncrypt, NewBlockCipher};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// Number of base OTs, one per bit of the computational security parameter.
+pub const BASE_OT_CNT: usize = LABEL_SECURITY_LEVEL * 8;
+/// Statistical security parameter of the KOS consistency check.
+pub const STATISTICAL_SECURITY: usize = 40;
+/// Extra random OTs the receiver appends, they are sacrificed to hide its choices in the check.
+pub const KOS_PADDING_CNT: usize = BASE_OT_CNT + STATISTICAL_SECURITY;
+
+/// IKNP OT extension (Ishai–Kilian–Nissim–Petrank, CRYPTO 2003).
+///
+/// `BASE_OT_CNT` public-key OTs are run with swapped roles (the extension receiver acts
+/// as base-OT sender of PRG seeds), afterwards any number of OTs cost only symmetric-key
+/// operations plus `BASE_OT_CNT` bits of communication per transferred pair:
+/// 1. receiver -> sender: `OtSetup` of the base OTs;
+/// 2. sender -> receiver: `OtChoices`, the base-OT choice bits are the sender's secret `s`;
+/// 3. receiver -> sender: `IknpExtend`, the seeds (through base OT) and the matrix `U`;
+/// 4. sender -> receiver: `OtTransfer`, every pair masked by `H(j, q_j)` and `H(j, q_j ^ s)`.
+///
+/// Plain IKNP is only secure against a semi-honest receiver: inconsistent columns of `U`
+/// let a malicious receiver learn bits of `s`, hence of both messages. The extension carries
+/// the KOS15 correlation check (Keller–Orsini–Scholl, CRYPTO 2015): for random challenges
+/// `chi_j` in GF(2^128) the receiver proves `sum chi_j * q_j = sum chi_j * t_j ^ (sum chi_j * r_j) * s`,
+/// and the sender aborts before transferring anything if the equation does not hold.
+/// The challenges are derived from the transcript (Fiat–Shamir), so the flows stay unchanged.
+#[derive(Debug)]
+pub struct IknpSender {
+    delta: WireLabel, //the secret s, packed as a 128-bit row
+    base: OtReceiver,
+}
+
+#[derive(Debug)]
+pub struct IknpReceiver {
+    choice_bits: Vec<bool>,
+    base: OtSender,
+    seeds: Vec<(WireLabel, WireLabel)>,
+    t_rows: Vec<WireLabel>,
+}
+
+/// Third flow: the base-OT transfer of the seeds together with the columns of `U`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IknpExtend {
+    pub base_transfer: OtTransfer,
+    pub columns: Vec<Vec<u8>>, //BASE_OT_CNT columns, one bit per extended OT (padding included)
+
+    pub check_x: WireLabel, //KOS check: sum chi_j * r_j
+    pub check_t: WireLabel, //KOS check: sum chi_j * t_j
+}
+
+pub fn get_bit(bytes: &[u8], idx: usize) -> bool {
+    (bytes[idx / 8] >> (idx % 8)) & 1 == 1
+}
+
+fn set_bit(bytes: &mut [u8], idx: usize) {
+    bytes[idx / 8] |= 1 << (idx % 8);
+}
+
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+    for (i, &bit) in bits.iter().enumerate() {
+        if bit {
+            set_bit(&mut bytes, i);
+        }
+    }
+    bytes
+}
+
+pub fn label_bit(label: &WireLabel, idx: usize) -> bool {
+    (label[idx / 8] >> (idx % 8)) & 1 == 1
+}
+
+//Expand a 128-bit seed into `bits_cnt` pseudorandom bits, AES-128 in counter mode
+pub fn prg_expand(seed: &WireLabel, bits_cnt: usize) -> Vec<u8> {
+    let cipher = Aes128::new(&seed.to_generic_array());
+    let bytes_cnt = bits_cnt.div_ceil(8);
+    let mut output: Vec<u8> = Vec::with_capacity(bytes_cnt + LABEL_SECURITY_LEVEL);
+    let mut counter: u128 = 0;
+    while output.len() < bytes_cnt {
+        let mut block = GenericArray::from(counter.to_le_bytes());
+        cipher.encrypt_block(&mut block);
+        output.extend_from_slice(&block);
+        counter += 1;
+    }
+    output.truncate(bytes_cnt);
+    output
+}
+
+//Turn BASE_OT_CNT columns of `rows_cnt` bits into `rows_cnt` rows of BASE_OT_CNT bits
+pub fn transpose(columns: &[Vec<u8>], rows_cnt: usize) -> Vec<WireLabel> {
+    let mut rows = vec![[0u8; LABEL_SECURITY_LEVEL]; rows_cnt];
+    for (i, column) in columns.iter().enumerate() {
+        for (j, row) in rows.iter_mut().enumerate() {
+            if get_bit(column, j) {
+                set_bit(row, i);
+            }
+        }
+    }
+    rows.into_iter().map(WireLabel::from_data).collect()
+}
+
+//Correlation-robust hash used to break the correlation q_j = t_j ^ r_j * s
+pub fn cr_hash(index: usize, row: &WireLabel) -> WireLabel {
+    let mut hasher = Sha256::new();
+    hasher.update(b"sha256_gc/iknp");
+    hasher.update((index as u64).to_le_bytes());
+    hasher.update(row.to_generic_array());
+    let digest = hasher.finalize();
+
+    let mut pad = [0u8; LABEL_SECURITY_LEVEL];
+    pad.copy_from_slice(&digest[..LABEL_SECURITY_LEVEL]);
+    WireLabel::from_data(pad)
+}
+
+//Carry-less multiplication in GF(2^128), reduced by x^128 + x^7 + x^2 + x + 1
+pub fn gf128_mul(a: &WireLabel, b: &WireLabel) -> WireLabel {
+    let a = u128::from_le_bytes(a.to_generic_array().into());
+    let b = u128::from_le_bytes(b.to_generic_array().into());
+
+    let mut high: u128 = 0;
+    let mut low: u128 = 0;
+    for i in 0..128 {
+        if (b >> i) & 1 == 1 {
+            low ^= a << i;
+            if i > 0 {
+                high ^= a >> (128 - i);
+            }
+        }
+    }
+    //fold the high half twice: x^128 = x^7 + x^2 + x + 1
+    for _ in 0..2 {
+        let spill = high;
+        high = (spill >> 121) ^ (spill >> 126) ^ (spill >> 127);
+        low ^= spill ^ (spill << 1) ^ (spill << 2) ^ (spill << 7);
+    }
+    WireLabel::from_data(low.to_le_bytes())
+}
+
+//Fiat–Shamir challenges chi_j of the KOS check, bound to the receiver's matrix
+fn kos_challenges(columns: &[Vec<u8>], rows_cnt: usize) -> Vec<WireLabel> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"sha256_gc/kos-challenge");
+    for column in columns {
+        hasher.update(column);
+    }
+    let digest = hasher.finalize();
+    let mut seed = [0u8; LABEL_SECURITY_LEVEL];
+    seed.copy_from_slice(&digest[..LABEL_SECURITY_LEVEL]);
+
+    let stream = prg_expand(&WireLabel::from_data(seed), rows_cnt * BASE_OT_CNT);
+    stream
+        .chunks_exact(LABEL_SECURITY_LEVEL)
+        .map(|chunk| {
+            let mut chi = [0u8; LABEL_SECURITY_LEVEL];
+            chi.copy_from_slice(chunk);
+            WireLabel::from_data(chi)
+        })
+        .collect()
+}
+
+fn xor_bytes(dst: &mut [u8], src: &[u8]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= *s;
+    }
+}
+
+impl IknpSender {
+    pub fn new(setup: &OtSetup) -> io::Result<(Self, OtChoices)> {
+        let mut rng = rand::thread_rng();
+        let mut delta = [0u8; LABEL_SECURITY_LEVEL];
+        rng.fill(&mut delta);
+        let delta = WireLabel::from_data(delta);
+
+        let delta_bits: Vec<bool> = (0..BASE_OT_CNT).map(|i| label_bit(&delta, i)).collect();
+        let (base, choices) = OtReceiver::new(setup, &delta_bits)?;
+        Ok((IknpSender { delta, base }, choices))
+    }
+
+    //q_i = G(k_i^{s_i}) ^ s_i * u_i, i.e. every row satisfies q_j = t_j ^ r_j * s
+    fn q_rows(&self, extend: &IknpExtend, rows_cnt: usize) -> io::Result<Vec<WireLabel>> {
+        if extend.columns.len() != BASE_OT_CNT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "OT extension: expected {} columns, received {}",
+                    BASE_OT_CNT,
+                    extend.columns.len()
+                ),
+            ));
+        }
+        let seeds = self.base.receive(&extend.base_transfer)?;
+
+        let mut q_columns: Vec<Vec<u8>> = Vec::with_capacity(BASE_OT_CNT);
+        for (i, seed) in seeds.iter().enumerate() {
+            let u_column = &extend.columns[i];
+            if u_column.len() != rows_cnt.div_ceil(8) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("OT extension: column {} has a wrong length", i),
+                ));
+            }
+            let mut q_column = prg_expand(seed, rows_cnt);
+            if label_bit(&self.delta, i) {
+                xor_bytes(&mut q_column, u_column);
+            }
+            q_columns.push(q_column);
+        }
+        Ok(transpose(&q_columns, rows_cnt))
+    }
+
+    //KOS15 check: sum chi_j * q_j must equal check_t ^ check_x * s
+    fn check_consistency(&self, extend: &IknpExtend, q_rows: &[WireLabel]) -> io::Result<()> {
+        let challenges = kos_challenges(&extend.columns, q_rows.len());
+        let mut q_sum = WireLabel::zero();
+        for (q, chi) in q_rows.iter().zip(&challenges) {
+            q_sum ^= gf128_mul(q, chi);
+        }
+        if q_sum != extend.check_t ^ gf128_mul(&extend.check_x, &self.delta) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "OT extension: KOS consistency check failed, the receiver's matrix is malformed",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn delta(&self) -> WireLabel {
+        self.delta
+    }
+
+    /// The raw correlated OTs (q_j, q_j ^ s) of the extension, after the consistency check.
+    /// The receiver holds t_j = q_j ^ r_j * s, i.e. a correlated OT with global correlation s.
+    pub fn correlated(&self, extend: &IknpExtend, rows_cnt: usize) -> io::Result<Vec<WireLabel>> {
+        let mut q_rows = self.q_rows(extend, rows_cnt + KOS_PADDING_CNT)?;
+        self.check_consistency(extend, &q_rows)?;
+        q_rows.truncate(rows_cnt);
+        Ok(q_rows)
+    }
+
+    /// Mask every message pair, the receiver can only unmask the message of its choice bit.
+    /// Aborts without sending anything if the receiver's matrix fails the consistency check.
+    pub fn transfer(
+        &self,
+        extend: &IknpExtend,
+        messages: &[(WireLabel, WireLabel)],
+    ) -> io::Result<OtTransfer> {
+        let q_rows = self.correlated(extend, messages.len())?;
+
+        let ciphertexts = q_rows
+            .iter()
+            .zip(messages)
+            .enumerate()
+            .map(|(j, (q, (m0, m1)))| (*m0 ^ cr_hash(j, q), *m1 ^ cr_hash(j, &(*q ^ self.delta))))
+            .collect();
+        Ok(OtTransfer { ciphertexts })
+    }
+}
+
+impl IknpReceiver {
+    pub fn new(choice_bits: &[bool]) -> (Self, OtSetup) {
+        let mut rng = rand::thread_rng();
+        let seeds: Vec<(WireLabel, WireLabel)> = (0..BASE_OT_CNT)
+            .map(|_| {
+                let mut k0 = [0u8; LABEL_SECURITY_LEVEL];
+                let mut k1 = [0u8; LABEL_SECURITY_LEVEL];
+                rng.fill(&mut k0);
+                rng.fill(&mut k1);
+                (WireLabel::from_data(k0), WireLabel::from_data(k1))
+            })
+            .collect();
+
+        let (base, setup) = OtSender::new();
+        (
+            IknpReceiver {
+                choice_bits: choice_bits.to_vec(),
+                base,
+                seeds,
+                t_rows: Vec::new(),
+            },
+            setup,
+        )
+    }
+
+    //t_i = G(k_i^0), u_i = t_i ^ G(k_i^1) ^ r, where r is padded with KOS_PADDING_CNT random bits
+    pub fn extend(&mut self, choices: &OtChoices) -> io::Result<IknpExtend> {
+        let base_transfer = self.base.transfer(choices, &self.seeds)?;
+
+        let mut rng = rand::thread_rng();
+        let mut padded_choices = self.choice_bits.clone();
+        padded_choices.extend((0..KOS_PADDING_CNT).map(|_| rng.gen::<bool>()));
+
+        let rows_cnt = padded_choices.len();
+        let packed_choices = pack_bits(&padded_choices);
+        let mut t_columns: Vec<Vec<u8>> = Vec::with_capacity(BASE_OT_CNT);
+        let mut u_columns: Vec<Vec<u8>> = Vec::with_capacity(BASE_OT_CNT);
+        for (k0, k1) in &self.seeds {
+            let t_column = prg_expand(k0, rows_cnt);
+            let mut u_column = prg_expand(k1, rows_cnt);
+            xor_bytes(&mut u_column, &t_column);
+            xor_bytes(&mut u_column, &packed_choices);
+            t_columns.push(t_column);
+            u_columns.push(u_column);
+        }
+        let mut t_rows = transpose(&t_columns, rows_cnt);
+
+        let challenges = kos_challenges(&u_columns, rows_cnt);
+        let mut check_x = WireLabel::zero();
+        let mut check_t = WireLabel::zero();
+        for ((t, chi), &bit) in t_rows.iter().zip(&challenges).zip(&padded_choices) {
+            if bit {
+                check_x ^= *chi;
+            }
+            check_t ^= gf128_mul(t, chi);
+        }
+
+        t_rows.truncate(self.choice_bits.len());
+        self.t_rows = t_rows;
+
+        Ok(IknpExtend {
+            base_transfer,
+            columns: u_columns,
+            check_x,
+            check_t,
+        })
+    }
+
+    /// The receiver's half t_j of the raw correlated OTs, available after `extend`.
+    pub fn correlated(&self) -> &[WireLabel] {
+        &self.t_rows
+    }
+
+    /// Unmask the chosen message of every pair with H(j, t_j).
+    pub fn receive(&self, transfer: &OtTransfer) -> io::Result<Vec<WireLabel>> {
+        if transfer.ciphertexts.len() != self.t_rows.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "OT extension: expected {} ciphertext pairs, received {}",
+                    self.t_rows.len(),
+                    transfer.ciphertexts.len()
+                ),
+            ));
+        }
+
+        Ok(transfer
+            .ciphertexts
+            .iter()
+            .zip(self.t_rows.iter().zip(&self.choice_bits))
+            .enumerate()
+            .map(|(j, ((y0, y1), (t, &bit)))| {
+                let pad = cr_hash(j, t);
+                if bit {
+                    *y1 ^ pad
+                } else {
+                    *y0 ^ pad
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_pairs(cnt: usize) -> Vec<(WireLabel, WireLabel)> {
+        let mut rng = rand::thread_rng();
+        (0..cnt)
+            .map(|_| {
+                (
+                    WireLabel::from_data(rng.gen()),
+                    WireLabel::from_data(rng.gen()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn iknp_delivers_the_chosen_labels() {
+        let mut rng = rand::thread_rng();
+        let messages = random_pairs(600);
+        let choice_bits: Vec<bool> = (0..messages.len()).map(|_| rng.gen()).collect();
+
+        let (mut receiver, setup) = IknpReceiver::new(&choice_bits);
+        let (sender, choices) = IknpSender::new(&setup).unwrap();
+        let extend = receiver.extend(&choices).unwrap();
+        let transfer = sender.transfer(&extend, &messages).unwrap();
+        let received = receiver.receive(&transfer).unwrap();
+
+        assert_eq!(received.len(), messages.len());
+        for ((label, (m0, m1)), &bit) in received.iter().zip(&messages).zip(&choice_bits) {
+            assert_eq!(label, if bit { m1 } else { m0 });
+        }
+    }
+
+    #[test]
+    fn honest_receiver_passes_the_kos_check_for_a_single_ot() {
+        let messages = random_pairs(1);
+        let (mut receiver, setup) = IknpReceiver::new(&[true]);
+        let (sender, choices) = IknpSender::new(&setup).unwrap();
+        let extend = receiver.extend(&choices).unwrap();
+        let transfer = sender.transfer(&extend, &messages).unwrap();
+        assert_eq!(receiver.receive(&transfer).unwrap(), vec![messages[0].1]);
+    }
+
+    //A malicious receiver: like `IknpReceiver::extend`, but row `row` of column `column` of U
+    //uses the flipped choice bit. The challenges and check values are computed from the altered
+    //matrix, as a cheating receiver would
+    fn inconsistent_extend(
+        receiver: &IknpReceiver,
+        choices: &OtChoices,
+        column: usize,
+        row: usize,
+    ) -> IknpExtend {
+        let base_transfer = receiver.base.transfer(choices, &receiver.seeds).unwrap();
+        let mut padded_choices = receiver.choice_bits.clone();
+        padded_choices.resize(receiver.choice_bits.len() + KOS_PADDING_CNT, false);
+        let rows_cnt = padded_choices.len();
+        let packed_choices = pack_bits(&padded_choices);
+
+        let mut t_columns: Vec<Vec<u8>> = Vec::with_capacity(BASE_OT_CNT);
+        let mut u_columns: Vec<Vec<u8>> = Vec::with_capacity(BASE_OT_CNT);
+        for (k0, k1) in &receiver.seeds {
+            let t_column = prg_expand(k0, rows_cnt);
+            let mut u_column = prg_expand(k1, rows_cnt);
+            xor_bytes(&mut u_column, &t_column);
+            xor_bytes(&mut u_column, &packed_choices);
+            t_columns.push(t_column);
+            u_columns.push(u_column);
+        }
+        u_columns[column][row / 8] ^= 1 << (row % 8);
+
+        let t_rows = transpose(&t_columns, rows_cnt);
+        let challenges = kos_challenges(&u_columns, rows_cnt);
+        let mut check_x = WireLabel::zero();
+        let mut check_t = WireLabel::zero();
+        for ((t, chi), &bit) in t_rows.iter().zip(&challenges).zip(&padded_choices) {
+            if bit {
+                check_x ^= *chi;
+            }
+            check_t ^= gf128_mul(t, chi);
+        }
+        IknpExtend {
+            base_transfer,
+            columns: u_columns,
+            check_x,
+            check_t,
+        }
+    }
+
+    #[test]
+    fn sender_rejects_an_inconsistent_matrix() {
+        let mut rng = rand::thread_rng();
+        let messages = random_pairs(200);
+        let choice_bits: Vec<bool> = (0..messages.len()).map(|_| rng.gen()).collect();
+        let (receiver, setup) = IknpReceiver::new(&choice_bits);
+        let (sender, choices) = IknpSender::new(&setup).unwrap();
+
+        //the error only reaches q in the columns where the sender's bit s_i is 1, elsewhere it
+        //goes unnoticed and leaks s_i, the one-bit guess KOS tolerates
+        let delta = sender.delta();
+        let (caught, missed): (Vec<usize>, Vec<usize>) =
+            (0..BASE_OT_CNT).partition(|&i| label_bit(&delta, i));
+        for column in [caught[0], caught[caught.len() - 1]] {
+            let extend = inconsistent_extend(&receiver, &choices, column, 17);
+            let err = sender.transfer(&extend, &messages).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.to_string(),
+                "OT extension: KOS consistency check failed, the receiver's matrix is malformed"
+            );
+        }
+        let extend = inconsistent_extend(&receiver, &choices, missed[0], 17);
+        assert!(sender.transfer(&extend, &messages).is_ok());
+    }
+}
@@ -1,15 +1,59 @@
 use crate::utils::convert_bits2_bytes;
 use crate::utils::convert_bytes2_bits;
-use crate::utils::get_padded_bits;
+use crate::utils::midstate_bits;
+use crate::utils::pad_message_bits;
 
+#[cfg(any(test, feature = "insecure"))]
+use crate::auth_gc::{
+    block_inputs, AuthEvaluator, AuthGarbledAnd, AuthGarbler, AuthPrep, AuthShare, BlockInput,
+    MacedBit, OutputOpening, TrustedDealer,
+};
+use crate::channel::Channel;
+use crate::channel::TranscriptChannel;
+use crate::cut_and_choose::apply_matrix;
+use crate::cut_and_choose::cheating_detected;
+use crate::cut_and_choose::choose_checked_copies;
+use crate::cut_and_choose::consistency_matrix;
+use crate::cut_and_choose::decode_p1_labels;
+use crate::cut_and_choose::decrypt_p1_labels;
+use crate::cut_and_choose::encode_p1_bits;
+use crate::cut_and_choose::encode_p1_zero_labels;
+use crate::cut_and_choose::encrypt_p1_labels;
+use crate::cut_and_choose::hash_labels;
+use crate::cut_and_choose::hash_seed;
+use crate::cut_and_choose::majority_output;
+use crate::cut_and_choose::CheckedCopy;
+use crate::cut_and_choose::CopyCommitment;
+use crate::cut_and_choose::EvaluatedCopy;
+use crate::cut_and_choose::CONSISTENCY_HASH_BITS;
+use crate::cut_and_choose::CONSISTENCY_PAD_BITS;
+use crate::cut_and_choose::P1_INPUT_SHARES;
 use crate::gc::EvalWire;
-use crate::gc::GarbleAnd;
+use crate::gc::GarbledCircuit;
+use crate::gc::GarbledTables;
+use crate::gc::GarblingMode;
 use crate::gc::WireLabel;
-use crate::GarbledCircuit;
+use crate::hmac;
+use crate::hmac::hmac_hashes;
+use crate::hmac::HmacInput;
+use crate::hmac::HmacMessage;
+use crate::hmac::MessageBit;
+use crate::length_hiding::HiddenLength;
+use crate::ot::OtChoices;
+use crate::ot::OtSetup;
+use crate::ot::OtTransfer;
+use crate::ot_ext::IknpExtend;
+use crate::ot_ext::IknpReceiver;
+use crate::ot_ext::IknpSender;
+use crate::silent_ot::SilentExtend;
+use crate::silent_ot::SilentReceiver;
+use crate::silent_ot::SilentSender;
+use crate::silent_ot::SilentTransfer;
 
 use std::collections::HashMap;
-use std::collections::VecDeque;
 
+use crate::circuit::HashFunction;
+use crate::circuit::OutputWire;
 use crate::circuit::Sha256Circuit;
 use crate::circuit::XorAndGate;
 use crate::circuit::AND_GATES_CNT;
@@ -17,110 +61,357 @@ use crate::circuit::OUTPUT_BITS_LEN;
 use crate::circuit::SINGLE_BLOCK_BITS_LEN;
 use crate::circuit::STATE_INFO_BITS_LEN;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
 use std::io;
 
-pub struct GarbleResult {
-    pub p0_labels: Vec<WireLabel>, //label0 corrsponding to p0's real input bits
-    pub p1_labels: Vec<WireLabel>, //a pair of messages: (rnd, rand \xor R)
+//One 512-bit (or 1024-bit) block of the garbled circuit, sent as soon as it is garbled so neither party
+//ever holds more than a single block of tables
+#[derive(Serialize, Deserialize)]
+pub struct GarbledBlock {
+    pub p0_labels: Vec<WireLabel>, //labels of p0's real input bits falling into this block
 
-    pub garbled_and: Vec<GarbleAnd>, //all AND gates's garbled result
-    pub permu_bits: Vec<bool>,       //Final output wire's permutation bits
+    pub garbled_and: GarbledTables, //this block's AND gates garbled result
+}
+
+//Session start, both ways: the parameters both parties must agree on, the bit length of the
+//sender's input, and the length it expects from the peer when the inputs are concatenated
+#[derive(Serialize, Deserialize)]
+pub struct SessionDeclaration {
+    pub security_mode: SecurityMode,
+    pub copies: Option<usize>, //garbled copies of cut-and-choose
+    pub hash_function: HashFunction,
+    pub ot_provider: OtProvider,
+    pub garbling_mode: GarblingMode,
+    pub output_policy: OutputPolicy,
+    pub prefix_hash: [u8; 32], //SHA-256 of the public prefix
+    pub bits_cnt: usize,
+    pub peer_bits_cnt: Option<usize>,
+    pub word_bits: Option<usize>, //word size of arithmetic shares, none for XOR shares
+    pub max_len: Option<usize>,   //bound of a hidden message length, in bytes
+}
+
+//Authenticated garbling, first message of a block (garbler -> evaluator)
+#[cfg(any(test, feature = "insecure"))]
+#[derive(Serialize, Deserialize)]
+pub struct AuthBlockSetup {
+    pub prep: AuthPrep, //the evaluator's preprocessing, dealt by the garbler's stand-in dealer
+    pub p1_masks: Vec<MacedBit>, //garbler's shares of the masks on P1's input wires
+}
+
+//Authenticated garbling, evaluator's reply
+#[cfg(any(test, feature = "insecure"))]
+#[derive(Serialize, Deserialize)]
+pub struct AuthInputs {
+    pub p0_masks: Vec<MacedBit>, //evaluator's shares of the masks on P0's input wires
+    pub p1_masked: Vec<bool>,    //P1's masked input bits y ⊕ λ
+}
+
+//Authenticated garbling, the garbled block (garbler -> evaluator)
+#[cfg(any(test, feature = "insecure"))]
+#[derive(Serialize, Deserialize)]
+pub struct AuthGarbledBlock {
+    pub p0_masked: Vec<bool>,         //P0's masked input bits x ⊕ λ
+    pub input_labels: Vec<WireLabel>, //labels of the masked values, P0's then P1's wire per bit
+    pub garbled_and: Vec<AuthGarbledAnd>,
+}
+
+//Which OT protocol transfers P1's input labels, both parties must agree on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OtProvider {
+    Iknp,   //KOS-checked IKNP extension, 128 bits per input bit
+    Silent, //Ferret-style LPN expansion, one bit per input bit on top of the base COTs
+}
+
+impl OtProvider {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "iknp" => Ok(OtProvider::Iknp),
+            "silent" => Ok(OtProvider::Silent),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown OT provider {}, use iknp or silent", other),
+            )),
+        }
+    }
+}
+
+//How the parties protect themselves against a malicious peer, both must agree on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecurityMode {
+    SemiHonest,    //a single garbled circuit
+    DualExecution, //both parties garble once, a malicious garbler may learn one bit
+    CutAndChoose,  //the garbler commits to several copies, some are checked, the others evaluated
+    Authenticated, //WRK17 authenticated garbling with a dealer inside the garbler, `insecure` only
+}
+
+impl SecurityMode {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "semihonest" => Ok(SecurityMode::SemiHonest),
+            "dualex" => Ok(SecurityMode::DualExecution),
+            "cutandchoose" => Ok(SecurityMode::CutAndChoose),
+            "authenticated" if cfg!(feature = "insecure") => Ok(SecurityMode::Authenticated),
+            "authenticated" => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The authenticated mode deals its preprocessing inside the garbler, which then \
+                 learns the evaluator's input. Build with --features insecure to use it anyway",
+            )),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown security mode {}, use semihonest, dualex, cutandchoose or authenticated",
+                    other
+                ),
+            )),
+        }
+    }
+}
+
+//How the inputs of both parties make up the hashed message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    Xor,                             //x0 XOR x1, both shares have the same length
+    Concat { peer_len: usize },      //x0 || x1, the peer's input length in bytes is public
+    Arithmetic { word_bits: usize }, //x0 + x1 per byte (8) or per big-endian 32-bit word (32)
+}
+
+impl InputMode {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown input mode {}, use xor, concat:<peer length in bytes>, arith8 or arith32",
+                    name
+                ),
+            )
+        };
+        match name.split_once(':') {
+            None if name == "xor" => Ok(InputMode::Xor),
+            None if name == "arith8" => Ok(InputMode::Arithmetic { word_bits: 8 }),
+            None if name == "arith32" => Ok(InputMode::Arithmetic { word_bits: 32 }),
+            Some(("concat", peer_len)) => Ok(InputMode::Concat {
+                peer_len: peer_len.parse().map_err(|_| invalid())?,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+//Who learns the digest in the semi-honest protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputPolicy {
+    Evaluator, //the permutation bits go to the evaluator, which keeps the digest
+    Garbler,   //the evaluator returns its output labels, only the garbler decodes them
+    Both,      //the evaluator decodes and returns its output labels for the garbler to decode
+    Shared,    //each party keeps an XOR share of the digest, nobody decodes
+}
+
+impl OutputPolicy {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "evaluator" => Ok(OutputPolicy::Evaluator),
+            "garbler" => Ok(OutputPolicy::Garbler),
+            "both" => Ok(OutputPolicy::Both),
+            "shared" => Ok(OutputPolicy::Shared),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown output policy {}, use evaluator, garbler, both or shared",
+                    other
+                ),
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum LabelSender {
+    Iknp(IknpSender),
+    Silent(SilentSender),
+}
+
+#[derive(Debug)]
+enum LabelReceiver {
+    Iknp(IknpReceiver),
+    Silent(SilentReceiver),
+}
+
+//Third OT flow (evaluator -> garbler)
+#[derive(Serialize, Deserialize)]
+pub enum LabelRequest {
+    Iknp(IknpExtend),
+    Silent(SilentExtend),
+}
+
+//Fourth OT flow (garbler -> evaluator)
+#[derive(Serialize, Deserialize)]
+pub enum LabelTransfer {
+    Iknp(OtTransfer),
+    Silent(SilentTransfer),
+}
+
+//Dual execution: P0 opens the commitment to its view of the output labels
+#[derive(Serialize, Deserialize)]
+pub struct EqualityOpening {
+    pub view: [u8; 32],
+    pub nonce: [u8; 32],
+}
+
+fn provider_mismatch() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "OT message does not match the selected OT provider",
+    )
 }
 
 //generate the whol sha256 circuit
 #[derive(Debug)]
 pub struct Party {
+    role: usize,
     secret_bits: Vec<bool>,
     circuit: Sha256Circuit,
+
+    ot_provider: OtProvider,
+    garbling_mode: GarblingMode,
+    output_policy: OutputPolicy,
+    input_mode: InputMode,
+    public_prefix: Vec<u8>, //known to both parties, hashed before the secret message
+    length_share: Option<usize>, //share of the secret message length, the input length is the bound
+    p1_label_pairs: Vec<(WireLabel, WireLabel)>, //garbler only: P1's OT message pairs, never sent in clear
+    ot_sender: Option<LabelSender>,
+    ot_receiver: Option<LabelReceiver>,
 }
 
 impl Party {
-    // Create a new party
+    /// Create a new party, `message` is its share of the input. Any length is supported, the empty
+    /// message and the lengths whose padding spills into an extra block (56 to 63 bytes) included,
+    /// and either share may be empty. The shorter share is zero-extended to the longer one.
     pub fn new(role: usize, message: &[u8]) -> io::Result<Self> {
-        let m_circuit = Sha256Circuit::new().map_err(|e| {
-            println!("Failed to create circuit: {}", e);
-
-            io::Error::new(io::ErrorKind::Other, "Circuit creation failed")
-        })?;
-
-        if role == 0 {
-            //server as garbler
-            m_circuit.display();
-        } else { //client as evaluator
-        }
+        let m_circuit = Sha256Circuit::new()?;
 
         // Return the new Party instance
         Ok(Party {
-            // network:network_interface,
+            role,
             secret_bits: convert_bytes2_bits(message).to_vec(),
             circuit: m_circuit,
+            ot_provider: OtProvider::Iknp,
+            garbling_mode: GarblingMode::HalfGates,
+            output_policy: OutputPolicy::Both,
+            input_mode: InputMode::Xor,
+            public_prefix: Vec::new(),
+            length_share: None,
+            p1_label_pairs: Vec::new(),
+            ot_sender: None,
+            ot_receiver: None,
         })
     }
 
-    pub fn start_garbling(&mut self) -> GarbleResult {
-        let mut garbler = GarbledCircuit::new();
+    //-----Step-0: generate random input wire labels, P0's half first then P1's half------------//
+    fn prepare_input_labels(&self, garbler: &mut GarbledCircuit) -> Vec<WireLabel> {
+        let (p0_bits_cnt, p1_bits_cnt) = self.input_bits_cnt();
+        let label_size: usize = p0_bits_cnt + p1_bits_cnt;
+        (0..label_size)
+            .map(|_| random_label(&mut garbler.rng))
+            .collect()
+    }
 
-        let secret_bits_cnt: usize = self.secret_bits.len();
-        //-----Step-0: generate random input wire labels------------//
-        let label_size: usize = secret_bits_cnt * 2;
-
-        let zero_labels: Vec<WireLabel> = (0..label_size)
-            .map(|_| {
-                let mut label = [0u8; 16];
-                garbler.rng.fill(&mut label); // Fill the label's 16 bytes with random data
-                WireLabel::from_data(label)
-            })
-            .collect();
+    #[allow(clippy::needless_range_loop)]
+    fn start_garbling(
+        &mut self,
+        garbler: &mut GarbledCircuit,
+        zero_labels: &[WireLabel],
+        reveal_p0_labels: bool, //false when P0's labels are opened separately (cut-and-choose)
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<WireLabel>> {
+        let (p0_bits_cnt, _) = self.input_bits_cnt();
+        let message_bits_cnt: usize = self.message_bits_cnt();
+        let prefix_bits_cnt: usize = self.prefix_bits_cnt();
 
         //-----Step-1: Prepare garbled circuit gate by gate of the circuit (gc)------------//
         //compute zero-labels for every wire (with flipped state because of internal Non gate )
         //A EvalWire structure with with flipped state is chosen, as though input wires are by default false, internal out gates can be marked as flipped=true, thus, we universally store EvalWire type for convenince use
-        let mut p0_vec: Vec<WireLabel> = Vec::with_capacity(secret_bits_cnt); //Prepare P0's pseudorandom label for transmission
-        let mut p1_ot_vec: Vec<WireLabel> = Vec::with_capacity(secret_bits_cnt * 2); // Prepare P1's OT message pair (zero-lable, one-lable) list
-        for j in 0..secret_bits_cnt {
-            if self.secret_bits[j] {
-                p0_vec.push(zero_labels[j] ^ garbler.global_r);
-            } else {
-                p0_vec.push(zero_labels[j]);
-            }
-            p1_ot_vec.push(zero_labels[secret_bits_cnt + j]); //define this as the zero label
-            p1_ot_vec.push(zero_labels[secret_bits_cnt + j] ^ garbler.global_r);
-        }
-        let mut all_garbled_ands: Vec<GarbleAnd> = Vec::new();
-        let mut output_permutation_bits: Vec<bool> = Vec::new();
+        let mut output_zero_labels: Vec<WireLabel> = Vec::new(); //the output labels decoding to 0
 
-        let overall_bits = get_padded_bits(secret_bits_cnt / 8);
-        let block_cnt: usize = overall_bits.len() / SINGLE_BLOCK_BITS_LEN;
-        // let mut last_evaluation_result: Vec<EvalWire>;
-        // for j in 0..STATE_INFO_BITS_LEN {
-        //     last_evaluation_result.push(label: WireLabel::zero(), flipped: self.circuit.get_initial_hash(j));
-        // }
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        let overall_bits = self.public_message_bits();
+        let block_cnt: usize = overall_bits.len() / block_bits_len;
 
-        let mut last_evaluation_result: Vec<EvalWire> = (0..STATE_INFO_BITS_LEN)
-            .map(|j| EvalWire {
+        let mut last_evaluation_result: Vec<EvalWire> = self
+            .initial_state_bits()
+            .into_iter()
+            .map(|bit| EvalWire {
                 label: WireLabel::zero(),
-                flipped: self.circuit.get_initial_hash(j),
+                flipped: bit,
             })
             .collect();
 
+        //With a hidden length, P0's length share labels serve every block so they are sent once,
+        //and the chaining value of the final block is selected into an accumulator
+        let hidden_length: Option<HiddenLength> = self.hidden_length();
+        if hidden_length.is_some() && reveal_p0_labels {
+            let p0_length_labels: Vec<WireLabel> = self.input_bits()[message_bits_cnt..]
+                .iter()
+                .enumerate()
+                .map(|(k, &bit)| {
+                    let zero: WireLabel = zero_labels[message_bits_cnt + k];
+                    if bit {
+                        zero ^ garbler.global_r
+                    } else {
+                        zero
+                    }
+                })
+                .collect();
+            channel.send(&p0_length_labels)?;
+        }
+        let mut accumulator: Vec<EvalWire> = vec![
+            EvalWire {
+                label: WireLabel::zero(),
+                flipped: false,
+            };
+            self.circuit.state_bits_len()
+        ];
+
         for i in 0..block_cnt {
             // It stores every k-v: wire_number - evaluation results
             let mut zero_label_map: HashMap<usize, EvalWire> = HashMap::new();
 
-            //Process the first input: 512 bits block
+            //Process the first input: 512 bits block (1024 for SHA-512)
             let mut extra_double_gates: Vec<XorAndGate> = Vec::new();
+            let mut p0_vec: Vec<WireLabel> = Vec::new(); //Prepare P0's pseudorandom label for transmission
 
-            let left_start_index: usize = i * SINGLE_BLOCK_BITS_LEN;
-            let right_end_index: usize = (i + 1) * SINGLE_BLOCK_BITS_LEN;
+            let left_start_index: usize = i * block_bits_len;
+            let right_end_index: usize = (i + 1) * block_bits_len;
 
             for j in left_start_index..right_end_index {
-                let circuit_wire_id: usize =
-                    SINGLE_BLOCK_BITS_LEN - 1 - (j % SINGLE_BLOCK_BITS_LEN);
-                if j < secret_bits_cnt {
+                let circuit_wire_id: usize = block_bits_len - 1 - (j % block_bits_len);
+                //index among the secret message bits, which follow the rest of the public prefix
+                let secret_index: Option<usize> = j
+                    .checked_sub(prefix_bits_cnt)
+                    .filter(|&s| s < message_bits_cnt);
+                if let (Some(s), InputMode::Concat { .. }) = (secret_index, self.input_mode) {
+                    //concatenated input: the message bit is the input bit of P0 or P1 itself
+                    zero_label_map.insert(
+                        circuit_wire_id,
+                        EvalWire {
+                            label: zero_labels[s],
+                            flipped: false,
+                        },
+                    );
+                    if !reveal_p0_labels || s >= p0_bits_cnt {
+                        //nothing to attach
+                    } else if self.secret_bits[s] {
+                        p0_vec.push(zero_labels[s] ^ garbler.global_r);
+                    } else {
+                        p0_vec.push(zero_labels[s]);
+                    }
+                } else if let Some(s) = secret_index {
                     //real secret bits input
                     let gate = XorAndGate {
-                        input0: self.circuit.extra_input_wire + 2 * j,
-                        input1: self.circuit.extra_input_wire + 2 * j + 1,
+                        input0: self.circuit.extra_input_wire + 2 * s,
+                        input1: self.circuit.extra_input_wire + 2 * s + 1,
                         output: circuit_wire_id,
                         input0_flipped: false,
                         input1_flipped: false,
@@ -129,20 +420,29 @@ impl Party {
                     zero_label_map.insert(
                         gate.input0,
                         EvalWire {
-                            label: zero_labels[j],
+                            label: zero_labels[s],
                             flipped: false,
                         },
                     ); //party-0 zero_label
                     zero_label_map.insert(
                         gate.input1,
                         EvalWire {
-                            label: zero_labels[secret_bits_cnt + j],
+                            label: zero_labels[p0_bits_cnt + s],
                             flipped: false,
                         },
                     ); //party-1 zero_label
-                    extra_double_gates.push(gate);
+                    if !reveal_p0_labels {
+                        //nothing to attach
+                    } else if self.secret_bits[s] {
+                        p0_vec.push(zero_labels[s] ^ garbler.global_r);
+                    } else {
+                        p0_vec.push(zero_labels[s]);
+                    }
+                    if self.input_mode == InputMode::Xor && hidden_length.is_none() {
+                        extra_double_gates.push(gate);
+                    }
                 } else {
-                    //padding bits
+                    //public bits: the rest of the prefix and the padding
                     zero_label_map.insert(
                         circuit_wire_id,
                         EvalWire {
@@ -153,148 +453,2046 @@ impl Party {
                 }
             }
 
-            //Process the second input: 256 bits state info
-            for (j, last) in last_evaluation_result.iter().enumerate() {
-                zero_label_map.insert(SINGLE_BLOCK_BITS_LEN + j, last.clone());
+            //hidden length: the length shares, the public constants and the accumulator
+            let mut selection_gates: Vec<XorAndGate> = Vec::new();
+            if let Some(hidden) = &hidden_length {
+                for k in 0..hidden.length_bits_cnt() {
+                    for (party, offset) in [(0, 0), (1, p0_bits_cnt)] {
+                        zero_label_map.insert(
+                            hidden.length_wire(k, party),
+                            EvalWire {
+                                label: zero_labels[offset + message_bits_cnt + k],
+                                flipped: false,
+                            },
+                        );
+                    }
+                }
+                for (wire, bit) in [(hidden.zero_wire(), false), (hidden.one_wire(), true)] {
+                    zero_label_map.insert(
+                        wire,
+                        EvalWire {
+                            label: WireLabel::zero(),
+                            flipped: bit,
+                        },
+                    );
+                }
+                for (k, acc) in accumulator.iter().enumerate() {
+                    zero_label_map.insert(hidden.accumulator_wire(k), acc.clone());
+                }
+                let (message_gates, selection) =
+                    hidden.block_gates(i, &self.circuit.output_wire_ids);
+                extra_double_gates.extend(message_gates);
+                selection_gates = selection;
             }
 
             //update circuit gates
-            self.circuit.update_extra_circuit(&extra_double_gates);
+            extra_double_gates.extend(self.adder_gates(left_start_index, right_end_index));
+            //the final block's output is the digest, the others chain into the next one
+            output_zero_labels = self.garble_block(
+                garbler,
+                &mut zero_label_map,
+                &mut last_evaluation_result,
+                (&extra_double_gates, &selection_gates),
+                p0_vec,
+                channel,
+            )?;
+            if let Some(hidden) = &hidden_length {
+                for (k, acc) in accumulator.iter_mut().enumerate() {
+                    *acc = zero_label_map[&hidden.selected_wire(k)].clone();
+                }
+                if i == block_cnt - 1 {
+                    //the selected chaining value replaces the one of the last block
+                    output_zero_labels = accumulator
+                        .iter()
+                        .map(|acc| {
+                            if acc.flipped {
+                                acc.label ^ garbler.global_r
+                            } else {
+                                acc.label
+                            }
+                        })
+                        .collect();
+                }
+            }
+        }
 
-            all_garbled_ands.extend(garbler.garble_circuit(&self.circuit, &mut zero_label_map));
+        //A truncated digest hides the rest of the chaining value
+        Ok(output_zero_labels.split_off(self.circuit.digest_outputs().start))
+    }
+
+    //One step of the block chaining shared by every garbling path: the message wires of the block
+    //are already in `zero_label_map`, the chaining value `state` feeds the state wires. The block
+    //is garbled with `extra_gates` before the compression function and `appended_gates` after it,
+    //then streamed with P0's labels. `state` becomes the block's output, whose zero labels are
+    //returned for the final block
+    fn garble_block(
+        &mut self,
+        garbler: &mut GarbledCircuit,
+        zero_label_map: &mut HashMap<usize, EvalWire>,
+        state: &mut [EvalWire],
+        (extra_gates, appended_gates): (&[XorAndGate], &[XorAndGate]),
+        p0_labels: Vec<WireLabel>,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<WireLabel>> {
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        for (j, last) in state.iter().enumerate() {
+            zero_label_map.insert(block_bits_len + j, last.clone());
+        }
+        self.circuit.update_extra_circuit(extra_gates);
+        self.circuit.append_extra_circuit(appended_gates);
+
+        let garbled_and = garbler.garble_circuit(&self.circuit, zero_label_map)?;
+        channel.send(&GarbledBlock {
+            p0_labels,
+            garbled_and,
+        })?;
+
+        let mut output_zero_labels: Vec<WireLabel> = Vec::with_capacity(state.len());
+        for (k, output_wire) in self.circuit.output_wire_ids.iter().enumerate() {
+            let wire = output_wire_label(zero_label_map, output_source(output_wire))?;
+            //flip real_input wire to the next round evaluation
+            state[k] = EvalWire {
+                label: wire.label,
+                flipped: output_wire.should_trace ^ wire.flipped,
+            };
+            if wire.flipped {
+                output_zero_labels.push(wire.label ^ garbler.global_r);
+            } else {
+                output_zero_labels.push(wire.label);
+            }
+        }
+        Ok(output_zero_labels)
+    }
+
+    //Evaluator side of `garble_block`, `state` becomes the output labels of the block
+    fn evaluate_block(
+        &mut self,
+        evaluator: &mut GarbledCircuit,
+        label_map: &mut HashMap<usize, WireLabel>,
+        state: &mut [WireLabel],
+        (extra_gates, appended_gates): (&[XorAndGate], &[XorAndGate]),
+        garbled_and: &GarbledTables,
+    ) -> io::Result<()> {
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        for (j, wire) in state.iter().enumerate() {
+            label_map.insert(block_bits_len + j, *wire);
+        }
+        self.circuit.update_extra_circuit(extra_gates);
+        self.circuit.append_extra_circuit(appended_gates);
 
-            for (k, output_wire) in self.circuit.output_wire_ids.iter().enumerate() {
-                //final output wires logic
-                let mut real_id: usize = output_wire.id;
-                if output_wire.should_trace {
-                    //A not gate is applied here
-                    real_id = output_wire.input_id;
+        evaluator.evaluate(&self.circuit, garbled_and, label_map)?;
+        for (k, output_wire) in self.circuit.output_wire_ids.iter().enumerate() {
+            state[k] = *output_wire_label(label_map, output_source(output_wire))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_ot_provider(&mut self, provider: OtProvider) {
+        self.ot_provider = provider;
+    }
+
+    pub fn set_garbling_mode(&mut self, mode: GarblingMode) {
+        self.garbling_mode = mode;
+    }
+
+    pub fn set_output_policy(&mut self, policy: OutputPolicy) {
+        self.output_policy = policy;
+    }
+
+    pub fn set_input_mode(&mut self, mode: InputMode) {
+        self.input_mode = mode;
+    }
+
+    /// Compute another hash of the SHA-2 family. SHA-224 runs the SHA-256 circuit from its own
+    /// initial hash values, and only the first 224 bits of the last chaining value are decoded.
+    /// SHA-512 and SHA-384 (likewise truncated) switch to a generated circuit for the SHA-512
+    /// compression function, with 1024-bit blocks, a 512-bit state and a 128-bit length field:
+    /// ```
+    /// use sha2::{Digest, Sha224, Sha384, Sha512};
+    /// use sha256_gc::channel::MemoryChannel;
+    /// use sha256_gc::circuit::HashFunction;
+    /// use sha256_gc::party::Party;
+    /// use std::thread;
+    ///
+    /// let x: Vec<u8> = b"abc".iter().zip(b"xyz").map(|(a, b)| a ^ b).collect();
+    /// for (hash_function, expected) in [
+    ///     (HashFunction::Sha224, Sha224::digest(&x).to_vec()),
+    ///     (HashFunction::Sha384, Sha384::digest(&x).to_vec()),
+    ///     (HashFunction::Sha512, Sha512::digest(&x).to_vec()),
+    /// ] {
+    ///     let (mut c0, mut c1) = MemoryChannel::pair();
+    ///     let p1 = thread::spawn(move || {
+    ///         let mut p1 = Party::new(1, b"xyz").unwrap();
+    ///         p1.set_hash_function(hash_function).unwrap();
+    ///         p1.run_evaluator(&mut c1).unwrap()
+    ///     });
+    ///     let mut p0 = Party::new(0, b"abc").unwrap();
+    ///     p0.set_hash_function(hash_function).unwrap();
+    ///     let digest = p0.run_garbler(&mut c0).unwrap();
+    ///     assert_eq!(digest, Some(expected));
+    ///     assert_eq!(p1.join().unwrap(), digest);
+    /// }
+    /// ```
+    pub fn set_hash_function(&mut self, hash_function: HashFunction) -> io::Result<()> {
+        if hash_function.is_sha512_family() != self.circuit.hash_function.is_sha512_family() {
+            self.circuit = Sha256Circuit::with_hash_function(hash_function)?;
+        }
+        self.circuit.set_hash_function(hash_function);
+        Ok(())
+    }
+
+    /// Hash only the first `bits_cnt` bits of the message (MSB first within each byte), for
+    /// messages whose length is not a whole number of bytes. SHA-256 then appends its `1` marker
+    /// right after the last message bit, as in the bit-oriented test vectors of
+    /// [SHAVS](https://csrc.nist.gov/projects/cryptographic-algorithm-validation-program). It
+    /// fails when the share holds fewer than `bits_cnt` bits:
+    /// ```
+    /// use sha256_gc::channel::MemoryChannel;
+    /// use sha256_gc::party::Party;
+    /// use std::thread;
+    ///
+    /// //the 5-bit message 01101, shared as 0x50 XOR 0x38
+    /// let (mut c0, mut c1) = MemoryChannel::pair();
+    /// let p1 = thread::spawn(move || {
+    ///     let mut p1 = Party::new(1, &[0x38]).unwrap();
+    ///     p1.set_message_bits_cnt(5).unwrap();
+    ///     p1.run_evaluator(&mut c1).unwrap()
+    /// });
+    /// let mut p0 = Party::new(0, &[0x50]).unwrap();
+    /// assert!(p0.set_message_bits_cnt(9).is_err());
+    /// p0.set_message_bits_cnt(5).unwrap();
+    /// let digest = p0.run_garbler(&mut c0).unwrap().unwrap();
+    /// assert_eq!(
+    ///     hex::encode(&digest),
+    ///     "d6d3e02a31a84a8caa9718ed6c2057be09db45e7823eb5079ce7a573a3760f95"
+    /// );
+    /// assert_eq!(p1.join().unwrap(), Some(digest));
+    /// ```
+    pub fn set_message_bits_cnt(&mut self, bits_cnt: usize) -> io::Result<()> {
+        if bits_cnt > self.secret_bits.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Cannot hash {} bits of a {}-bit share",
+                    bits_cnt,
+                    self.secret_bits.len()
+                ),
+            ));
+        }
+        self.secret_bits.truncate(bits_cnt);
+        Ok(())
+    }
+
+    /// Hash `prefix ‖ m` instead of the secret message `m`, where the prefix is public and must be
+    /// the same for both parties. The whole blocks of the prefix are compressed in the clear and
+    /// their midstate enters the garbled circuit as public state wires, so only the rest of the
+    /// prefix and the secret message are garbled:
+    /// ```
+    /// use sha2::{Digest, Sha256};
+    /// use sha256_gc::channel::MemoryChannel;
+    /// use sha256_gc::party::Party;
+    /// use std::thread;
+    ///
+    /// let prefix: Vec<u8> = (0..70).collect(); //one block compressed, 6 bytes garbled
+    /// let (mut c0, mut c1) = MemoryChannel::pair();
+    /// let p1_prefix = prefix.clone();
+    /// let p1 = thread::spawn(move || {
+    ///     let mut p1 = Party::new(1, b"xyz").unwrap();
+    ///     p1.set_public_prefix(&p1_prefix);
+    ///     p1.run_evaluator(&mut c1).unwrap()
+    /// });
+    /// let mut p0 = Party::new(0, b"abc").unwrap();
+    /// p0.set_public_prefix(&prefix);
+    /// let digest = p0.run_garbler(&mut c0).unwrap();
+    ///
+    /// let mut message = prefix;
+    /// message.extend([b'a' ^ b'x', b'b' ^ b'y', b'c' ^ b'z']);
+    /// assert_eq!(digest, Some(Sha256::digest(&message).to_vec()));
+    /// assert_eq!(p1.join().unwrap(), digest);
+    /// ```
+    pub fn set_public_prefix(&mut self, prefix: &[u8]) {
+        self.public_prefix = prefix.to_vec();
+    }
+
+    //Bytes of the public prefix compressed in the clear: its whole blocks
+    fn compressed_prefix_len(&self) -> usize {
+        let block_bytes: usize = self.circuit.block_bits_len() / 8;
+        self.public_prefix.len() / block_bytes * block_bytes
+    }
+
+    //Bits of the public prefix left to the garbled circuit, they precede the secret message bits
+    fn prefix_bits_cnt(&self) -> usize {
+        (self.public_prefix.len() - self.compressed_prefix_len()) * 8
+    }
+
+    //Public values of the garbled message bits: the rest of the prefix, zeros in place of the secret
+    //bits, then the padding of the whole message (prefix included)
+    fn public_message_bits(&self) -> Vec<bool> {
+        let compressed_len: usize = self.compressed_prefix_len();
+        let message_bits_cnt: usize = self.public_prefix.len() * 8 + self.message_bits_cnt();
+        let mut overall_bits = pad_message_bits(message_bits_cnt, self.circuit.hash_function)
+            .split_off(compressed_len * 8);
+        let prefix_bits = convert_bytes2_bits(&self.public_prefix[compressed_len..]);
+        overall_bits[..prefix_bits.len()].copy_from_slice(&prefix_bits);
+        overall_bits
+    }
+
+    //State wires of the first garbled block: the initial hash values, or the midstate of the prefix
+    fn initial_state_bits(&self) -> Vec<bool> {
+        if self.public_prefix.len() < self.circuit.block_bits_len() / 8 {
+            return self.circuit.initial_hash_vec.clone();
+        }
+        midstate_bits(
+            self.circuit.hash_function,
+            &self.public_prefix[..self.compressed_prefix_len()],
+        )
+    }
+
+    /// Hide the length of the message. The inputs of both parties are zero-extended to an agreed
+    /// maximum length, and the true length ℓ ≤ maximum is XOR-shared: `share` is this party's
+    /// ℓ0 or ℓ1, no wider than the maximum length in binary. The garbled circuit pads the message
+    /// and selects the chaining value of the block that ends it, so every length up to the
+    /// maximum gives the same transcript. A length past the maximum makes the run fail once the
+    /// digest is decoded (the shares of [`OutputPolicy::Shared`] then XOR to zero):
+    /// ```
+    /// use sha2::{Digest, Sha256};
+    /// use sha256_gc::channel::MemoryChannel;
+    /// use sha256_gc::party::Party;
+    /// use std::thread;
+    ///
+    /// //"abc" zero-extended to a 60-byte bound, the length 3 shared as 45 XOR 46
+    /// let (mut x0, mut x1) = (vec![7u8; 60], vec![7u8; 60]);
+    /// x1[..3].copy_from_slice(&[b'a' ^ 7, b'b' ^ 7, b'c' ^ 7]);
+    /// let (mut c0, mut c1) = MemoryChannel::pair();
+    /// let p1 = thread::spawn(move || {
+    ///     let mut p1 = Party::new(1, &x1).unwrap();
+    ///     p1.set_length_share(46);
+    ///     p1.run_evaluator(&mut c1).unwrap()
+    /// });
+    /// let mut p0 = Party::new(0, &x0).unwrap();
+    /// p0.set_length_share(45);
+    /// let digest = p0.run_garbler(&mut c0).unwrap();
+    /// assert_eq!(digest, Some(Sha256::digest(b"abc").to_vec()));
+    /// assert_eq!(p1.join().unwrap(), digest);
+    /// ```
+    pub fn set_length_share(&mut self, share: usize) {
+        self.length_share = Some(share);
+    }
+
+    //The gates padding the message in the circuit, when its length is hidden
+    fn hidden_length(&self) -> Option<HiddenLength> {
+        self.length_share.map(|_| {
+            HiddenLength::new(
+                self.circuit.extra_input_wire,
+                self.secret_bits.len() / 8,
+                self.circuit.hash_function,
+            )
+        })
+    }
+
+    //The decoded digest. With a hidden length, the zero digest means that no block was selected
+    //because ℓ exceeds the maximum length, see `HiddenLength`
+    fn checked_digest(&self, output_bits: &[bool]) -> io::Result<Vec<u8>> {
+        if self.length_share.is_some() && output_bits.iter().all(|&bit| !bit) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The hidden message length exceeds the maximum length of {} bytes",
+                    self.secret_bits.len() / 8
+                ),
+            ));
+        }
+        Ok(output_digest(output_bits))
+    }
+
+    //Own input bits: the message, then the length share (MSB first) when the length is hidden
+    fn input_bits(&self) -> Vec<bool> {
+        let mut bits: Vec<bool> = self.secret_bits.clone();
+        if let (Some(share), Some(hidden)) = (self.length_share, self.hidden_length()) {
+            let length_bits_cnt: usize = hidden.length_bits_cnt();
+            bits.extend(
+                (0..length_bits_cnt).map(|k| (share >> (length_bits_cnt - 1 - k)) & 1 == 1),
+            );
+        }
+        bits
+    }
+
+    //The authenticated and HMAC protocols hash their own messages from the initial hash values
+    fn reject_public_prefix(&self) -> io::Result<()> {
+        if self.public_prefix.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "A public prefix is not supported by this protocol",
+        ))
+    }
+
+    //HMAC keys are made of whole bytes
+    fn key_len(&self) -> io::Result<usize> {
+        if !self.secret_bits.len().is_multiple_of(8) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "An HMAC key share must be whole bytes, got {} bits",
+                    self.secret_bits.len()
+                ),
+            ));
+        }
+        Ok(self.secret_bits.len() / 8)
+    }
+
+    //Only the semi-honest and dual-execution protocols pad the message inside the circuit
+    fn reject_hidden_length(&self) -> io::Result<()> {
+        if self.length_share.is_none() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "A hidden length is not supported by this protocol",
+        ))
+    }
+
+    //The HMAC session chains full SHA-256 digests
+    fn require_sha256(&self) -> io::Result<()> {
+        match self.circuit.hash_function {
+            HashFunction::Sha256 => Ok(()),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("HMAC is only supported over SHA-256, not {:?}", other),
+            )),
+        }
+    }
+
+    //Number of input bits of P0 and P1
+    fn input_bits_cnt(&self) -> (usize, usize) {
+        let own: usize = self.secret_bits.len()
+            + self
+                .hidden_length()
+                .map_or(0, |hidden| hidden.length_bits_cnt());
+        match self.input_mode {
+            InputMode::Xor | InputMode::Arithmetic { .. } => (own, own),
+            InputMode::Concat { peer_len } if self.role == 0 => (own, peer_len * 8),
+            InputMode::Concat { peer_len } => (peer_len * 8, own),
+        }
+    }
+
+    //Number of bits of the hashed message, before padding
+    fn message_bits_cnt(&self) -> usize {
+        let (p0_bits_cnt, p1_bits_cnt) = self.input_bits_cnt();
+        match self.input_mode {
+            InputMode::Xor | InputMode::Arithmetic { .. } => self.secret_bits.len(),
+            InputMode::Concat { .. } => p0_bits_cnt + p1_bits_cnt,
+        }
+    }
+
+    //Ripple-carry adders summing both shares of every word among the garbled message bits left..right.
+    //The carry is c' = c XOR ((a XOR c) AND (b XOR c)): one AND gate per bit, none for the top bit
+    //whose carry is dropped (mod 2^word_bits)
+    fn adder_gates(&self, left: usize, right: usize) -> Vec<XorAndGate> {
+        let InputMode::Arithmetic { word_bits } = self.input_mode else {
+            return Vec::new();
+        };
+        let message_bits_cnt: usize = self.message_bits_cnt();
+        let prefix_bits_cnt: usize = self.prefix_bits_cnt();
+        let extra_input_wire: usize = self.circuit.extra_input_wire;
+        let input_wire = |s: usize, party: usize| extra_input_wire + 2 * s + party;
+        //Fresh wires of secret bit s: a XOR c, b XOR c, their AND and the carry out of bit s
+        let adder_wire = |s: usize, k: usize| extra_input_wire + 2 * message_bits_cnt + 4 * s + k;
+        let gate = |input0: usize, input1: usize, output: usize, gate_type: bool| XorAndGate {
+            input0,
+            input1,
+            output,
+            input0_flipped: false,
+            input1_flipped: false,
+            gate_type,
+        };
+
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        let mut gates: Vec<XorAndGate> = Vec::new();
+        //secret bits of the block, the prefix remainder is whole words so none straddles two blocks
+        let secret_left: usize = left.saturating_sub(prefix_bits_cnt);
+        let secret_right: usize = right.saturating_sub(prefix_bits_cnt).min(message_bits_cnt);
+        for word_start in (secret_left..secret_right).step_by(word_bits) {
+            //bits are MSB first, so the least significant bit ends the word
+            for k in 0..word_bits {
+                let s: usize = word_start + word_bits - 1 - k;
+                let circuit_wire_id: usize =
+                    block_bits_len - 1 - ((prefix_bits_cnt + s) % block_bits_len);
+                let (a, b) = (input_wire(s, 0), input_wire(s, 1));
+                if k == 0 {
+                    gates.push(gate(a, b, circuit_wire_id, false));
+                    gates.push(gate(a, b, adder_wire(s, 3), true));
+                    continue;
                 }
-                match zero_label_map.get(&real_id) {
-                    Some(wire) => {
-                        //derive permutation bits by last block
-                        //Manage output (just output the final permutation bit for label 0)
-                        if i < block_cnt - 1 {
-                            last_evaluation_result[k] = EvalWire {
-                                label: wire.label,
-                                flipped: output_wire.should_trace ^ wire.flipped,
-                            }; //flip real_input wire to the next round evaluation
-                        } else {
-                            let mut output_p_bit: bool = wire.label.check_lsb();
-                            output_p_bit ^= wire.flipped;
-                            output_permutation_bits.push(output_p_bit); //This should be the real output wire zero label last bit
-                        }
-                    }
-                    None => {
-                        println!("Output wire {} not found!!", real_id);
+                let carry: usize = adder_wire(s + 1, 3);
+                gates.push(gate(a, carry, adder_wire(s, 0), false));
+                gates.push(gate(adder_wire(s, 0), b, circuit_wire_id, false));
+                if k < word_bits - 1 {
+                    gates.push(gate(b, carry, adder_wire(s, 1), false));
+                    gates.push(gate(
+                        adder_wire(s, 0),
+                        adder_wire(s, 1),
+                        adder_wire(s, 2),
+                        true,
+                    ));
+                    gates.push(gate(carry, adder_wire(s, 2), adder_wire(s, 3), false));
+                }
+            }
+        }
+        gates
+    }
+
+    //Arithmetic shares must split into whole words, a length share must fit the maximum length
+    fn check_input_mode(&self) -> io::Result<()> {
+        if let (Some(share), Some(hidden)) = (self.length_share, self.hidden_length()) {
+            if self.input_mode != InputMode::Xor
+                || !self.public_prefix.is_empty()
+                || !self.secret_bits.len().is_multiple_of(8)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "A hidden length needs the xor input mode, whole bytes and no public prefix",
+                ));
+            }
+            if share
+                .checked_shr(hidden.length_bits_cnt() as u32)
+                .unwrap_or(0)
+                != 0
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Length share {} does not fit in {} bits",
+                        share,
+                        hidden.length_bits_cnt()
+                    ),
+                ));
+            }
+        }
+        match self.input_mode {
+            InputMode::Arithmetic { word_bits }
+                if !self.secret_bits.len().is_multiple_of(word_bits) =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Arithmetic shares modulo 2^{} need a multiple of {} bytes, got {}",
+                        word_bits,
+                        word_bits / 8,
+                        self.secret_bits.len() / 8
+                    ),
+                ))
+            }
+            //a word must not straddle two garbled blocks
+            InputMode::Arithmetic { word_bits }
+                if !self.public_prefix.len().is_multiple_of(word_bits / 8) =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Arithmetic shares modulo 2^{} need a public prefix of a multiple of {} bytes, got {}",
+                        word_bits,
+                        word_bits / 8,
+                        self.public_prefix.len()
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    //Declare the session parameters and the input lengths at session start. The parameters must
+    //be the same on both sides. XOR and arithmetic shares of different lengths are both
+    //zero-extended to the longer one, concatenated inputs must have the lengths each party
+    //declared for its peer
+    fn agree_session(
+        &mut self,
+        security_mode: SecurityMode,
+        copies: Option<usize>,
+        channel: &mut impl Channel,
+    ) -> io::Result<()> {
+        let own = SessionDeclaration {
+            security_mode,
+            copies,
+            hash_function: self.circuit.hash_function,
+            ot_provider: self.ot_provider,
+            garbling_mode: self.garbling_mode,
+            output_policy: self.output_policy,
+            prefix_hash: Sha256::digest(&self.public_prefix).into(),
+            bits_cnt: self.secret_bits.len(),
+            peer_bits_cnt: match self.input_mode {
+                InputMode::Concat { peer_len } => Some(peer_len * 8),
+                _ => None,
+            },
+            word_bits: match self.input_mode {
+                InputMode::Arithmetic { word_bits } => Some(word_bits),
+                _ => None,
+            },
+            max_len: self.hidden_length().map(|_| self.secret_bits.len() / 8),
+        };
+        let peer: SessionDeclaration = if self.role == 0 {
+            channel.send(&own)?;
+            channel.recv()?
+        } else {
+            let peer: SessionDeclaration = channel.recv()?;
+            channel.send(&own)?;
+            peer
+        };
+
+        check_agreement("security mode", own.security_mode, peer.security_mode)?;
+        check_agreement("cut-and-choose copy count", own.copies, peer.copies)?;
+        check_agreement("hash function", own.hash_function, peer.hash_function)?;
+        check_agreement("OT provider", own.ot_provider, peer.ot_provider)?;
+        check_agreement("garbling mode", own.garbling_mode, peer.garbling_mode)?;
+        check_agreement("output policy", own.output_policy, peer.output_policy)?;
+        if own.prefix_hash != peer.prefix_hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The parties disagree on the public prefix",
+            ));
+        }
+        check_agreement("arithmetic word size", own.word_bits, peer.word_bits)?;
+        check_agreement("maximum hidden length", own.max_len, peer.max_len)?;
+
+        match (own.peer_bits_cnt, peer.peer_bits_cnt) {
+            (Some(expected_cnt), Some(expected_by_peer)) => {
+                if peer.bits_cnt != expected_cnt || own.bits_cnt != expected_by_peer {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Declared input lengths disagree: this party holds {} bits and expects {} from the peer, the peer holds {} bits and expects {}",
+                            own.bits_cnt, expected_cnt, peer.bits_cnt, expected_by_peer
+                        ),
+                    ));
+                }
+            }
+            (None, None) => {
+                //each share must split into whole words before the shorter one is extended
+                if let InputMode::Arithmetic { word_bits } = self.input_mode {
+                    if let Some(bits_cnt) = [own.bits_cnt, peer.bits_cnt]
+                        .into_iter()
+                        .find(|bits_cnt| !bits_cnt.is_multiple_of(word_bits))
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "Arithmetic shares modulo 2^{} need a multiple of {} bits, got {}",
+                                word_bits, word_bits, bits_cnt
+                            ),
+                        ));
                     }
                 }
+                self.secret_bits
+                    .resize(own.bits_cnt.max(peer.bits_cnt), false);
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Only one of the parties concatenates the inputs",
+                ))
             }
-            println!("Garbler: {}/{} blocks garbled.", i + 1, block_cnt);
         }
+        Ok(())
+    }
 
-        GarbleResult {
-            p0_labels: p0_vec,
-            p1_labels: p1_ot_vec,
-            garbled_and: all_garbled_ands,
-            permu_bits: output_permutation_bits,
+    //The malicious protocols feed one bit of each party into every message bit
+    fn require_xor_input(&self) -> io::Result<()> {
+        match self.input_mode {
+            InputMode::Xor => Ok(()),
+            mode => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Input mode {:?} is only supported by the semi-honest protocol",
+                    mode
+                ),
+            )),
         }
     }
 
-    #[allow(clippy::needless_range_loop)]
-    pub fn start_evaluating(&mut self, ret: &mut GarbleResult) -> Vec<u8> {
-        let mut evaluator = GarbledCircuit::new();
+    /// Garbler endpoint: serve P1's label request over OT, stream the garbled circuit block by block,
+    /// then settle the output according to the output policy. Returns the digest (or its share under
+    /// [`OutputPolicy::Shared`]), None when the policy reveals it to the evaluator only.
+    ///
+    /// Whenever the garbler learns the output, it is decoded from the output labels returned by
+    /// the evaluator, each of which must be the zero label or the zero label XOR R. An evaluator
+    /// that lies about an output bit would have to guess the other label, so it is caught:
+    /// ```
+    /// use sha256_gc::channel::{Channel, MemoryChannel};
+    /// use sha256_gc::gc::WireLabel;
+    /// use sha256_gc::party::{OutputPolicy, Party};
+    /// use std::io;
+    /// use std::thread;
+    ///
+    /// //Flips the first output bit in the output labels P1 returns (its fourth message)
+    /// struct Lying(MemoryChannel, usize);
+    /// impl Channel for Lying {
+    ///     fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+    ///         self.1 += 1;
+    ///         if self.1 != 4 {
+    ///             return self.0.send_bytes(data);
+    ///         }
+    ///         let mut labels: Vec<WireLabel> = bincode::deserialize(data).unwrap();
+    ///         labels[0] ^= WireLabel::from_data([1; 16]);
+    ///         self.0.send_bytes(&bincode::serialize(&labels).unwrap())
+    ///     }
+    ///     fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+    ///         self.0.recv_bytes()
+    ///     }
+    /// }
+    ///
+    /// let (mut c0, c1) = MemoryChannel::pair();
+    /// let p1 = thread::spawn(move || {
+    ///     let mut p1 = Party::new(1, b"xyz").unwrap();
+    ///     p1.set_output_policy(OutputPolicy::Garbler);
+    ///     p1.run_evaluator(&mut Lying(c1, 0))
+    /// });
+    /// let mut p0 = Party::new(0, b"abc").unwrap();
+    /// p0.set_output_policy(OutputPolicy::Garbler);
+    /// let rejected = p0.run_garbler(&mut c0).unwrap_err();
+    /// assert_eq!(rejected.kind(), io::ErrorKind::InvalidData);
+    /// assert!(p1.join().unwrap().is_ok());
+    /// ```
+    pub fn run_garbler(&mut self, channel: &mut impl Channel) -> io::Result<Option<Vec<u8>>> {
+        self.agree_session(SecurityMode::SemiHonest, None, channel)?;
+        self.check_input_mode()?;
+        let (output_zero_labels, global_r) = self.garble_pass(channel)?;
+        let permu_bits = output_permutation_bits(&output_zero_labels);
+        match self.output_policy {
+            OutputPolicy::Evaluator => {
+                channel.send(&permu_bits)?;
+                Ok(None)
+            }
+            OutputPolicy::Garbler => {
+                let output_bits = verify_output_labels(&output_zero_labels, global_r, channel)?;
+                Ok(Some(self.checked_digest(&output_bits)?))
+            }
+            OutputPolicy::Both => {
+                channel.send(&permu_bits)?;
+                let output_bits = verify_output_labels(&output_zero_labels, global_r, channel)?;
+                Ok(Some(self.checked_digest(&output_bits)?))
+            }
+            //The digest is permu_bits XOR the last bits of the evaluator's labels
+            OutputPolicy::Shared => Ok(Some(output_digest(&permu_bits))),
+        }
+    }
+
+    //Evaluator endpoint: fetch P1's labels over OT, evaluate each garbled block as it arrives, then
+    //settle the output according to the output policy, see `run_garbler`
+    pub fn run_evaluator(&mut self, channel: &mut impl Channel) -> io::Result<Option<Vec<u8>>> {
+        self.agree_session(SecurityMode::SemiHonest, None, channel)?;
+        self.check_input_mode()?;
+        let output_labels = self.evaluate_pass(channel)?;
+        match self.output_policy {
+            OutputPolicy::Evaluator => {
+                let output_bits = decode_output(&output_labels, channel)?;
+                Ok(Some(self.checked_digest(&output_bits)?))
+            }
+            OutputPolicy::Garbler => {
+                channel.send(&output_labels)?;
+                Ok(None)
+            }
+            OutputPolicy::Both => {
+                let output_bits = decode_output(&output_labels, channel)?;
+                channel.send(&output_labels)?;
+                Ok(Some(self.checked_digest(&output_bits)?))
+            }
+            OutputPolicy::Shared => {
+                let share: Vec<bool> = output_labels.iter().map(|l| l.check_lsb()).collect();
+                Ok(Some(output_digest(&share)))
+            }
+        }
+    }
+
+    /// Dual execution ([MF06](https://www.iacr.org/archive/pkc2006/39580468/39580468.pdf),
+    /// [HKE12](https://www.cs.umd.edu/~jkatz/papers/SP12.pdf)): P0 garbles and P1 evaluates, then
+    /// the roles are swapped with fresh labels, so every party evaluates a circuit garbled by the
+    /// other. Before the digest is released, both check that the output labels they hold agree
+    /// with the outputs of their own circuit. A malicious garbler can then only learn whether its
+    /// corrupted circuit produced the honest output, i.e. at most one bit.
+    ///
+    /// Since `x0 XOR x1` is symmetric, the second execution reuses the circuit as is, with P1's
+    /// input on the garbler's wires.
+    ///
+    /// A garbler that corrupts its AND tables is caught by the equality check:
+    /// ```
+    /// use sha256_gc::channel::{Channel, MemoryChannel};
+    /// use sha256_gc::party::{GarbledBlock, Party};
+    /// use sha256_gc::gc::{GarbledTables, WireLabel};
+    /// use std::io;
+    /// use std::thread;
+    ///
+    /// //Flips every AND row of the first garbled block P0 sends (its fourth message)
+    /// struct Cheating(MemoryChannel, usize);
+    /// impl Channel for Cheating {
+    ///     fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+    ///         self.1 += 1;
+    ///         if self.1 != 4 {
+    ///             return self.0.send_bytes(data);
+    ///         }
+    ///         let mut block: GarbledBlock = bincode::deserialize(data).unwrap();
+    ///         if let GarbledTables::HalfGates(tables) = &mut block.garbled_and {
+    ///             for table in tables.iter_mut() {
+    ///                 table.t_g ^= WireLabel::from_data([1; 16]);
+    ///                 table.t_e ^= WireLabel::from_data([1; 16]);
+    ///             }
+    ///         }
+    ///         self.0.send_bytes(&bincode::serialize(&block).unwrap())
+    ///     }
+    ///     fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+    ///         self.0.recv_bytes()
+    ///     }
+    /// }
+    ///
+    /// let (c0, mut c1) = MemoryChannel::pair();
+    /// let p0 = thread::spawn(move || {
+    ///     let mut p0 = Party::new(0, b"abc").unwrap();
+    ///     p0.run_dual_execution(&mut Cheating(c0, 0))
+    /// });
+    /// let mut p1 = Party::new(1, b"xyz").unwrap();
+    /// let aborted = p1.run_dual_execution(&mut c1).unwrap_err();
+    /// assert_eq!(aborted.kind(), io::ErrorKind::InvalidData);
+    /// assert!(p0.join().unwrap().is_err());
+    /// ```
+    pub fn run_dual_execution(&mut self, channel: &mut impl Channel) -> io::Result<Vec<u8>> {
+        self.require_xor_input()?;
+        self.agree_session(SecurityMode::DualExecution, None, channel)?;
+        self.check_input_mode()?;
+        let (own, evaluated_labels, output_bits) = if self.role == 0 {
+            let own = self.garble_pass(channel)?;
+            channel.send(&output_permutation_bits(&own.0))?;
+            let evaluated_labels = self.evaluate_pass(channel)?;
+            let output_bits = decode_output(&evaluated_labels, channel)?;
+            (own, evaluated_labels, output_bits)
+        } else {
+            let evaluated_labels = self.evaluate_pass(channel)?;
+            let output_bits = decode_output(&evaluated_labels, channel)?;
+            let own = self.garble_pass(channel)?;
+            channel.send(&output_permutation_bits(&own.0))?;
+            (own, evaluated_labels, output_bits)
+        };
+        let (output_zero_labels, global_r) = own;
+
+        //The labels of our own circuit for the output we evaluated, an honest peer holds the same
+        let expected_labels: Vec<WireLabel> = output_zero_labels
+            .iter()
+            .zip(&output_bits)
+            .map(|(&label, &bit)| if bit { label ^ global_r } else { label })
+            .collect();
+        //Both views list P0's circuit first
+        let view = if self.role == 0 {
+            output_view(&expected_labels, &evaluated_labels)
+        } else {
+            output_view(&evaluated_labels, &expected_labels)
+        };
+
+        //P0 commits to its view before P1 reveals its own, so neither can adapt to the other
+        let equal = if self.role == 0 {
+            let mut nonce = [0u8; 32];
+            rand::thread_rng().fill(&mut nonce);
+            channel.send(&commit_view(&view, &nonce))?;
+            let peer_view: [u8; 32] = channel.recv()?;
+            channel.send(&EqualityOpening { view, nonce })?;
+            peer_view == view
+        } else {
+            let commitment: [u8; 32] = channel.recv()?;
+            channel.send(&view)?;
+            let opening: EqualityOpening = channel.recv()?;
+            commit_view(&opening.view, &opening.nonce) == commitment && opening.view == view
+        };
+        if !equal {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Dual execution equality check failed, the peer's garbled circuit is inconsistent",
+            ));
+        }
+        self.checked_digest(&output_bits)
+    }
+
+    //One execution as the garbler: serve the peer's labels over OT and stream the garbled circuit,
+    //returns the output labels decoding to 0 and the global R
+    fn garble_pass(
+        &mut self,
+        channel: &mut impl Channel,
+    ) -> io::Result<(Vec<WireLabel>, WireLabel)> {
+        let mut garbler = GarbledCircuit::with_mode(self.garbling_mode);
+        let zero_labels = self.prepare_input_labels(&mut garbler);
+
+        // Prepare P1's OT message pair (zero-lable, one-lable) list
+        let (p0_bits_cnt, _) = self.input_bits_cnt();
+        self.p1_label_pairs = zero_labels[p0_bits_cnt..]
+            .iter()
+            .map(|&zero| (zero, zero ^ garbler.global_r))
+            .collect();
+        self.serve_label_request(channel)?;
+
+        let output_zero_labels = self.start_garbling(&mut garbler, &zero_labels, true, channel)?;
+        Ok((output_zero_labels, garbler.global_r))
+    }
+
+    //One execution as the evaluator: fetch our labels over OT and evaluate the streamed circuit,
+    //returns the output labels in output wire order, still to be decoded
+    fn evaluate_pass(&mut self, channel: &mut impl Channel) -> io::Result<Vec<WireLabel>> {
+        let choices = self.input_bits();
+        let p1_labels = self.fetch_labels(&choices, channel)?;
+        self.start_evaluating(&p1_labels, None, channel)
+    }
+
+    //Garbler side of the four OT flows, transfers `p1_label_pairs`
+    fn serve_label_request(&mut self, channel: &mut impl Channel) -> io::Result<()> {
+        let setup: OtSetup = channel.recv()?;
+        let choices = self.accept_label_request(&setup)?;
+        channel.send(&choices)?;
+        let request: LabelRequest = channel.recv()?;
+        let transfer = self.transfer_labels(&request)?;
+        channel.send(&transfer)
+    }
+
+    //Evaluator side of the four OT flows, returns the messages matching P1's bits
+    fn fetch_labels(
+        &mut self,
+        choices: &[bool],
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<WireLabel>> {
+        channel.send(&self.request_labels(choices))?;
+        let choices: OtChoices = channel.recv()?;
+        let request = self.extend_label_request(&choices)?;
+        channel.send(&request)?;
+        let transfer: LabelTransfer = channel.recv()?;
+        self.receive_labels(&transfer)
+    }
+
+    /// Cut-and-choose ([LP07](https://eprint.iacr.org/2008/049), [sS11](https://eprint.iacr.org/2011/272)):
+    /// the garbler commits to `copies` garblings, each derived from its own seed. The evaluator
+    /// regarbles a random 3/5 of them from their opened seeds and evaluates the others, whose
+    /// output must have a strict majority. See
+    /// [`CUT_AND_CHOOSE_COPIES`](crate::cut_and_choose::CUT_AND_CHOOSE_COPIES) for the cheating
+    /// bound.
+    ///
+    /// P1's labels go through a single OT of key pairs, and every copy encrypts its label pairs
+    /// under these keys. P1 encodes each input bit as random shares XORing to it, so that
+    /// corrupted OT keys cannot probe its input.
+    ///
+    /// The evaluator returns the output labels of one copy behind the majority, the garbler only
+    /// accepts valid labels of that evaluated copy and decodes them itself. The garbler's input is bound to its commitments before the evaluator
+    /// picks the consistency hash M·(x0 || r), and every evaluated copy must give the same hash.
+    pub fn run_cut_and_choose_garbler(
+        &mut self,
+        copies: usize,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<u8>> {
+        let x0 = self.secret_bits.clone();
+        self.cut_and_choose_garbler(copies, &|_| x0.clone(), channel)
+    }
+
+    //The garbler of `run_cut_and_choose_garbler`, feeding `copy_x0(c)` to copy c: an honest
+    //garbler uses x0 everywhere, the consistency hash catches one that does not
+    pub(crate) fn cut_and_choose_garbler(
+        &mut self,
+        copies: usize,
+        copy_x0: &dyn Fn(usize) -> Vec<bool>,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<u8>> {
+        self.require_xor_input()?;
+        self.reject_hidden_length()?;
+        self.agree_session(SecurityMode::CutAndChoose, Some(copies), channel)?;
         let secret_bits_cnt: usize = self.secret_bits.len();
-        let overall_bits = get_padded_bits(secret_bits_cnt / 8);
-        let block_cnt: usize = overall_bits.len() / SINGLE_BLOCK_BITS_LEN;
+        let mut rng = rand::thread_rng();
+        self.p1_label_pairs = (0..secret_bits_cnt * P1_INPUT_SHARES)
+            .map(|_| (random_label(&mut rng), random_label(&mut rng)))
+            .collect();
+        self.serve_label_request(channel)?;
+
+        let pad_bits: Vec<bool> = (0..CONSISTENCY_PAD_BITS).map(|_| rng.gen()).collect();
+        let seeds: Vec<[u8; 32]> = (0..copies).map(|_| rng.gen()).collect();
+
+        //Commit to every copy without sending any table
+        let mut commitments: Vec<CopyCommitment> = Vec::with_capacity(copies);
+        for (c, seed) in seeds.iter().enumerate() {
+            let mut garbler = GarbledCircuit::from_seed(self.garbling_mode, *seed);
+            let (zero_labels, pad_labels, share_labels) = self.copy_input_labels(&mut garbler);
+            let p0_inputs =
+                p0_input_labels(&garbler, &zero_labels, &pad_labels, &copy_x0(c), &pad_bits);
+            let p1_inputs = encrypt_p1_labels(&garbler, &self.p1_label_pairs, &share_labels, c);
+            let mut transcript = TranscriptChannel::sink();
+            let output_zero_labels =
+                self.start_garbling(&mut garbler, &zero_labels, false, &mut transcript)?;
+            transcript.send(&output_permutation_bits(&output_zero_labels))?;
+            commitments.push(CopyCommitment {
+                seed: hash_seed(seed),
+                p0_inputs: hash_labels(&p0_inputs),
+                p1_inputs: hash_labels(p1_inputs.iter().flatten()),
+                tables: transcript.digest(),
+            });
+        }
+        channel.send(&commitments)?;
 
-        let mut p1_labels: Vec<WireLabel> = Vec::with_capacity(secret_bits_cnt);
-        for j in 0..secret_bits_cnt {
-            if self.secret_bits[j] {
-                p1_labels.push(ret.p1_labels[2 * j + 1]);
+        //Decoding bits of the consistency hash, committed through the seeds
+        let matrix_seed: [u8; 32] = channel.recv()?;
+        let matrix = consistency_matrix(matrix_seed, secret_bits_cnt + CONSISTENCY_PAD_BITS);
+        let decoding: Vec<Vec<bool>> = seeds
+            .iter()
+            .map(|seed| {
+                let mut garbler = GarbledCircuit::from_seed(self.garbling_mode, *seed);
+                let (zero_labels, pad_labels, _) = self.copy_input_labels(&mut garbler);
+                let hashed_zero_labels =
+                    [&zero_labels[..secret_bits_cnt], &pad_labels[..]].concat();
+                apply_matrix(&matrix, &hashed_zero_labels)
+                    .iter()
+                    .map(|label| label.check_lsb())
+                    .collect()
+            })
+            .collect();
+        channel.send(&decoding)?;
+
+        let checked: Vec<bool> = channel.recv()?;
+        if checked.len() != copies {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Expected a challenge over {} copies, received {}",
+                    copies,
+                    checked.len()
+                ),
+            ));
+        }
+        //Output zero labels and global R of the evaluated copies, to check the returned labels
+        let mut evaluated: Vec<Option<(Vec<WireLabel>, WireLabel)>> = vec![None; copies];
+        for (c, seed) in seeds.iter().enumerate() {
+            let mut garbler = GarbledCircuit::from_seed(self.garbling_mode, *seed);
+            let (zero_labels, pad_labels, share_labels) = self.copy_input_labels(&mut garbler);
+            let p1_inputs = encrypt_p1_labels(&garbler, &self.p1_label_pairs, &share_labels, c);
+            if checked[c] {
+                channel.send(&CheckedCopy {
+                    seed: *seed,
+                    p1_inputs,
+                })?;
+            } else {
+                let p0_inputs =
+                    p0_input_labels(&garbler, &zero_labels, &pad_labels, &copy_x0(c), &pad_bits);
+                channel.send(&EvaluatedCopy {
+                    p0_inputs,
+                    p1_inputs,
+                })?;
+                let output_zero_labels =
+                    self.start_garbling(&mut garbler, &zero_labels, false, channel)?;
+                channel.send(&output_permutation_bits(&output_zero_labels))?;
+                evaluated[c] = Some((output_zero_labels, garbler.global_r));
+            }
+        }
+        let copy: usize = channel.recv()?;
+        let Some((output_zero_labels, global_r)) = evaluated.get(copy).and_then(Option::as_ref)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Copy {} was not evaluated, its output is not accepted",
+                    copy
+                ),
+            ));
+        };
+        let output_bits = verify_output_labels(output_zero_labels, *global_r, channel)?;
+        Ok(output_digest(&output_bits))
+    }
+
+    /// Evaluator side of [`Party::run_cut_and_choose_garbler`], outputs the majority digest of the
+    /// evaluated copies and aborts if a checked copy or the garbler's input consistency fails.
+    pub fn run_cut_and_choose_evaluator(
+        &mut self,
+        copies: usize,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<u8>> {
+        self.require_xor_input()?;
+        self.reject_hidden_length()?;
+        self.agree_session(SecurityMode::CutAndChoose, Some(copies), channel)?;
+        let secret_bits_cnt: usize = self.secret_bits.len();
+        let choices = encode_p1_bits(&self.secret_bits, &mut rand::thread_rng());
+        let keys = self.fetch_labels(&choices, channel)?;
+
+        let commitments: Vec<CopyCommitment> = channel.recv()?;
+        if commitments.len() != copies {
+            return Err(cheating_detected(format!(
+                "expected {} copies, the garbler committed to {}",
+                copies,
+                commitments.len()
+            )));
+        }
+        let matrix_seed: [u8; 32] = rand::thread_rng().gen();
+        channel.send(&matrix_seed)?;
+        let matrix = consistency_matrix(matrix_seed, secret_bits_cnt + CONSISTENCY_PAD_BITS);
+        let decoding: Vec<Vec<bool>> = channel.recv()?;
+        if decoding.len() != copies || decoding.iter().any(|d| d.len() != CONSISTENCY_HASH_BITS) {
+            return Err(cheating_detected(
+                "malformed consistency decoding bits".to_string(),
+            ));
+        }
+        let checked = choose_checked_copies(copies);
+        channel.send(&checked)?;
+
+        let hasher = GarbledCircuit::with_mode(self.garbling_mode);
+        let mut outputs: Vec<Vec<u8>> = Vec::new();
+        let mut output_labels_of: Vec<(usize, Vec<WireLabel>)> = Vec::new();
+        let mut consistency_hash: Option<Vec<bool>> = None;
+        for (c, commitment) in commitments.iter().enumerate() {
+            if checked[c] {
+                //Regarble the copy from its seed, it must match everything committed
+                let opened: CheckedCopy = channel.recv()?;
+                if hash_seed(&opened.seed) != commitment.seed
+                    || opened.p1_inputs.len() != choices.len()
+                {
+                    return Err(cheating_detected(format!(
+                        "copy {} does not open its seed",
+                        c
+                    )));
+                }
+                let mut garbler = GarbledCircuit::from_seed(self.garbling_mode, opened.seed);
+                let (zero_labels, pad_labels, share_labels) = self.copy_input_labels(&mut garbler);
+                let hashed_zero_labels =
+                    [&zero_labels[..secret_bits_cnt], &pad_labels[..]].concat();
+                let expected_decoding: Vec<bool> = apply_matrix(&matrix, &hashed_zero_labels)
+                    .iter()
+                    .map(|label| label.check_lsb())
+                    .collect();
+                let expected_p1_labels: Vec<WireLabel> = share_labels
+                    .iter()
+                    .zip(&choices)
+                    .map(|(&zero, &bit)| if bit { zero ^ garbler.global_r } else { zero })
+                    .collect();
+                let p1_labels = decrypt_p1_labels(&hasher, &keys, &choices, &opened.p1_inputs, c);
+                let mut transcript = TranscriptChannel::sink();
+                let output_zero_labels =
+                    self.start_garbling(&mut garbler, &zero_labels, false, &mut transcript)?;
+                transcript.send(&output_permutation_bits(&output_zero_labels))?;
+                if transcript.digest() != commitment.tables
+                    || decoding[c] != expected_decoding
+                    || hash_labels(opened.p1_inputs.iter().flatten()) != commitment.p1_inputs
+                    || p1_labels != expected_p1_labels
+                {
+                    return Err(cheating_detected(format!(
+                        "checked copy {} was not garbled honestly",
+                        c
+                    )));
+                }
             } else {
-                p1_labels.push(ret.p1_labels[2 * j]);
+                let opened: EvaluatedCopy = channel.recv()?;
+                if opened.p0_inputs.len() != secret_bits_cnt + CONSISTENCY_PAD_BITS
+                    || opened.p1_inputs.len() != choices.len()
+                    || hash_labels(&opened.p0_inputs) != commitment.p0_inputs
+                    || hash_labels(opened.p1_inputs.iter().flatten()) != commitment.p1_inputs
+                {
+                    return Err(cheating_detected(format!(
+                        "inputs of copy {} do not match their commitment",
+                        c
+                    )));
+                }
+
+                //All evaluated copies must agree on M·(x0 || r)
+                let hash_bits: Vec<bool> = apply_matrix(&matrix, &opened.p0_inputs)
+                    .iter()
+                    .zip(&decoding[c])
+                    .map(|(label, &decode_bit)| label.check_lsb() ^ decode_bit)
+                    .collect();
+                match &consistency_hash {
+                    Some(expected) if *expected != hash_bits => {
+                        return Err(cheating_detected(format!(
+                            "copy {} was fed a different x0",
+                            c
+                        )));
+                    }
+                    Some(_) => {}
+                    None => consistency_hash = Some(hash_bits),
+                }
+
+                let p1_labels = decode_p1_labels(&decrypt_p1_labels(
+                    &hasher,
+                    &keys,
+                    &choices,
+                    &opened.p1_inputs,
+                    c,
+                ));
+                let mut transcript = TranscriptChannel::tap(channel);
+                let output_labels = self.start_evaluating(
+                    &p1_labels,
+                    Some(&opened.p0_inputs[..secret_bits_cnt]),
+                    &mut transcript,
+                )?;
+                let output_bits = decode_output(&output_labels, &mut transcript)?;
+                if transcript.digest() != commitment.tables {
+                    return Err(cheating_detected(format!(
+                        "tables of copy {} do not match their commitment",
+                        c
+                    )));
+                }
+                outputs.push(output_digest(&output_bits));
+                output_labels_of.push((c, output_labels));
             }
         }
 
-        let mut last_evaluation_result: Vec<WireLabel> =
-            vec![WireLabel::zero(); STATE_INFO_BITS_LEN];
-        let mut output_bits: Vec<bool> = Vec::with_capacity(OUTPUT_BITS_LEN);
+        let digest = majority_output(&outputs).ok_or_else(|| {
+            cheating_detected("no output has a majority of the evaluated copies".to_string())
+        })?;
+        //The labels prove the output to the garbler, which holds both labels of every output wire
+        let majority = outputs
+            .iter()
+            .position(|output| *output == digest)
+            .unwrap_or_default();
+        let (copy, output_labels) = &output_labels_of[majority];
+        channel.send(copy)?;
+        channel.send(output_labels)?;
+        Ok(digest)
+    }
+
+    /// Authenticated garbling ([WRK17](https://eprint.iacr.org/2017/030)): every wire mask is
+    /// XOR-shared between both parties with information-theoretic MACs, and every garbled row
+    /// carries the evaluator's share of the output mask with its MAC. A garbler that corrupts a
+    /// table or lies about an opened mask is caught by a failing MAC check, and the evaluator's
+    /// abort does not depend on its input.
+    ///
+    /// **This mode gives P1 no privacy.** The preprocessing is dealt by a [`TrustedDealer`] run
+    /// by the garbler, a stand-in for the two-party preprocessing protocol of WRK17. The garbler
+    /// thus knows the evaluator's mask shares and recovers P1's input from its masked bits, and
+    /// knowing Δ_B it can forge every MAC. It is only built with the `insecure` feature (and for
+    /// the unit tests).
+    ///
+    /// The evaluator opens the output labels and its output mask shares, the garbler decodes the
+    /// digest from them once they check out.
+    #[cfg(any(test, feature = "insecure"))]
+    pub fn run_auth_garbler(&mut self, channel: &mut impl Channel) -> io::Result<Vec<u8>> {
+        self.require_xor_input()?;
+        self.reject_public_prefix()?;
+        self.reject_hidden_length()?;
+        self.agree_session(SecurityMode::Authenticated, None, channel)?;
+        let secret_bits_cnt: usize = self.secret_bits.len();
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        let overall_bits = pad_message_bits(secret_bits_cnt, self.circuit.hash_function);
+        let block_cnt: usize = overall_bits.len() / block_bits_len;
+
+        let mut garbler = AuthGarbler::new(&self.circuit);
+        let mut dealer = TrustedDealer::new(&self.circuit, garbler.delta());
+        channel.send(&dealer.delta_b())?;
+
+        for i in 0..block_cnt {
+            let (extra_double_gates, inputs) =
+                block_inputs(&self.circuit, secret_bits_cnt, &overall_bits, i);
+            self.circuit.update_extra_circuit(&extra_double_gates);
+            let (prep, evaluator_prep) = dealer.deal_block(&self.circuit, &inputs);
+            let p1_masks: Vec<MacedBit> = prep
+                .inputs
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .map(AuthShare::reveal)
+                .collect();
+            channel.send(&AuthBlockSetup {
+                prep: evaluator_prep,
+                p1_masks,
+            })?;
+
+            let request: AuthInputs = channel.recv()?;
+            let real_bits_cnt: usize = prep.inputs.len() / 2;
+            if request.p0_masks.len() != real_bits_cnt || request.p1_masked.len() != real_bits_cnt {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Malformed input masks for block {}", i),
+                ));
+            }
+            //Λ = x ⊕ r ⊕ s on P0's wires, once the evaluator's share s is authenticated
+            let mut masked: Vec<bool> = Vec::with_capacity(prep.inputs.len());
+            for (s, (p0_mask, &p1_masked)) in
+                request.p0_masks.iter().zip(&request.p1_masked).enumerate()
+            {
+                let share = &prep.inputs[2 * s];
+                if !share.verifies(p0_mask, &garbler.delta()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("MAC check failed on input mask {} of block {}", s, i),
+                    ));
+                }
+                let bit = self.secret_bits[i * block_bits_len + s];
+                masked.push(bit ^ share.bit ^ p0_mask.bit);
+                masked.push(p1_masked);
+            }
+
+            let (input_labels, garbled_and) =
+                garbler.garble_block(&self.circuit, &inputs, &prep, &masked);
+            channel.send(&AuthGarbledBlock {
+                p0_masked: masked.iter().step_by(2).copied().collect(),
+                input_labels,
+                garbled_and,
+            })?;
+        }
+
+        channel.send(&garbler.output_masks(&self.circuit))?;
+        let openings: Vec<OutputOpening> = channel.recv()?;
+        let output_bits = garbler.decode_outputs(&self.circuit, &openings)?;
+        Ok(output_digest(&output_bits))
+    }
+
+    /// Evaluator side of [`Party::run_auth_garbler`], aborts on the first failing MAC check.
+    #[cfg(any(test, feature = "insecure"))]
+    pub fn run_auth_evaluator(&mut self, channel: &mut impl Channel) -> io::Result<Vec<u8>> {
+        self.require_xor_input()?;
+        self.reject_public_prefix()?;
+        self.reject_hidden_length()?;
+        self.agree_session(SecurityMode::Authenticated, None, channel)?;
+        let secret_bits_cnt: usize = self.secret_bits.len();
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        let overall_bits = pad_message_bits(secret_bits_cnt, self.circuit.hash_function);
+        let block_cnt: usize = overall_bits.len() / block_bits_len;
+
+        let delta_b: WireLabel = channel.recv()?;
+        let mut evaluator = AuthEvaluator::new(&self.circuit, delta_b);
+
         for i in 0..block_cnt {
+            let (extra_double_gates, inputs) =
+                block_inputs(&self.circuit, secret_bits_cnt, &overall_bits, i);
+            self.circuit.update_extra_circuit(&extra_double_gates);
+            let real_bits_cnt: usize = inputs
+                .iter()
+                .filter(|input| matches!(input, BlockInput::Secret { .. }))
+                .count();
+
+            let setup: AuthBlockSetup = channel.recv()?;
+            if setup.prep.inputs.len() != 2 * real_bits_cnt
+                || setup.prep.and_gates.len() != self.circuit.and_cnt
+                || setup.p1_masks.len() != real_bits_cnt
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Malformed preprocessing for block {}", i),
+                ));
+            }
+            //Λ = y ⊕ r ⊕ s on P1's wires, once the garbler's share r is authenticated
+            let mut p0_masks: Vec<MacedBit> = Vec::with_capacity(real_bits_cnt);
+            let mut p1_masked: Vec<bool> = Vec::with_capacity(real_bits_cnt);
+            for (s, p1_mask) in setup.p1_masks.iter().enumerate() {
+                let share = &setup.prep.inputs[2 * s + 1];
+                if !share.verifies(p1_mask, &delta_b) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("MAC check failed on input mask {} of block {}", s, i),
+                    ));
+                }
+                let bit = self.secret_bits[i * block_bits_len + s];
+                p1_masked.push(bit ^ share.bit ^ p1_mask.bit);
+                p0_masks.push(setup.prep.inputs[2 * s].reveal());
+            }
+            channel.send(&AuthInputs {
+                p0_masks,
+                p1_masked: p1_masked.clone(),
+            })?;
+
+            let block: AuthGarbledBlock = channel.recv()?;
+            if block.p0_masked.len() != real_bits_cnt
+                || block.input_labels.len() != 2 * real_bits_cnt
+                || block.garbled_and.len() != self.circuit.and_cnt
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Malformed authenticated block {}", i),
+                ));
+            }
+            let masked: Vec<bool> = block
+                .p0_masked
+                .iter()
+                .zip(&p1_masked)
+                .flat_map(|(&p0, &p1)| [p0, p1])
+                .collect();
+            evaluator.evaluate_block(
+                &self.circuit,
+                &inputs,
+                &setup.prep,
+                &block.input_labels,
+                &masked,
+                &block.garbled_and,
+            )?;
+        }
+
+        let output_masks: Vec<MacedBit> = channel.recv()?;
+        let output_bits = evaluator.decode_outputs(&self.circuit, &output_masks)?;
+        channel.send(&evaluator.output_openings(&self.circuit))?;
+        Ok(output_digest(&output_bits))
+    }
+
+    /// HMAC-SHA256 over a key XOR-shared between both parties: each party's input is its share
+    /// of the key, and `message` is public or held by one of the parties. The inner and outer
+    /// hashes (and the hash of a key longer than a block) are chained in the garbled circuit,
+    /// the ipad/opad XOR is free. Both parties learn the MAC, and the garbler verifies the
+    /// evaluator's output labels.
+    ///
+    /// Test case 2 of [RFC 4231](https://www.rfc-editor.org/rfc/rfc4231):
+    /// ```
+    /// use sha256_gc::channel::MemoryChannel;
+    /// use sha256_gc::hmac::HmacMessage;
+    /// use sha256_gc::party::Party;
+    /// use std::thread;
+    ///
+    /// let key = b"Jefe";
+    /// let k0 = [0x5a, 0x13, 0xc7, 0x01];
+    /// let k1: Vec<u8> = key.iter().zip(&k0).map(|(k, s)| k ^ s).collect();
+    /// let (mut c0, mut c1) = MemoryChannel::pair();
+    /// let p0 = thread::spawn(move || {
+    ///     let mut p0 = Party::new(0, &k0).unwrap();
+    ///     p0.run_hmac_garbler(&HmacMessage::Peer(28), &mut c0).unwrap()
+    /// });
+    /// let mut p1 = Party::new(1, &k1).unwrap();
+    /// let message = HmacMessage::Own(b"what do ya want for nothing?".to_vec());
+    /// let mac = p1.run_hmac_evaluator(&message, &mut c1).unwrap();
+    /// assert_eq!(
+    ///     hex::encode(&mac),
+    ///     "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    /// );
+    /// assert_eq!(p0.join().unwrap(), mac);
+    /// ```
+    pub fn run_hmac_garbler(
+        &mut self,
+        message: &HmacMessage,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<u8>> {
+        self.require_sha256()?;
+        self.reject_public_prefix()?;
+        self.reject_hidden_length()?;
+        self.agree_session(SecurityMode::SemiHonest, None, channel)?;
+        let key_len: usize = self.key_len()?;
+        let hashes = hmac_hashes(key_len, &HmacInput::new(self.role, message));
+        let mut inputs: Vec<bool> = self.secret_bits.clone();
+        if let HmacMessage::Own(data) = message {
+            inputs.extend(convert_bytes2_bits(data));
+        }
+
+        let mut garbler = GarbledCircuit::with_mode(self.garbling_mode);
+        let p0_zero_labels: Vec<WireLabel> = (0..inputs.len())
+            .map(|_| random_label(&mut garbler.rng))
+            .collect();
+        let p1_zero_labels: Vec<WireLabel> = (0..hmac::input_bits_cnt(&hashes, 1))
+            .map(|_| random_label(&mut garbler.rng))
+            .collect();
+        self.p1_label_pairs = p1_zero_labels
+            .iter()
+            .map(|&zero| (zero, zero ^ garbler.global_r))
+            .collect();
+        self.serve_label_request(channel)?;
+
+        let output_zero_labels = self.garble_session(
+            &mut garbler,
+            [&p0_zero_labels, &p1_zero_labels],
+            &inputs,
+            &hashes,
+            channel,
+        )?;
+        channel.send(&output_permutation_bits(&output_zero_labels))?;
+        let output_bits = verify_output_labels(&output_zero_labels, garbler.global_r, channel)?;
+        Ok(output_digest(&output_bits))
+    }
+
+    /// Evaluator side of [`Party::run_hmac_garbler`].
+    pub fn run_hmac_evaluator(
+        &mut self,
+        message: &HmacMessage,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<u8>> {
+        self.require_sha256()?;
+        self.reject_public_prefix()?;
+        self.reject_hidden_length()?;
+        self.agree_session(SecurityMode::SemiHonest, None, channel)?;
+        let key_len: usize = self.key_len()?;
+        let hashes = hmac_hashes(key_len, &HmacInput::new(self.role, message));
+        let mut inputs: Vec<bool> = self.secret_bits.clone();
+        if let HmacMessage::Own(data) = message {
+            inputs.extend(convert_bytes2_bits(data));
+        }
+
+        let p1_labels = self.fetch_labels(&inputs, channel)?;
+        let output_labels = self.evaluate_session(&p1_labels, &hashes, channel)?;
+        let output_bits = decode_output(&output_labels, channel)?;
+        channel.send(&output_labels)?;
+        Ok(output_digest(&output_bits))
+    }
+
+    //Garble a session of SHA-256 hashes, each over a padded message whose bits may come from the
+    //digests of earlier hashes. `zero_labels` are the zero labels of each party's inputs and
+    //`p0_inputs` P0's input bits. Returns the zero labels of the last digest's output wires
+    fn garble_session(
+        &mut self,
+        garbler: &mut GarbledCircuit,
+        zero_labels: [&[WireLabel]; 2],
+        p0_inputs: &[bool],
+        hashes: &[Vec<MessageBit>],
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<WireLabel>> {
+        let mut digests: Vec<Vec<WireLabel>> = Vec::with_capacity(hashes.len());
+        for message in hashes {
+            let mut last_evaluation_result: Vec<EvalWire> = (0..STATE_INFO_BITS_LEN)
+                .map(|j| EvalWire {
+                    label: WireLabel::zero(),
+                    flipped: self.circuit.get_initial_hash(j),
+                })
+                .collect();
+            let mut output_zero_labels: Vec<WireLabel> = Vec::new();
+            for block_bits in message.chunks(SINGLE_BLOCK_BITS_LEN) {
+                let mut zero_label_map: HashMap<usize, EvalWire> = HashMap::new();
+                let mut extra_double_gates: Vec<XorAndGate> = Vec::new();
+                let mut p0_vec: Vec<WireLabel> = Vec::new();
+                let own_label = |input: usize| {
+                    let zero = zero_labels[0][input];
+                    if p0_inputs[input] {
+                        zero ^ garbler.global_r
+                    } else {
+                        zero
+                    }
+                };
+
+                for (j, bit) in block_bits.iter().enumerate() {
+                    let circuit_wire_id: usize = SINGLE_BLOCK_BITS_LEN - 1 - j;
+                    let source = match *bit {
+                        MessageBit::Public(value) => EvalWire {
+                            label: WireLabel::zero(),
+                            flipped: value,
+                        },
+                        MessageBit::Shared { input, flip } => {
+                            let gate = XorAndGate {
+                                input0: self.circuit.extra_input_wire + 2 * j,
+                                input1: self.circuit.extra_input_wire + 2 * j + 1,
+                                output: circuit_wire_id,
+                                input0_flipped: flip,
+                                input1_flipped: false,
+                                gate_type: false,
+                            };
+                            for (party, wire) in [gate.input0, gate.input1].into_iter().enumerate()
+                            {
+                                zero_label_map.insert(
+                                    wire,
+                                    EvalWire {
+                                        label: zero_labels[party][input],
+                                        flipped: false,
+                                    },
+                                );
+                            }
+                            p0_vec.push(own_label(input));
+                            extra_double_gates.push(gate);
+                            continue;
+                        }
+                        MessageBit::Private { party, input } => {
+                            if party == 0 {
+                                p0_vec.push(own_label(input));
+                            }
+                            EvalWire {
+                                label: zero_labels[party][input],
+                                flipped: false,
+                            }
+                        }
+                        MessageBit::Hashed { hash, bit, flip } => EvalWire {
+                            label: digests[hash][OUTPUT_BITS_LEN - 1 - bit],
+                            flipped: flip,
+                        },
+                    };
+                    zero_label_map.insert(circuit_wire_id, source);
+                }
+                output_zero_labels = self.garble_block(
+                    garbler,
+                    &mut zero_label_map,
+                    &mut last_evaluation_result,
+                    (&extra_double_gates, &[]),
+                    p0_vec,
+                    channel,
+                )?;
+            }
+            digests.push(output_zero_labels);
+        }
+        Ok(digests.pop().unwrap_or_default())
+    }
+
+    //Evaluator side of `garble_session`, returns the output labels of the last digest
+    fn evaluate_session(
+        &mut self,
+        p1_labels: &[WireLabel],
+        hashes: &[Vec<MessageBit>],
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<WireLabel>> {
+        if p1_labels.len() != hmac::input_bits_cnt(hashes, 1) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Expected {} input labels, received {}",
+                    hmac::input_bits_cnt(hashes, 1),
+                    p1_labels.len()
+                ),
+            ));
+        }
+        let mut evaluator = GarbledCircuit::with_mode(self.garbling_mode);
+        let mut digests: Vec<Vec<WireLabel>> = Vec::with_capacity(hashes.len());
+        for message in hashes {
+            let mut last_evaluation_result: Vec<WireLabel> =
+                vec![WireLabel::zero(); STATE_INFO_BITS_LEN];
+            for (i, block_bits) in message.chunks(SINGLE_BLOCK_BITS_LEN).enumerate() {
+                let block: GarbledBlock = channel.recv()?;
+                let p0_labels_cnt: usize = block_bits
+                    .iter()
+                    .filter(|bit| {
+                        matches!(
+                            bit,
+                            MessageBit::Shared { .. } | MessageBit::Private { party: 0, .. }
+                        )
+                    })
+                    .count();
+                if block.garbled_and.len() != AND_GATES_CNT
+                    || block.p0_labels.len() != p0_labels_cnt
+                    || block.garbled_and.mode() != self.garbling_mode
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Garbled block {} of hash {} is malformed", i, digests.len()),
+                    ));
+                }
+
+                let mut evaluate_label_map: HashMap<usize, WireLabel> = HashMap::new();
+                let mut extra_double_gates: Vec<XorAndGate> = Vec::new();
+                let mut p0_labels = block.p0_labels.iter();
+                for (j, bit) in block_bits.iter().enumerate() {
+                    let circuit_wire_id: usize = SINGLE_BLOCK_BITS_LEN - 1 - j;
+                    let label = match *bit {
+                        MessageBit::Public(_) => WireLabel::zero(),
+                        MessageBit::Shared { input, flip } => {
+                            let gate = XorAndGate {
+                                input0: self.circuit.extra_input_wire + 2 * j,
+                                input1: self.circuit.extra_input_wire + 2 * j + 1,
+                                output: circuit_wire_id,
+                                input0_flipped: flip,
+                                input1_flipped: false,
+                                gate_type: false,
+                            };
+                            evaluate_label_map.insert(gate.input0, *p0_labels.next().unwrap());
+                            evaluate_label_map.insert(gate.input1, p1_labels[input]);
+                            extra_double_gates.push(gate);
+                            continue;
+                        }
+                        MessageBit::Private { party: 0, .. } => *p0_labels.next().unwrap(),
+                        MessageBit::Private { input, .. } => p1_labels[input],
+                        MessageBit::Hashed { hash, bit, .. } => {
+                            digests[hash][OUTPUT_BITS_LEN - 1 - bit]
+                        }
+                    };
+                    evaluate_label_map.insert(circuit_wire_id, label);
+                }
+                self.evaluate_block(
+                    &mut evaluator,
+                    &mut evaluate_label_map,
+                    &mut last_evaluation_result,
+                    (&extra_double_gates, &[]),
+                    &block.garbled_and,
+                )?;
+            }
+            digests.push(last_evaluation_result);
+        }
+        Ok(digests.pop().unwrap_or_default())
+    }
+
+    //Zero labels of one cut-and-choose copy: the input wires of both parties, the pad r of the
+    //consistency hash and the shares of P1's bits, all drawn from the copy's seed before any table
+    fn copy_input_labels(
+        &self,
+        garbler: &mut GarbledCircuit,
+    ) -> (Vec<WireLabel>, Vec<WireLabel>, Vec<WireLabel>) {
+        let zero_labels = self.prepare_input_labels(garbler);
+        let pad_labels: Vec<WireLabel> = (0..CONSISTENCY_PAD_BITS)
+            .map(|_| random_label(&mut garbler.rng))
+            .collect();
+        let (p0_bits_cnt, _) = self.input_bits_cnt();
+        let share_labels = encode_p1_zero_labels(&mut garbler.rng, &zero_labels[p0_bits_cnt..]);
+        (zero_labels, pad_labels, share_labels)
+    }
+
+    //Evaluator: P1's input labels are fetched in bulk through OT extension, P1 starts the base OTs
+    fn request_labels(&mut self, choice_bits: &[bool]) -> OtSetup {
+        let (receiver, setup) = match self.ot_provider {
+            OtProvider::Iknp => {
+                let (receiver, setup) = IknpReceiver::new(choice_bits);
+                (LabelReceiver::Iknp(receiver), setup)
+            }
+            OtProvider::Silent => {
+                let (receiver, setup) = SilentReceiver::new(choice_bits);
+                (LabelReceiver::Silent(receiver), setup)
+            }
+        };
+        self.ot_receiver = Some(receiver);
+        setup
+    }
+
+    //Garbler: answer the base OTs with its secret extension correlation
+    fn accept_label_request(&mut self, setup: &OtSetup) -> io::Result<OtChoices> {
+        let (sender, choices) = match self.ot_provider {
+            OtProvider::Iknp => {
+                let (sender, choices) = IknpSender::new(setup)?;
+                (LabelSender::Iknp(sender), choices)
+            }
+            OtProvider::Silent => {
+                let (sender, choices) = SilentSender::new(setup)?;
+                (LabelSender::Silent(sender), choices)
+            }
+        };
+        self.ot_sender = Some(sender);
+        Ok(choices)
+    }
+
+    //Evaluator: transfer the base seeds and the extension matrix
+    fn extend_label_request(&mut self, choices: &OtChoices) -> io::Result<LabelRequest> {
+        match &mut self.ot_receiver {
+            Some(LabelReceiver::Iknp(receiver)) => {
+                Ok(LabelRequest::Iknp(receiver.extend(choices)?))
+            }
+            Some(LabelReceiver::Silent(receiver)) => {
+                Ok(LabelRequest::Silent(receiver.extend(choices)?))
+            }
+            None => Err(io::Error::other("OT receiver is not ready")),
+        }
+    }
+
+    //Garbler: obliviously transfer P1's input labels, only the label matching each choice bit can be decrypted
+    fn transfer_labels(&self, request: &LabelRequest) -> io::Result<LabelTransfer> {
+        match (&self.ot_sender, request) {
+            (Some(LabelSender::Iknp(sender)), LabelRequest::Iknp(extend)) => Ok(
+                LabelTransfer::Iknp(sender.transfer(extend, &self.p1_label_pairs)?),
+            ),
+            (Some(LabelSender::Silent(sender)), LabelRequest::Silent(extend)) => Ok(
+                LabelTransfer::Silent(sender.transfer(extend, &self.p1_label_pairs)?),
+            ),
+            (Some(_), _) => Err(provider_mismatch()),
+            (None, _) => Err(io::Error::other("OT sender is not ready")),
+        }
+    }
+
+    //Evaluator: decrypt the OT messages matching P1's bits
+    fn receive_labels(&self, transfer: &LabelTransfer) -> io::Result<Vec<WireLabel>> {
+        match (&self.ot_receiver, transfer) {
+            (Some(LabelReceiver::Iknp(receiver)), LabelTransfer::Iknp(transfer)) => {
+                receiver.receive(transfer)
+            }
+            (Some(LabelReceiver::Silent(receiver)), LabelTransfer::Silent(transfer)) => {
+                receiver.receive(transfer)
+            }
+            (Some(_), _) => Err(provider_mismatch()),
+            (None, _) => Err(io::Error::other("OT receiver is not ready")),
+        }
+    }
+
+    //P0's labels come with every garbled block, unless they were opened beforehand (`p0_labels`)
+    #[allow(clippy::needless_range_loop)]
+    fn start_evaluating(
+        &mut self,
+        p1_labels: &[WireLabel],
+        p0_labels: Option<&[WireLabel]>,
+        channel: &mut impl Channel,
+    ) -> io::Result<Vec<WireLabel>> {
+        let mut evaluator = GarbledCircuit::with_mode(self.garbling_mode);
+        let (p0_bits_cnt, _) = self.input_bits_cnt();
+        let message_bits_cnt: usize = self.message_bits_cnt();
+        let prefix_bits_cnt: usize = self.prefix_bits_cnt();
+        let block_bits_len: usize = self.circuit.block_bits_len();
+        let state_bits_len: usize = self.circuit.state_bits_len();
+        let overall_bits = self.public_message_bits();
+        let block_cnt: usize = overall_bits.len() / block_bits_len;
+        let hidden_length: Option<HiddenLength> = self.hidden_length();
+        let p0_message_bits_cnt: usize =
+            p0_bits_cnt - hidden_length.as_ref().map_or(0, |h| h.length_bits_cnt());
+
+        //the initial state is public, its zero labels are all the garbler needs to flip
+        let mut last_evaluation_result: Vec<WireLabel> = vec![WireLabel::zero(); state_bits_len];
+
+        //hidden length: P0's length share labels come first, see `start_garbling`
+        let mut accumulator: Vec<WireLabel> = vec![WireLabel::zero(); state_bits_len];
+        let p0_length_labels: Vec<WireLabel> = match (&hidden_length, p0_labels) {
+            (None, _) => Vec::new(),
+            (Some(_), Some(labels)) => labels[p0_message_bits_cnt..].to_vec(),
+            (Some(_), None) => channel.recv()?,
+        };
+        if p0_length_labels.len() != p0_bits_cnt - p0_message_bits_cnt {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Received {} length share labels, expected {}",
+                    p0_length_labels.len(),
+                    p0_bits_cnt - p0_message_bits_cnt
+                ),
+            ));
+        }
+        for i in 0..block_cnt {
+            let block: GarbledBlock = channel.recv()?;
+            let left_start_index: usize = i * block_bits_len;
+            let right_end_index: usize = (i + 1) * block_bits_len;
+            //P0's bits are the first secret bits, the block holds those from index p0_left on
+            let p0_left: usize = left_start_index.saturating_sub(prefix_bits_cnt);
+            let p0_labels_cnt = match p0_labels {
+                Some(_) => 0,
+                None => right_end_index
+                    .saturating_sub(prefix_bits_cnt)
+                    .min(p0_message_bits_cnt)
+                    .saturating_sub(p0_left),
+            };
+            let mut adder_gates = self.adder_gates(left_start_index, right_end_index);
+            let selection_gates: Vec<XorAndGate> = match &hidden_length {
+                Some(hidden) => {
+                    let (message_gates, selection) =
+                        hidden.block_gates(i, &self.circuit.output_wire_ids);
+                    adder_gates.extend(message_gates);
+                    selection
+                }
+                None => Vec::new(),
+            };
+            let and_gates_cnt: usize = self.circuit.and_cnt
+                + adder_gates
+                    .iter()
+                    .chain(&selection_gates)
+                    .filter(|gate| gate.gate_type)
+                    .count();
+            if block.garbled_and.len() != and_gates_cnt || block.p0_labels.len() != p0_labels_cnt {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Garbled block {} holds {} tables and {} input labels, expected {} and {}",
+                        i,
+                        block.garbled_and.len(),
+                        block.p0_labels.len(),
+                        and_gates_cnt,
+                        p0_labels_cnt
+                    ),
+                ));
+            }
+            if block.garbled_and.mode() != self.garbling_mode {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Garbled block {} uses {:?} tables, expected {:?}",
+                        i,
+                        block.garbled_and.mode(),
+                        self.garbling_mode
+                    ),
+                ));
+            }
+
             // It stores every k-v: wire_number - evaluation results
             let mut evaluate_label_map: HashMap<usize, WireLabel> = HashMap::new();
-            //Process the first input: 512 bits block
+            //Process the first input: 512 bits block (1024 for SHA-512)
             let mut extra_double_gates: Vec<XorAndGate> = Vec::new();
-            let left_start_index: usize = i * SINGLE_BLOCK_BITS_LEN;
-            let right_end_index: usize = (i + 1) * SINGLE_BLOCK_BITS_LEN;
 
             for j in left_start_index..right_end_index {
-                let circuit_wire_id: usize =
-                    SINGLE_BLOCK_BITS_LEN - 1 - (j % SINGLE_BLOCK_BITS_LEN);
-                if j < secret_bits_cnt {
+                let circuit_wire_id: usize = block_bits_len - 1 - (j % block_bits_len);
+                let secret_index: Option<usize> = j
+                    .checked_sub(prefix_bits_cnt)
+                    .filter(|&s| s < message_bits_cnt);
+                if let (Some(s), InputMode::Concat { .. }) = (secret_index, self.input_mode) {
+                    //concatenated input: P0's bits come first, then P1's
+                    let label = if s >= p0_bits_cnt {
+                        p1_labels[s - p0_bits_cnt]
+                    } else {
+                        match p0_labels {
+                            Some(labels) => labels[s],
+                            None => block.p0_labels[s - p0_left],
+                        }
+                    };
+                    evaluate_label_map.insert(circuit_wire_id, label);
+                } else if let Some(s) = secret_index {
                     //real secret bits input
                     let gate = XorAndGate {
-                        input0: self.circuit.extra_input_wire + 2 * j,
-                        input1: self.circuit.extra_input_wire + 2 * j + 1,
+                        input0: self.circuit.extra_input_wire + 2 * s,
+                        input1: self.circuit.extra_input_wire + 2 * s + 1,
                         output: circuit_wire_id,
                         input0_flipped: false,
                         input1_flipped: false,
                         gate_type: false,
                     };
-                    evaluate_label_map.insert(gate.input0, ret.p0_labels[j]); //party-0 real_label
-                    evaluate_label_map.insert(gate.input1, p1_labels[j]); //party-1 real_label
-                    extra_double_gates.push(gate);
+                    let p0_label = match p0_labels {
+                        Some(labels) => labels[s],
+                        None => block.p0_labels[s - p0_left],
+                    };
+                    evaluate_label_map.insert(gate.input0, p0_label); //party-0 real_label
+                    evaluate_label_map.insert(gate.input1, p1_labels[s]); //party-1 real_label
+                    if self.input_mode == InputMode::Xor && hidden_length.is_none() {
+                        extra_double_gates.push(gate);
+                    }
                 } else {
-                    //padding bits
+                    //public bits: the rest of the prefix and the padding
                     evaluate_label_map.insert(circuit_wire_id, WireLabel::zero());
                     //public garbage input
                 }
             }
 
-            for (j, wire) in last_evaluation_result.iter().enumerate() {
-                evaluate_label_map.insert(SINGLE_BLOCK_BITS_LEN + j, *wire);
+            if let Some(hidden) = &hidden_length {
+                for (k, &label) in p0_length_labels.iter().enumerate() {
+                    evaluate_label_map.insert(hidden.length_wire(k, 0), label);
+                    evaluate_label_map
+                        .insert(hidden.length_wire(k, 1), p1_labels[message_bits_cnt + k]);
+                }
+                evaluate_label_map.insert(hidden.zero_wire(), WireLabel::zero());
+                evaluate_label_map.insert(hidden.one_wire(), WireLabel::zero());
+                for (k, &acc) in accumulator.iter().enumerate() {
+                    evaluate_label_map.insert(hidden.accumulator_wire(k), acc);
+                }
             }
 
             //update circuit gates
-            self.circuit.update_extra_circuit(&extra_double_gates);
+            extra_double_gates.extend(adder_gates);
+            self.evaluate_block(
+                &mut evaluator,
+                &mut evaluate_label_map,
+                &mut last_evaluation_result,
+                (&extra_double_gates, &selection_gates),
+                &block.garbled_and,
+            )?;
+            if let Some(hidden) = &hidden_length {
+                for (k, acc) in accumulator.iter_mut().enumerate() {
+                    *acc = evaluate_label_map[&hidden.selected_wire(k)];
+                }
+            }
+        }
 
-            let cur_garble_vec: Vec<GarbleAnd> = ret.garbled_and.drain(0..AND_GATES_CNT).collect();
-            let mut cur_garbled_gates: VecDeque<GarbleAnd> = VecDeque::from(cur_garble_vec); //Convert Vec to VecDeque
+        //the output of the final block, or the chaining value selected with a hidden length
+        let mut output_wires: Vec<WireLabel> = match hidden_length {
+            Some(_) => accumulator,
+            None => last_evaluation_result,
+        };
+        Ok(output_wires.split_off(self.circuit.digest_outputs().start))
+    }
+}
 
-            evaluator.evaluate(
-                &self.circuit,
-                &mut cur_garbled_gates,
-                &mut evaluate_label_map,
-            );
+//The labels of P0's actual input x0 || r in one cut-and-choose copy
+fn p0_input_labels(
+    garbler: &GarbledCircuit,
+    zero_labels: &[WireLabel],
+    pad_labels: &[WireLabel],
+    x0: &[bool],
+    pad_bits: &[bool],
+) -> Vec<WireLabel> {
+    zero_labels
+        .iter()
+        .zip(x0)
+        .chain(pad_labels.iter().zip(pad_bits))
+        .map(|(&zero, &bit)| if bit { zero ^ garbler.global_r } else { zero })
+        .collect()
+}
 
-            for (k, output_wire) in self.circuit.output_wire_ids.iter().enumerate() {
-                //final output logic
-                let mut real_id: usize = output_wire.id;
-                if output_wire.should_trace {
-                    //A not gate is applied here
-                    real_id = output_wire.input_id;
-                }
-                match evaluate_label_map.get(&real_id) {
-                    Some(wire) => {
-                        //derive permutation bits by last block
-                        if i < block_cnt - 1 {
-                            last_evaluation_result[k] = *wire;
-                        } else {
-                            //decrypt output
-                            let mut out_bit: bool = ret.permu_bits[k];
-                            out_bit ^= wire.check_lsb();
-                            output_bits.push(out_bit);
-                        }
-                    }
-                    None => {
-                        println!("Output wire {} not found!!", real_id);
-                    }
-                }
+//The wire an output is read from, the input of the not gate it comes out of if any
+fn output_source(output_wire: &OutputWire) -> usize {
+    if output_wire.should_trace {
+        output_wire.input_id
+    } else {
+        output_wire.id
+    }
+}
+
+//A session parameter that must be the same on both sides
+fn check_agreement<T: PartialEq + fmt::Debug>(what: &str, own: T, peer: T) -> io::Result<()> {
+    if own == peer {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "The parties disagree on the {}: {:?} here, {:?} at the peer",
+            what, own, peer
+        ),
+    ))
+}
+
+//Label of an output wire after a block, missing only if the circuit is malformed
+fn output_wire_label<T>(label_map: &HashMap<usize, T>, wire: usize) -> io::Result<&T> {
+    label_map.get(&wire).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Output wire {} has no label", wire),
+        )
+    })
+}
+
+//The output permutation bits: the last bit of every output label decoding to 0
+fn output_permutation_bits(output_zero_labels: &[WireLabel]) -> Vec<bool> {
+    output_zero_labels
+        .iter()
+        .map(|label| label.check_lsb())
+        .collect()
+}
+
+//Garbler: decode the output labels returned by the evaluator, rejecting any label that is
+//neither the zero label nor the zero label XOR R of its wire
+fn verify_output_labels(
+    output_zero_labels: &[WireLabel],
+    global_r: WireLabel,
+    channel: &mut impl Channel,
+) -> io::Result<Vec<bool>> {
+    let output_labels: Vec<WireLabel> = channel.recv()?;
+    if output_labels.len() != output_zero_labels.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Expected {} output labels, received {}",
+                output_zero_labels.len(),
+                output_labels.len()
+            ),
+        ));
+    }
+    output_labels
+        .iter()
+        .zip(output_zero_labels)
+        .enumerate()
+        .map(|(k, (&label, &zero))| {
+            if label == zero {
+                Ok(false)
+            } else if label == zero ^ global_r {
+                Ok(true)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Output label {} is not a valid label of its wire", k),
+                ))
             }
+        })
+        .collect()
+}
 
-            println!("Evaluator: {}/{} blocks evaluated.", i + 1, block_cnt);
-        }
-        output_bits.reverse();
-        convert_bits2_bytes(&output_bits)
+//Decrypt the output labels with the permutation bits sent by the garbler
+fn decode_output(output_labels: &[WireLabel], channel: &mut impl Channel) -> io::Result<Vec<bool>> {
+    let permu_bits: Vec<bool> = channel.recv()?;
+    if permu_bits.len() != output_labels.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Expected {} output permutation bits, received {}",
+                output_labels.len(),
+                permu_bits.len()
+            ),
+        ));
+    }
+    Ok(output_labels
+        .iter()
+        .zip(&permu_bits)
+        .map(|(label, &permu_bit)| permu_bit ^ label.check_lsb())
+        .collect())
+}
+
+fn random_label(rng: &mut impl Rng) -> WireLabel {
+    let mut label = [0u8; 16];
+    rng.fill(&mut label); // Fill the label's 16 bytes with random data
+    WireLabel::from_data(label)
+}
+
+//The circuit outputs the digest LSB first
+fn output_digest(output_bits: &[bool]) -> Vec<u8> {
+    let mut bits = output_bits.to_vec();
+    bits.reverse();
+    convert_bits2_bytes(&bits)
+}
+
+//Hash of the output labels of both circuits, P0's circuit first
+fn output_view(p0_circuit: &[WireLabel], p1_circuit: &[WireLabel]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for label in p0_circuit.iter().chain(p1_circuit) {
+        hasher.update(label.to_generic_array());
     }
+    hasher.finalize().into()
+}
+
+fn commit_view(view: &[u8; 32], nonce: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(view);
+    hasher.update(nonce);
+    hasher.finalize().into()
 }
@@ -0,0 +1,323 @@
+use crate::circuit::SHA512_BLOCK_BITS_LEN;
+use crate::circuit::SHA512_STATE_BITS_LEN;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const WORD_BITS: usize = 64;
+const STATE_WORDS: usize = SHA512_STATE_BITS_LEN / WORD_BITS;
+
+//K_t of FIPS 180-4, section 4.2.3
+const ROUND_CONSTANTS: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+//A 64-bit word, the wire of every bit from the least significant one
+type Word = Vec<usize>;
+
+enum Gate {
+    Xor(usize, usize, usize),
+    And(usize, usize, usize),
+    Inv(usize, usize),
+}
+
+//Gate list with fresh output wires
+struct GateBuilder {
+    gates: Vec<Gate>,
+    next_wire: usize,
+}
+
+impl GateBuilder {
+    fn fresh(&mut self) -> usize {
+        let wire: usize = self.next_wire;
+        self.next_wire += 1;
+        wire
+    }
+
+    fn xor(&mut self, input0: usize, input1: usize) -> usize {
+        let output: usize = self.fresh();
+        self.gates.push(Gate::Xor(input0, input1, output));
+        output
+    }
+
+    fn and(&mut self, input0: usize, input1: usize) -> usize {
+        let output: usize = self.fresh();
+        self.gates.push(Gate::And(input0, input1, output));
+        output
+    }
+
+    fn inv(&mut self, input: usize) -> usize {
+        let output: usize = self.fresh();
+        self.gates.push(Gate::Inv(input, output));
+        output
+    }
+
+    //x + y mod 2^64, the carry is c' = c ⊕ ((x ⊕ c) ∧ (y ⊕ c))
+    fn add(&mut self, x: &Word, y: &Word) -> Word {
+        let mut sum: Word = Vec::with_capacity(WORD_BITS);
+        let mut carry: Option<usize> = None; //None while it is constant 0
+        for b in 0..WORD_BITS {
+            let last: bool = b == WORD_BITS - 1;
+            match carry {
+                None => {
+                    sum.push(self.xor(x[b], y[b]));
+                    if !last {
+                        carry = Some(self.and(x[b], y[b]));
+                    }
+                }
+                Some(c) => {
+                    let xc: usize = self.xor(x[b], c);
+                    sum.push(self.xor(xc, y[b]));
+                    if !last {
+                        let yc: usize = self.xor(y[b], c);
+                        let both: usize = self.and(xc, yc);
+                        carry = Some(self.xor(c, both));
+                    }
+                }
+            }
+        }
+        sum
+    }
+
+    //x + k mod 2^64 for a public k, the carry is x ∨ c where k has a 1 and x ∧ c elsewhere
+    #[allow(clippy::needless_range_loop)]
+    fn add_constant(&mut self, x: &Word, k: u64) -> Word {
+        let mut sum: Word = Vec::with_capacity(WORD_BITS);
+        let mut carry: Option<usize> = None;
+        for b in 0..WORD_BITS {
+            let bit: bool = (k >> b) & 1 == 1;
+            let last: bool = b == WORD_BITS - 1;
+            match carry {
+                None if bit => {
+                    sum.push(self.inv(x[b]));
+                    carry = Some(x[b]);
+                }
+                None => sum.push(x[b]),
+                Some(c) => {
+                    let xc: usize = self.xor(x[b], c);
+                    sum.push(if bit { self.inv(xc) } else { xc });
+                    if !last {
+                        //x ∨ c = (x ⊕ c) ⊕ (x ∧ c)
+                        let both: usize = self.and(x[b], c);
+                        carry = Some(if bit { self.xor(xc, both) } else { both });
+                    }
+                }
+            }
+        }
+        sum
+    }
+
+    //ROTR^r0(x) ⊕ ROTR^r1(x) ⊕ ROTR^r2(x)
+    fn big_sigma(&mut self, x: &Word, rotations: [usize; 3]) -> Word {
+        (0..WORD_BITS)
+            .map(|b| {
+                let [r0, r1, r2] = rotations.map(|r| x[(b + r) % WORD_BITS]);
+                let t: usize = self.xor(r0, r1);
+                self.xor(t, r2)
+            })
+            .collect()
+    }
+
+    //ROTR^r0(x) ⊕ ROTR^r1(x) ⊕ SHR^s(x)
+    fn small_sigma(&mut self, x: &Word, rotations: [usize; 2], shift: usize) -> Word {
+        (0..WORD_BITS)
+            .map(|b| {
+                let t: usize = self.xor(
+                    x[(b + rotations[0]) % WORD_BITS],
+                    x[(b + rotations[1]) % WORD_BITS],
+                );
+                match x.get(b + shift) {
+                    Some(&shifted) => self.xor(t, shifted),
+                    None => t,
+                }
+            })
+            .collect()
+    }
+
+    //Ch(e, f, g) = g ⊕ (e ∧ (f ⊕ g))
+    fn choose(&mut self, e: &Word, f: &Word, g: &Word) -> Word {
+        (0..WORD_BITS)
+            .map(|b| {
+                let fg: usize = self.xor(f[b], g[b]);
+                let chosen: usize = self.and(e[b], fg);
+                self.xor(g[b], chosen)
+            })
+            .collect()
+    }
+
+    //Maj(a, b, c) = a ⊕ ((a ⊕ b) ∧ (a ⊕ c))
+    fn majority(&mut self, a: &Word, b: &Word, c: &Word) -> Word {
+        (0..WORD_BITS)
+            .map(|i| {
+                let ab: usize = self.xor(a[i], b[i]);
+                let ac: usize = self.xor(a[i], c[i]);
+                let both: usize = self.and(ab, ac);
+                self.xor(a[i], both)
+            })
+            .collect()
+    }
+}
+
+//The wires of word `idx` of a big-endian bit string held LSB first by `bits_len` wires from `base`
+fn input_word(base: usize, bits_len: usize, idx: usize) -> Word {
+    let lsb: usize = base + bits_len - WORD_BITS * (idx + 1);
+    (lsb..lsb + WORD_BITS).collect()
+}
+
+/// The SHA-512 compression function in Bristol format, with the layout of the SHA-256 circuit
+/// in `data/`: the 1024-bit block on wires 0..1024 and the 512-bit chaining value on the next
+/// 512 wires, both LSB first, and the updated chaining value on the last 512 wires.
+pub fn bristol_circuit() -> String {
+    let mut builder = GateBuilder {
+        gates: Vec::new(),
+        next_wire: SHA512_BLOCK_BITS_LEN + SHA512_STATE_BITS_LEN,
+    };
+
+    //message schedule
+    let mut schedule: Vec<Word> = (0..SHA512_BLOCK_BITS_LEN / WORD_BITS)
+        .map(|t| input_word(0, SHA512_BLOCK_BITS_LEN, t))
+        .collect();
+    for t in schedule.len()..ROUND_CONSTANTS.len() {
+        let s1 = builder.small_sigma(&schedule[t - 2], [19, 61], 6);
+        let s0 = builder.small_sigma(&schedule[t - 15], [1, 8], 7);
+        let sum = builder.add(&s1, &schedule[t - 7]);
+        let sum = builder.add(&sum, &s0);
+        let word = builder.add(&sum, &schedule[t - 16]);
+        schedule.push(word);
+    }
+
+    let initial: Vec<Word> = (0..STATE_WORDS)
+        .map(|i| input_word(SHA512_BLOCK_BITS_LEN, SHA512_STATE_BITS_LEN, i))
+        .collect();
+    let mut state: Vec<Word> = initial.clone(); //a, b, c, d, e, f, g, h
+    for (t, &k) in ROUND_CONSTANTS.iter().enumerate() {
+        let s1 = builder.big_sigma(&state[4], [14, 18, 41]);
+        let ch = builder.choose(&state[4], &state[5], &state[6]);
+        let t1 = builder.add(&state[7], &s1);
+        let t1 = builder.add(&t1, &ch);
+        let t1 = builder.add_constant(&t1, k);
+        let t1 = builder.add(&t1, &schedule[t]);
+        let s0 = builder.big_sigma(&state[0], [28, 34, 39]);
+        let maj = builder.majority(&state[0], &state[1], &state[2]);
+        let t2 = builder.add(&s0, &maj);
+
+        state.pop();
+        state[3] = builder.add(&state[3], &t1);
+        let a = builder.add(&t1, &t2);
+        state.insert(0, a);
+    }
+    let outputs: Vec<Word> = initial
+        .iter()
+        .zip(&state)
+        .map(|(h, x)| builder.add(h, x))
+        .collect();
+
+    //move the output bits onto the last wires, word 0 holding the most significant ones
+    let wires_cnt: usize = builder.next_wire + SHA512_STATE_BITS_LEN;
+    let mut renamed: HashMap<usize, usize> = HashMap::new();
+    for (i, word) in outputs.iter().enumerate() {
+        for (b, &wire) in word.iter().enumerate() {
+            renamed.insert(wire, wires_cnt - WORD_BITS * (i + 1) + b);
+        }
+    }
+    let name = |wire: usize| *renamed.get(&wire).unwrap_or(&wire);
+
+    let mut text = String::new();
+    writeln!(text, "{} {}", builder.gates.len(), wires_cnt).unwrap();
+    writeln!(
+        text,
+        "2 {} {}",
+        SHA512_BLOCK_BITS_LEN, SHA512_STATE_BITS_LEN
+    )
+    .unwrap();
+    writeln!(text, "1 {}", SHA512_STATE_BITS_LEN).unwrap();
+    writeln!(text).unwrap();
+    for gate in &builder.gates {
+        match *gate {
+            Gate::Xor(a, b, out) => writeln!(text, "2 1 {} {} {} XOR", name(a), name(b), name(out)),
+            Gate::And(a, b, out) => writeln!(text, "2 1 {} {} {} AND", name(a), name(b), name(out)),
+            Gate::Inv(a, out) => writeln!(text, "1 1 {} {} INV", name(a), name(out)),
+        }
+        .unwrap();
+    }
+    text
+}
@@ -0,0 +1,497 @@
+use crate::gc::WireLabel;
+use crate::gc::LABEL_SECURITY_LEVEL;
+use crate::ot::OtChoices;
+use crate::ot::OtSetup;
+use crate::ot::OtTransfer;
+use crate::ot_ext::cr_hash;
+use crate::ot_ext::get_bit;
+use crate::ot_ext::pack_bits;
+use crate::ot_ext::IknpExtend;
+use crate::ot_ext::IknpReceiver;
+use crate::ot_ext::IknpSender;
+
+use aes::cipher::generic_array::GenericArray;
+use aes::{Aes128, BlockEncrypt, NewBlockCipher};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Length k of the LPN secret, i.e. base correlated OTs consumed per iteration.
+pub const LPN_SECRET_LEN: usize = 40_960;
+/// Non-zero entries per row of the sparse LPN matrix A.
+pub const LPN_ROW_WEIGHT: usize = 10;
+/// Depth of the GGM tree of every single-point COT.
+pub const GGM_DEPTH: usize = 9;
+/// Outputs covered by one single-point COT, the noise is regular: one error per bucket.
+pub const BUCKET_SIZE: usize = 1 << GGM_DEPTH;
+/// Noise weight t of a full iteration.
+pub const BUCKET_CNT: usize = 1_280;
+/// Correlated OTs n produced by a full iteration.
+pub const LPN_OUTPUT_LEN: usize = BUCKET_CNT * BUCKET_SIZE;
+/// Fewer transferred pairs than this are sent through the IKNP extension directly. An LPN
+/// iteration saves about 120 bits per pair (a 128-bit column of U against one derandomization
+/// bit and the GGM messages), which only pays for its `LPN_SECRET_LEN` base COTs from here on.
+pub const SILENT_MIN_OUTPUTS: usize = 44_000;
+
+/// Silent OT in the style of Ferret (Yang–Weng–Lan–Zhang–Wang, CCS 2020).
+///
+/// The expensive part of the transfer is produced locally from a small amount of correlated
+/// randomness: from `LPN_SECRET_LEN` base COTs `(K_i, M_i = K_i ^ u_i * s)` and a regular sparse
+/// noise vector `e` (one GGM-tree single-point COT per bucket) both parties expand
+/// `K = A * K_base ^ v` and `M = A * M_base ^ w = K ^ (A * u ^ e) * s`, which are
+/// `LPN_OUTPUT_LEN` random correlated OTs for the pseudorandom LPN choice bits `b = A * u ^ e`.
+/// The base COTs (LPN secret and GGM levels) are bootstrapped from the KOS-checked IKNP extension.
+///
+/// The flows match the IKNP extension:
+/// 1. receiver -> sender: `OtSetup` of the base OTs;
+/// 2. sender -> receiver: `OtChoices`;
+/// 3. receiver -> sender: `SilentExtend`, the base COT extension and the derandomization bits
+///    `d_j = c_j ^ b_j` (one bit per transferred pair);
+/// 4. sender -> receiver: `SilentTransfer`, the GGM level messages and every pair masked by
+///    `H(K_j ^ d_j * s)` and `H(K_j ^ !d_j * s)`.
+///
+/// Only the buckets covering the requested outputs are expanded, and below `SILENT_MIN_OUTPUTS`
+/// pairs no iteration runs at all: the IKNP extension transfers them itself, so the provider never
+/// costs more than plain IKNP. The single-point COTs carry no consistency check, this provider is
+/// semi-honest.
+#[derive(Debug)]
+pub struct SilentSender {
+    iknp: IknpSender,
+}
+
+#[derive(Debug)]
+pub struct SilentReceiver {
+    choice_bits: Vec<bool>,
+    iknp: IknpReceiver,
+    lpn_secrets: Vec<Vec<bool>>,      //u, one per iteration
+    noise_positions: Vec<Vec<usize>>, //alpha of every bucket, one list per iteration
+}
+
+/// Third flow: base COT extension and the derandomization bits.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SilentExtend {
+    pub extend: IknpExtend,
+    pub corrections: Vec<u8>,
+}
+
+/// Fourth flow: GGM level messages, the single-point corrections and the masked pairs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SilentTransfer {
+    pub levels: Vec<(WireLabel, WireLabel)>, //GGM_DEPTH masked level sums per bucket
+    pub leaf_sums: Vec<WireLabel>,           //s ^ (sum of the leaves) per bucket
+    pub transfer: OtTransfer,
+}
+
+//Which outputs and which base COTs belong to one LPN iteration
+struct Iteration {
+    outputs_offset: usize,
+    outputs_cnt: usize,
+    buckets_cnt: usize,
+    base_offset: usize,
+}
+
+impl Iteration {
+    fn ggm_offset(&self, bucket: usize) -> usize {
+        self.base_offset + LPN_SECRET_LEN + bucket * GGM_DEPTH
+    }
+}
+
+//No iteration below `SILENT_MIN_OUTPUTS`, see `SilentSender`
+fn iterations(outputs_cnt: usize) -> Vec<Iteration> {
+    let mut layouts: Vec<Iteration> = Vec::new();
+    if outputs_cnt < SILENT_MIN_OUTPUTS {
+        return layouts;
+    }
+    let mut outputs_offset: usize = 0;
+    let mut base_offset: usize = 0;
+    while outputs_offset < outputs_cnt {
+        let cnt = LPN_OUTPUT_LEN.min(outputs_cnt - outputs_offset);
+        let buckets_cnt = cnt.div_ceil(BUCKET_SIZE);
+        layouts.push(Iteration {
+            outputs_offset,
+            outputs_cnt: cnt,
+            buckets_cnt,
+            base_offset,
+        });
+        outputs_offset += cnt;
+        base_offset += LPN_SECRET_LEN + buckets_cnt * GGM_DEPTH;
+    }
+    layouts
+}
+
+fn base_cots_cnt(layouts: &[Iteration]) -> usize {
+    layouts
+        .iter()
+        .map(|it| LPN_SECRET_LEN + it.buckets_cnt * GGM_DEPTH)
+        .sum()
+}
+
+//Public sparse matrix A, row j has LPN_ROW_WEIGHT pseudorandom columns in [0, LPN_SECRET_LEN)
+struct LpnMatrix {
+    cipher: Aes128,
+}
+
+impl LpnMatrix {
+    fn new(iteration: usize) -> Self {
+        let mut key = [0u8; LABEL_SECURITY_LEVEL];
+        key[..8].copy_from_slice(b"lpn-code");
+        key[8..].copy_from_slice(&(iteration as u64).to_le_bytes());
+        LpnMatrix {
+            cipher: Aes128::new(GenericArray::from_slice(&key)),
+        }
+    }
+
+    fn row(&self, j: usize) -> [usize; LPN_ROW_WEIGHT] {
+        let mut words = [0u32; 12];
+        for b in 0..3 {
+            let mut block = GenericArray::from(((3 * j + b) as u128).to_le_bytes());
+            self.cipher.encrypt_block(&mut block);
+            for w in 0..4 {
+                let bytes: [u8; 4] = block[4 * w..4 * w + 4].try_into().unwrap();
+                words[4 * b + w] = u32::from_le_bytes(bytes);
+            }
+        }
+        let mut row = [0usize; LPN_ROW_WEIGHT];
+        for (col, word) in row.iter_mut().zip(words) {
+            *col = word as usize % LPN_SECRET_LEN;
+        }
+        row
+    }
+}
+
+//Length-doubling PRG of the GGM tree: G(x) = (AES_k0(x) ^ x, AES_k1(x) ^ x)
+struct GgmPrg {
+    left: Aes128,
+    right: Aes128,
+}
+
+impl GgmPrg {
+    fn new() -> Self {
+        GgmPrg {
+            left: Aes128::new(GenericArray::from_slice(b"ggm-tree-left-ch")),
+            right: Aes128::new(GenericArray::from_slice(b"ggm-tree-right-c")),
+        }
+    }
+
+    fn expand(&self, seed: &WireLabel) -> (WireLabel, WireLabel) {
+        let mut left = seed.to_generic_array();
+        let mut right = seed.to_generic_array();
+        self.left.encrypt_block(&mut left);
+        self.right.encrypt_block(&mut right);
+        (
+            WireLabel::from_data(left.into()) ^ *seed,
+            WireLabel::from_data(right.into()) ^ *seed,
+        )
+    }
+}
+
+fn alpha_bit(alpha: usize, level: usize) -> bool {
+    (alpha >> (GGM_DEPTH - 1 - level)) & 1 == 1
+}
+
+fn random_label(rng: &mut impl Rng) -> WireLabel {
+    let mut data = [0u8; LABEL_SECURITY_LEVEL];
+    rng.fill(&mut data);
+    WireLabel::from_data(data)
+}
+
+fn lpn_combine(base: &[WireLabel], row: &[usize; LPN_ROW_WEIGHT]) -> WireLabel {
+    let mut acc = WireLabel::zero();
+    for &col in row {
+        acc ^= base[col];
+    }
+    acc
+}
+
+impl SilentSender {
+    pub fn new(setup: &OtSetup) -> io::Result<(Self, OtChoices)> {
+        let (iknp, choices) = IknpSender::new(setup)?;
+        Ok((SilentSender { iknp }, choices))
+    }
+
+    pub fn transfer(
+        &self,
+        msg: &SilentExtend,
+        messages: &[(WireLabel, WireLabel)],
+    ) -> io::Result<SilentTransfer> {
+        let layouts = iterations(messages.len());
+        //a transfer through IKNP needs no derandomization
+        let corrections_cnt: usize = layouts.iter().map(|it| it.outputs_cnt).sum();
+        if msg.corrections.len() != corrections_cnt.div_ceil(8) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Silent OT: derandomization bits do not match the transferred pairs",
+            ));
+        }
+        if layouts.is_empty() {
+            return Ok(SilentTransfer {
+                levels: Vec::new(),
+                leaf_sums: Vec::new(),
+                transfer: self.iknp.transfer(&msg.extend, messages)?,
+            });
+        }
+        let base_cnt = base_cots_cnt(&layouts);
+        let q_rows = self.iknp.correlated(&msg.extend, base_cnt)?;
+        let delta = self.iknp.delta();
+
+        let mut rng = rand::thread_rng();
+        let prg = GgmPrg::new();
+        let mut levels: Vec<(WireLabel, WireLabel)> = Vec::new();
+        let mut leaf_sums: Vec<WireLabel> = Vec::new();
+        let mut ciphertexts: Vec<(WireLabel, WireLabel)> = Vec::with_capacity(messages.len());
+
+        for (it_idx, it) in layouts.iter().enumerate() {
+            //Step-0: one single-point COT per bucket, the sender knows every leaf v
+            let mut leaves: Vec<WireLabel> = Vec::with_capacity(it.buckets_cnt * BUCKET_SIZE);
+            for bucket in 0..it.buckets_cnt {
+                let mut nodes: Vec<WireLabel> = vec![random_label(&mut rng)];
+                for level in 0..GGM_DEPTH {
+                    let mut children: Vec<WireLabel> = Vec::with_capacity(nodes.len() * 2);
+                    let mut left_sum = WireLabel::zero();
+                    let mut right_sum = WireLabel::zero();
+                    for node in &nodes {
+                        let (left, right) = prg.expand(node);
+                        left_sum ^= left;
+                        right_sum ^= right;
+                        children.push(left);
+                        children.push(right);
+                    }
+                    let idx = it.ggm_offset(bucket) + level;
+                    let q = q_rows[idx];
+                    levels.push((
+                        left_sum ^ cr_hash(idx, &q),
+                        right_sum ^ cr_hash(idx, &(q ^ delta)),
+                    ));
+                    nodes = children;
+                }
+                let mut sum = delta;
+                for leaf in &nodes {
+                    sum ^= *leaf;
+                }
+                leaf_sums.push(sum);
+                leaves.extend(nodes);
+            }
+
+            //Step-1: LPN expansion K_j = A_j * K_base ^ v_j, then derandomize onto the real pairs
+            let k_base = &q_rows[it.base_offset..it.base_offset + LPN_SECRET_LEN];
+            let matrix = LpnMatrix::new(it_idx);
+            for (j, leaf) in leaves.iter().enumerate().take(it.outputs_cnt) {
+                let global = it.outputs_offset + j;
+                let key = lpn_combine(k_base, &matrix.row(j)) ^ *leaf;
+                let (m0, m1) = messages[global];
+                let (pad0, pad1) = if get_bit(&msg.corrections, global) {
+                    (key ^ delta, key)
+                } else {
+                    (key, key ^ delta)
+                };
+                ciphertexts.push((
+                    m0 ^ cr_hash(base_cnt + global, &pad0),
+                    m1 ^ cr_hash(base_cnt + global, &pad1),
+                ));
+            }
+        }
+
+        Ok(SilentTransfer {
+            levels,
+            leaf_sums,
+            transfer: OtTransfer { ciphertexts },
+        })
+    }
+}
+
+impl SilentReceiver {
+    pub fn new(choice_bits: &[bool]) -> (Self, OtSetup) {
+        let mut rng = rand::thread_rng();
+        let layouts = iterations(choice_bits.len());
+
+        let mut lpn_secrets: Vec<Vec<bool>> = Vec::with_capacity(layouts.len());
+        let mut noise_positions: Vec<Vec<usize>> = Vec::with_capacity(layouts.len());
+        let mut base_choices: Vec<bool> = Vec::with_capacity(base_cots_cnt(&layouts));
+        if layouts.is_empty() {
+            //the IKNP extension transfers the pairs for the real choice bits
+            base_choices.extend(choice_bits);
+        }
+        for it in &layouts {
+            let secret: Vec<bool> = (0..LPN_SECRET_LEN).map(|_| rng.gen::<bool>()).collect();
+            let alphas: Vec<usize> = (0..it.buckets_cnt)
+                .map(|_| rng.gen_range(0..BUCKET_SIZE))
+                .collect();
+            base_choices.extend(&secret);
+            //The receiver must learn the level sum on the side off its punctured path
+            for &alpha in &alphas {
+                base_choices.extend((0..GGM_DEPTH).map(|level| !alpha_bit(alpha, level)));
+            }
+            lpn_secrets.push(secret);
+            noise_positions.push(alphas);
+        }
+
+        let (iknp, setup) = IknpReceiver::new(&base_choices);
+        (
+            SilentReceiver {
+                choice_bits: choice_bits.to_vec(),
+                iknp,
+                lpn_secrets,
+                noise_positions,
+            },
+            setup,
+        )
+    }
+
+    //The receiver's pseudorandom choice bits b = A * u ^ e are known before any expansion
+    pub fn extend(&mut self, choices: &OtChoices) -> io::Result<SilentExtend> {
+        let extend = self.iknp.extend(choices)?;
+
+        let mut corrections: Vec<bool> = Vec::with_capacity(self.choice_bits.len());
+        for (it_idx, it) in iterations(self.choice_bits.len()).iter().enumerate() {
+            let matrix = LpnMatrix::new(it_idx);
+            let secret = &self.lpn_secrets[it_idx];
+            for j in 0..it.outputs_cnt {
+                let mut b = self.noise_positions[it_idx][j / BUCKET_SIZE] == j % BUCKET_SIZE;
+                for col in matrix.row(j) {
+                    b ^= secret[col];
+                }
+                corrections.push(self.choice_bits[it.outputs_offset + j] ^ b);
+            }
+        }
+
+        Ok(SilentExtend {
+            extend,
+            corrections: pack_bits(&corrections),
+        })
+    }
+
+    //Rebuild every GGM leaf except the punctured one, w_alpha = v_alpha ^ s comes from the leaf sum
+    fn punctured_leaves(
+        &self,
+        alpha: usize,
+        levels: &[(WireLabel, WireLabel)],
+        leaf_sum: &WireLabel,
+        t_rows: &[WireLabel],
+        ggm_offset: usize,
+        prg: &GgmPrg,
+    ) -> Vec<WireLabel> {
+        let mut nodes: Vec<WireLabel> = vec![WireLabel::zero()];
+        let mut path: usize = 0;
+        for (level, (left_masked, right_masked)) in levels.iter().enumerate() {
+            let on_path = alpha_bit(alpha, level);
+            let idx = ggm_offset + level;
+            //the chosen bit is !on_path, so t_idx unmasks the opposite side's sum
+            let mut off_path_sum = if on_path {
+                *left_masked ^ cr_hash(idx, &t_rows[idx])
+            } else {
+                *right_masked ^ cr_hash(idx, &t_rows[idx])
+            };
+
+            let mut children: Vec<WireLabel> = vec![WireLabel::zero(); nodes.len() * 2];
+            for (i, node) in nodes.iter().enumerate() {
+                if i == path {
+                    continue;
+                }
+                let (left, right) = prg.expand(node);
+                children[2 * i] = left;
+                children[2 * i + 1] = right;
+                off_path_sum ^= if on_path { left } else { right };
+            }
+            children[2 * path + usize::from(!on_path)] = off_path_sum;
+            path = 2 * path + usize::from(on_path);
+            nodes = children;
+        }
+
+        let mut punctured = *leaf_sum;
+        for (i, leaf) in nodes.iter().enumerate() {
+            if i != path {
+                punctured ^= *leaf;
+            }
+        }
+        nodes[path] = punctured;
+        nodes
+    }
+
+    /// Expand the correlated OTs locally and unmask the chosen message of every pair.
+    pub fn receive(&self, msg: &SilentTransfer) -> io::Result<Vec<WireLabel>> {
+        let layouts = iterations(self.choice_bits.len());
+        let buckets_cnt: usize = layouts.iter().map(|it| it.buckets_cnt).sum();
+        if msg.transfer.ciphertexts.len() != self.choice_bits.len()
+            || msg.leaf_sums.len() != buckets_cnt
+            || msg.levels.len() != buckets_cnt * GGM_DEPTH
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Silent OT: malformed transfer message",
+            ));
+        }
+        if layouts.is_empty() {
+            return self.iknp.receive(&msg.transfer);
+        }
+        let base_cnt = base_cots_cnt(&layouts);
+        let t_rows = self.iknp.correlated();
+        let prg = GgmPrg::new();
+
+        let mut labels: Vec<WireLabel> = Vec::with_capacity(self.choice_bits.len());
+        let mut bucket_idx: usize = 0;
+        for (it_idx, it) in layouts.iter().enumerate() {
+            let mut leaves: Vec<WireLabel> = Vec::with_capacity(it.buckets_cnt * BUCKET_SIZE);
+            for (bucket, &alpha) in self.noise_positions[it_idx].iter().enumerate() {
+                leaves.extend(self.punctured_leaves(
+                    alpha,
+                    &msg.levels[bucket_idx * GGM_DEPTH..(bucket_idx + 1) * GGM_DEPTH],
+                    &msg.leaf_sums[bucket_idx],
+                    t_rows,
+                    it.ggm_offset(bucket),
+                    &prg,
+                ));
+                bucket_idx += 1;
+            }
+
+            let m_base = &t_rows[it.base_offset..it.base_offset + LPN_SECRET_LEN];
+            let matrix = LpnMatrix::new(it_idx);
+            for (j, leaf) in leaves.iter().enumerate().take(it.outputs_cnt) {
+                let global = it.outputs_offset + j;
+                let mac = lpn_combine(m_base, &matrix.row(j)) ^ *leaf;
+                let (y0, y1) = msg.transfer.ciphertexts[global];
+                let masked = if self.choice_bits[global] { y1 } else { y0 };
+                labels.push(masked ^ cr_hash(base_cnt + global, &mac));
+            }
+        }
+        Ok(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(cnt: usize) -> SilentTransfer {
+        let mut rng = rand::thread_rng();
+        let messages: Vec<(WireLabel, WireLabel)> = (0..cnt)
+            .map(|_| (random_label(&mut rng), random_label(&mut rng)))
+            .collect();
+        let choice_bits: Vec<bool> = (0..messages.len()).map(|_| rng.gen()).collect();
+
+        let (mut receiver, setup) = SilentReceiver::new(&choice_bits);
+        let (sender, choices) = SilentSender::new(&setup).unwrap();
+        let extend = receiver.extend(&choices).unwrap();
+        let transfer = sender.transfer(&extend, &messages).unwrap();
+        let received = receiver.receive(&transfer).unwrap();
+
+        assert_eq!(received.len(), messages.len());
+        for ((label, (m0, m1)), &bit) in received.iter().zip(&messages).zip(&choice_bits) {
+            assert_eq!(label, if bit { m1 } else { m0 });
+        }
+        transfer
+    }
+
+    #[test]
+    fn receiver_gets_the_chosen_labels() {
+        //the last bucket is only partly used
+        let transfer = transfer(SILENT_MIN_OUTPUTS + 77);
+        let buckets_cnt = (SILENT_MIN_OUTPUTS + 77).div_ceil(BUCKET_SIZE);
+        assert_eq!(transfer.leaf_sums.len(), buckets_cnt);
+    }
+
+    #[test]
+    fn short_transfers_fall_back_to_iknp() {
+        let transfer = transfer(2 * BUCKET_SIZE + 77);
+        assert!(transfer.levels.is_empty() && transfer.leaf_sums.is_empty());
+    }
+}
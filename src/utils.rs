@@ -1,3 +1,5 @@
+use crate::circuit::HashFunction;
+use sha2::digest::generic_array::GenericArray;
 use sha2::{Digest, Sha256};
 
 // Input label and output label are very well done..
@@ -12,27 +14,71 @@ pub fn sha256(message: &[u8]) -> String {
     hex::encode(result)
 }
 
-// From message lengh (in bits), compute the padding bits up to a whole block (512 bits)
-pub fn get_padded_bits(bytes_len: usize) -> Vec<bool> {
-    let message_bits_cnt: u64 = (bytes_len * 8) as u64;
+// From message lengh (in bytes), compute the padding bits up to a whole block of `hash_function`
+pub fn get_padded_bits(bytes_len: usize, hash_function: HashFunction) -> Vec<bool> {
+    pad_message_bits(bytes_len * 8, hash_function)
+}
 
-    let mut padding: Vec<u8> = vec![0; bytes_len];
-    // Step 2: Append 0x80 (which is 128 in decimal)
-    padding.push(0x80);
-    // Step 3: Append 0x00 until the message length (in bits) + 64 is a multiple of 512
-    while (padding.len() * 8 + 64) % 512 != 0 {
-        padding.push(0x00);
+// From message length (in bits), compute the padding bits up to a whole block (512 bits, 1024 for
+// SHA-512): a 1 bit right after the message, zeros, then the message length as a 64-bit (128-bit)
+// big-endian integer. The message bits themselves are left as zeros
+pub fn pad_message_bits(message_bits_cnt: usize, hash_function: HashFunction) -> Vec<bool> {
+    let block_bits_len: usize = hash_function.block_bits_len();
+    let length_field_bits_len: usize = hash_function.length_field_bits_len();
+    let mut padding: Vec<bool> = vec![false; message_bits_cnt];
+    padding.push(true);
+    while !(padding.len() + length_field_bits_len).is_multiple_of(block_bits_len) {
+        padding.push(false);
     }
-    // Step 4: Append the original length as an 8-byte (64-bit) big-endian integer
-    padding.extend(&message_bits_cnt.to_be_bytes());
+    let length_bytes = (message_bits_cnt as u128).to_be_bytes();
+    padding.extend(convert_bytes2_bits(
+        &length_bytes[length_bytes.len() - length_field_bits_len / 8..],
+    ));
 
-    // Assert that the final message length is a multiple of 512 bits
+    // Assert that the final message length is a multiple of the block length
     assert!(
-        (padding.len() * 8) % 512 == 0,
+        padding.len().is_multiple_of(block_bits_len),
         "Padding did not complete properly!"
     );
+    padding
+}
+
+// Compress whole blocks in the clear from the initial hash values of `hash_function`, the midstate
+// is returned in the bit order of the circuit's state wires (like `Sha256Circuit::initial_hash_vec`)
+pub fn midstate_bits(hash_function: HashFunction, blocks: &[u8]) -> Vec<bool> {
+    let block_bytes: usize = hash_function.block_bits_len() / 8;
+    assert!(
+        blocks.len().is_multiple_of(block_bytes),
+        "The compressed prefix must be made of whole blocks!"
+    );
+    let initial_hash_values: &[u8] = hash_function.initial_hash_values();
+    let state_bytes: Vec<u8> = if hash_function.is_sha512_family() {
+        let mut state = [0u64; 8];
+        for (word, bytes) in state.iter_mut().zip(initial_hash_values.chunks(8)) {
+            *word = u64::from_be_bytes(bytes.try_into().unwrap());
+        }
+        let blocks: Vec<_> = blocks
+            .chunks(block_bytes)
+            .map(|block| *GenericArray::from_slice(block))
+            .collect();
+        sha2::compress512(&mut state, &blocks);
+        state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    } else {
+        let mut state = [0u32; 8];
+        for (word, bytes) in state.iter_mut().zip(initial_hash_values.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let blocks: Vec<_> = blocks
+            .chunks(block_bytes)
+            .map(|block| *GenericArray::from_slice(block))
+            .collect();
+        sha2::compress256(&mut state, &blocks);
+        state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    };
 
-    convert_bytes2_bits(&padding)
+    let mut bits = convert_bytes2_bits(&state_bytes);
+    bits.reverse();
+    bits
 }
 
 pub fn convert_bytes2_bits(message: &[u8]) -> Vec<bool> {
@@ -50,7 +96,7 @@ pub fn convert_bytes2_bits(message: &[u8]) -> Vec<bool> {
 //assume len(bits) is a multiple of 8
 pub fn convert_bits2_bytes(bits: &[bool]) -> Vec<u8> {
     assert!(
-        bits.len() % 8 == 0,
+        bits.len().is_multiple_of(8),
         "The bits length must be a mulitple of 8!"
     );
     let mut output_bytes: Vec<u8> = Vec::new();
@@ -65,157 +111,3 @@ pub fn convert_bits2_bytes(bits: &[bool]) -> Vec<u8> {
     }
     output_bytes
 }
-
-// pub fn plaintext_block_eval( circuit: &mut Sha256Circuit, evaluation_map: &mut HashMap<usize, PlainEvalWire>,  output_bits: &mut Vec<bool>,
-// ) {
-//     //----------Step-0: Evaluate the single block circuit gate by gate------------//
-//     for gate in &circuit.extra_gates {
-//         // let mut input0:bool = false;
-//         // let mut input1:bool = false;
-//         match evaluation_map.get(&gate.input0) {
-//             Some(value) => {
-//                 //this evaluation value might also be flipped
-//                 match evaluation_map.get(&gate.input1) {
-//                     Some(value1) => {
-//                         //this evaluation value might also be flipped
-//                         let mut out: bool = false;
-//                         let mut out_flipped: bool = false;
-//                         if gate.gate_type {
-//                             //AND gate
-//                             let real_input0: bool = value.val ^ value.flipped ^ gate.input0_flipped;
-//                             let real_input1: bool =
-//                                 value1.val ^ value1.flipped ^ gate.input1_flipped;
-//                             out = real_input0 && real_input1;
-//                         } else {
-//                             //XOR gate
-//                             out = value.val ^ value1.val;
-//                             out_flipped = (value.flipped ^ gate.input0_flipped)
-//                                 ^ (value1.flipped ^ gate.input1_flipped);
-//                         }
-//                         evaluation_map.insert(
-//                             gate.output,
-//                             PlainEvalWire {
-//                                 val: out,
-//                                 flipped: out_flipped,
-//                             },
-//                         );
-//                     }
-//                     None => {
-//                         println!("Input1 wire {} not found!!", gate.input1);
-//                         break;
-//                     }
-//                 }
-//             }
-//             None => {
-//                 println!("Input0 wire {} not found!!", gate.input0);
-//                 break;
-//             }
-//         }
-//     }
-//     //Step-1: fetch the final output wires
-//     for (i, output_wire) in circuit.output_wire_ids.iter().enumerate() {
-//         let mut real_id: usize = output_wire.id;
-//         if output_wire.should_trace {
-//             //A not gate is applied here
-//             real_id = output_wire.input_id;
-//         }
-//         match evaluation_map.get(&real_id) {
-//             Some(value) => {
-//                 output_bits[i] = (value.val ^ value.flipped) ^ output_wire.should_trace;
-//             }
-//             None => {
-//                 println!("Output wire {} not found!!", real_id);
-//             }
-//         }
-//     }
-// }
-
-// pub fn planitext_eval(message: &Vec<u8>, circuit: &mut Sha256Circuit) -> Vec<u8> {
-//     //-----Step-0: Prepare plaintext values for all input wires from two parties------------//
-//     let secret_bits = convert_bytes2_bits(message);
-
-//     //the overall padded_message with original message bits
-//     let overall_bits = get_padded_bits(message.len());
-//     let secret_bits_cnt: usize = secret_bits.len();
-//     let block_cnt: usize = overall_bits.len() / SINGLE_BLOCK_BITS_LEN;
-
-//     let mut last_evaluation_result: Vec<bool> = vec![false; OUTPUT_BITS_LEN];
-//     for i in 0..block_cnt {
-//         println!("Start to process block {}", i);
-//         // It stores every k-v: wire_number - evaluation results
-//         let mut evaluation_map: HashMap<usize, PlainEvalWire> = HashMap::new();
-
-//         //Process the second input: 256 bits state info
-//         for j in 0..STATE_INFO_BITS_LEN {
-//             let state_wire_id: usize = SINGLE_BLOCK_BITS_LEN + j;
-//             if i == 0 {
-//                 evaluation_map.insert(
-//                     state_wire_id,
-//                     PlainEvalWire {
-//                         val: circuit.get_initial_hash(j),
-//                         flipped: false,
-//                     },
-//                 );
-//             } else {
-//                 //accept internal output wire result
-//                 evaluation_map.insert(
-//                     state_wire_id,
-//                     PlainEvalWire {
-//                         val: last_evaluation_result[j],
-//                         flipped: false,
-//                     },
-//                 );
-//             }
-//         }
-
-//         //Process the first input: 512 bits block
-//         let mut extra_double_gates: Vec<XorAndGate> = Vec::new();
-//         let left_start_index: usize = i * SINGLE_BLOCK_BITS_LEN;
-//         let right_end_index: usize = (i + 1) * SINGLE_BLOCK_BITS_LEN;
-//         for j in left_start_index..right_end_index {
-//             let circuit_wire_id: usize = SINGLE_BLOCK_BITS_LEN - 1 - (j % SINGLE_BLOCK_BITS_LEN);
-
-//             if j < secret_bits_cnt {
-//                 //real secret bits input
-//                 let gate = XorAndGate {
-//                     input0: circuit.extra_input_wire + 2 * j,
-//                     input1: circuit.extra_input_wire + 2 * j + 1,
-//                     output: circuit_wire_id,
-//                     input0_flipped: false,
-//                     input1_flipped: false,
-//                     gate_type: false,
-//                 };
-//                 evaluation_map.insert(
-//                     gate.input0,
-//                     PlainEvalWire {
-//                         val: secret_bits[j],
-//                         flipped: false,
-//                     },
-//                 ); //party-0 input
-//                 evaluation_map.insert(
-//                     gate.input1,
-//                     PlainEvalWire {
-//                         val: false,
-//                         flipped: false,
-//                     },
-//                 ); //party-1 input
-//                 extra_double_gates.push(gate);
-//             } else {
-//                 //padding bits
-//                 evaluation_map.insert(
-//                     circuit_wire_id,
-//                     PlainEvalWire {
-//                         val: overall_bits[j],
-//                         flipped: false,
-//                     },
-//                 ); //party-1 input
-//             }
-//         }
-//         //update circuit gates
-//         circuit.update_extra_circuit(&extra_double_gates);
-//         plaintext_block_eval(circuit, &mut evaluation_map, &mut last_evaluation_result);
-//     }
-
-//     last_evaluation_result.reverse();
-//     convert_bits2_bytes(&last_evaluation_result)
-// }
@@ -0,0 +1,387 @@
+use sha256_gc::channel::MemoryChannel;
+use sha256_gc::circuit::HashFunction;
+use sha256_gc::gc::{GarblingMode, LABEL_SECURITY_LEVEL};
+use sha256_gc::party::{InputMode, OtProvider, OutputPolicy, Party};
+
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::io;
+use std::thread;
+
+type Outputs = (Option<Vec<u8>>, Option<Vec<u8>>);
+type Configure = fn(&mut Party);
+
+//Run the semi-honest protocol over an in-memory channel, returns what (P0, P1) learn
+fn run(mut p0: Party, mut p1: Party) -> io::Result<Outputs> {
+    let (mut c0, mut c1) = MemoryChannel::pair();
+    let evaluator = thread::spawn(move || p1.run_evaluator(&mut c1));
+    let garbled = p0.run_garbler(&mut c0);
+    let evaluated = evaluator.join().unwrap();
+    Ok((garbled?, evaluated?))
+}
+
+//XOR shares of `message`
+fn xor_shares(message: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let x0: Vec<u8> = (0..message.len()).map(|_| rand::random()).collect();
+    let x1: Vec<u8> = message.iter().zip(&x0).map(|(m, s)| m ^ s).collect();
+    (x0, x1)
+}
+
+fn parties(x0: &[u8], x1: &[u8], configure: impl Fn(&mut Party)) -> (Party, Party) {
+    let mut p0 = Party::new(0, x0).unwrap();
+    let mut p1 = Party::new(1, x1).unwrap();
+    configure(&mut p0);
+    configure(&mut p1);
+    (p0, p1)
+}
+
+#[test]
+fn share_lengths_around_the_padding_boundary() {
+    //the empty message, and 55 to 64 bytes where the padding spills into an extra block, for
+    //either share: the shorter one is zero-extended
+    let lengths: Vec<usize> = [0].into_iter().chain(55..=64).collect();
+    for &len0 in &lengths {
+        for &len1 in &lengths {
+            let x0: Vec<u8> = (0..len0).map(|i| i as u8).collect();
+            let x1: Vec<u8> = (0..len1).map(|i| (3 * i + 1) as u8).collect();
+            let mut message = vec![0u8; len0.max(len1)];
+            for (byte, share) in message.iter_mut().zip(&x0) {
+                *byte ^= share;
+            }
+            for (byte, share) in message.iter_mut().zip(&x1) {
+                *byte ^= share;
+            }
+            let (p0, p1) = parties(&x0, &x1, |_| {});
+            let digest = Sha256::digest(&message).to_vec();
+            assert_eq!(
+                run(p0, p1).unwrap(),
+                (Some(digest.clone()), Some(digest)),
+                "shares of {} and {} bytes",
+                len0,
+                len1
+            );
+        }
+    }
+}
+
+#[test]
+fn three_halves_garbling() {
+    //one block, and two blocks with the length field spilling over
+    for len in [20, 60] {
+        let message: Vec<u8> = (0..len).map(|_| rand::random()).collect();
+        let (x0, x1) = xor_shares(&message);
+        let (p0, p1) = parties(&x0, &x1, |party| {
+            party.set_garbling_mode(GarblingMode::ThreeHalves)
+        });
+        let digest = Sha256::digest(&message).to_vec();
+        assert_eq!(run(p0, p1).unwrap(), (Some(digest.clone()), Some(digest)));
+    }
+}
+
+#[test]
+fn classical_garbling_backends() {
+    let message =
+        b"point-and-permute and GRR3 garbling, long enough to span two SHA-256 blocks".to_vec();
+    let (x0, x1) = xor_shares(&message);
+    let digest = Sha256::digest(&message).to_vec();
+    //ciphertext rows per AND gate: 4 and 3 here, against 2 for half-gates and 1.5 plus the
+    //control bits for three-halves
+    assert_eq!(
+        GarblingMode::PointAndPermute.table_bytes(),
+        4 * LABEL_SECURITY_LEVEL
+    );
+    assert_eq!(GarblingMode::Grr3.table_bytes(), 3 * LABEL_SECURITY_LEVEL);
+    assert_eq!(
+        GarblingMode::HalfGates.table_bytes(),
+        2 * LABEL_SECURITY_LEVEL
+    );
+    assert_eq!(
+        GarblingMode::ThreeHalves.table_bytes(),
+        3 * LABEL_SECURITY_LEVEL / 2 + 1
+    );
+    for mode in [GarblingMode::PointAndPermute, GarblingMode::Grr3] {
+        let (p0, p1) = parties(&x0, &x1, |party| party.set_garbling_mode(mode));
+        assert_eq!(
+            run(p0, p1).unwrap(),
+            (Some(digest.clone()), Some(digest.clone()))
+        );
+    }
+}
+
+#[test]
+fn output_policies() {
+    let message = b"who learns the digest".to_vec();
+    let (x0, x1) = xor_shares(&message);
+    let digest = Sha256::digest(&message).to_vec();
+    for (policy, expected) in [
+        (OutputPolicy::Evaluator, (None, Some(digest.clone()))),
+        (OutputPolicy::Garbler, (Some(digest.clone()), None)),
+        (
+            OutputPolicy::Both,
+            (Some(digest.clone()), Some(digest.clone())),
+        ),
+    ] {
+        let (p0, p1) = parties(&x0, &x1, |party| party.set_output_policy(policy));
+        assert_eq!(run(p0, p1).unwrap(), expected);
+    }
+
+    //neither share is the digest on its own, together they XOR to it
+    let (p0, p1) = parties(&x0, &x1, |party| {
+        party.set_output_policy(OutputPolicy::Shared)
+    });
+    let (Some(share0), Some(share1)) = run(p0, p1).unwrap() else {
+        panic!("both parties keep a share");
+    };
+    assert_ne!(share0, digest);
+    assert_ne!(share1, digest);
+    let combined: Vec<u8> = share0.iter().zip(&share1).map(|(a, b)| a ^ b).collect();
+    assert_eq!(combined, digest);
+}
+
+#[test]
+fn concatenated_inputs() {
+    //inputs of independent lengths, x0 || x1 spans two blocks
+    let x0 = b"P0's part of the message, ".to_vec();
+    let x1 = b"followed by the longer part that P1 contributes".to_vec();
+    let mut p0 = Party::new(0, &x0).unwrap();
+    let mut p1 = Party::new(1, &x1).unwrap();
+    p0.set_input_mode(InputMode::Concat { peer_len: x1.len() });
+    p1.set_input_mode(InputMode::Concat { peer_len: x0.len() });
+    let digest = Sha256::digest([x0, x1].concat()).to_vec();
+    assert_eq!(run(p0, p1).unwrap(), (Some(digest.clone()), Some(digest)));
+}
+
+#[test]
+fn arithmetic_shares() {
+    //64 bytes, so carries run across every byte and word of a full block
+    let message: Vec<u8> = (0..64).map(|_| rand::random()).collect();
+    let digest = Sha256::digest(&message).to_vec();
+
+    let x0: Vec<u8> = (0..message.len()).map(|_| rand::random()).collect();
+    let x1: Vec<u8> = message
+        .iter()
+        .zip(&x0)
+        .map(|(m, s)| m.wrapping_sub(*s))
+        .collect();
+    let (p0, p1) = parties(&x0, &x1, |party| {
+        party.set_input_mode(InputMode::Arithmetic { word_bits: 8 })
+    });
+    assert_eq!(
+        run(p0, p1).unwrap(),
+        (Some(digest.clone()), Some(digest.clone()))
+    );
+
+    let (mut x0, mut x1) = (Vec::new(), Vec::new());
+    for word in message.chunks(4) {
+        let word = u32::from_be_bytes(word.try_into().unwrap());
+        let share: u32 = rand::random();
+        x0.extend_from_slice(&share.to_be_bytes());
+        x1.extend_from_slice(&word.wrapping_sub(share).to_be_bytes());
+    }
+    let (p0, p1) = parties(&x0, &x1, |party| {
+        party.set_input_mode(InputMode::Arithmetic { word_bits: 32 })
+    });
+    assert_eq!(run(p0, p1).unwrap(), (Some(digest.clone()), Some(digest)));
+}
+
+#[test]
+fn hidden_length_bound() {
+    //60-byte inputs: the bound itself hashes the whole input, one byte more must abort
+    const MAX_LEN: usize = 60;
+    let message: Vec<u8> = (0..MAX_LEN).map(|_| rand::random()).collect();
+    let (x0, x1) = xor_shares(&message);
+    let share0: usize = rand::random::<usize>() % 64;
+    let (mut p0, mut p1) = parties(&x0, &x1, |_| {});
+    p0.set_length_share(share0);
+    p1.set_length_share(share0 ^ MAX_LEN);
+    let digest = Sha256::digest(&message).to_vec();
+    assert_eq!(run(p0, p1).unwrap(), (Some(digest.clone()), Some(digest)));
+
+    for (share0, share1) in [(63, 0), (share0, share0 ^ (MAX_LEN + 1))] {
+        let (mut p0, mut p1) = parties(&x0, &x1, |_| {});
+        p0.set_length_share(share0);
+        p1.set_length_share(share1);
+        let rejected = run(p0, p1).unwrap_err();
+        assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            rejected.to_string(),
+            "The hidden message length exceeds the maximum length of 60 bytes"
+        );
+    }
+}
+
+#[test]
+fn session_parameters_must_agree() {
+    let (x0, x1) = xor_shares(b"abc");
+    let mismatches: [(Configure, &str); 7] = [
+        (
+            |party| party.set_hash_function(HashFunction::Sha224).unwrap(),
+            "The parties disagree on the hash function: Sha256 here, Sha224 at the peer",
+        ),
+        (
+            |party| party.set_ot_provider(OtProvider::Silent),
+            "The parties disagree on the OT provider: Iknp here, Silent at the peer",
+        ),
+        (
+            |party| party.set_garbling_mode(GarblingMode::Grr3),
+            "The parties disagree on the garbling mode: HalfGates here, Grr3 at the peer",
+        ),
+        (
+            |party| party.set_output_policy(OutputPolicy::Shared),
+            "The parties disagree on the output policy: Both here, Shared at the peer",
+        ),
+        (
+            |party| party.set_public_prefix(b"prefix"),
+            "The parties disagree on the public prefix",
+        ),
+        (
+            |party| party.set_input_mode(InputMode::Arithmetic { word_bits: 8 }),
+            "The parties disagree on the arithmetic word size: None here, Some(8) at the peer",
+        ),
+        (
+            |party| party.set_length_share(3),
+            "The parties disagree on the maximum hidden length: None here, Some(3) at the peer",
+        ),
+    ];
+    for (configure_p1, expected) in mismatches {
+        let (p0, mut p1) = parties(&x0, &x1, |_| {});
+        configure_p1(&mut p1);
+        let rejected = run(p0, p1).unwrap_err();
+        assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(rejected.to_string(), expected);
+    }
+}
+
+#[test]
+fn security_modes_must_agree() {
+    //a semi-honest garbler facing a dual-execution evaluator would wait for a circuit forever
+    let (x0, x1) = xor_shares(b"abc");
+    let (mut p0, mut p1) = parties(&x0, &x1, |_| {});
+    let (mut c0, mut c1) = MemoryChannel::pair();
+    let p1 = thread::spawn(move || p1.run_dual_execution(&mut c1));
+    let rejected = p0.run_garbler(&mut c0).unwrap_err();
+    assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        rejected.to_string(),
+        "The parties disagree on the security mode: SemiHonest here, DualExecution at the peer"
+    );
+    assert_eq!(
+        p1.join().unwrap().unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn cut_and_choose_copy_counts_must_agree() {
+    let (x0, x1) = xor_shares(b"abc");
+    let (mut p0, mut p1) = parties(&x0, &x1, |_| {});
+    let (mut c0, mut c1) = MemoryChannel::pair();
+    let p1 = thread::spawn(move || p1.run_cut_and_choose_evaluator(7, &mut c1));
+    let rejected = p0.run_cut_and_choose_garbler(5, &mut c0).unwrap_err();
+    assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        rejected.to_string(),
+        "The parties disagree on the cut-and-choose copy count: Some(5) here, Some(7) at the peer"
+    );
+    assert!(p1.join().unwrap().is_err());
+}
+
+#[test]
+fn input_modes_must_agree() {
+    let (x0, x1) = xor_shares(b"abcd");
+    let (p0, mut p1) = parties(&x0, &x1, |party| {
+        party.set_input_mode(InputMode::Arithmetic { word_bits: 8 })
+    });
+    p1.set_input_mode(InputMode::Arithmetic { word_bits: 32 });
+    let rejected = run(p0, p1).unwrap_err();
+    assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        rejected.to_string(),
+        "The parties disagree on the arithmetic word size: Some(8) here, Some(32) at the peer"
+    );
+}
+
+#[test]
+fn hidden_length_bounds_must_agree() {
+    //both lengths are hidden, but the inputs bound them to 3 and 4 bytes
+    let mut p0 = Party::new(0, b"abc").unwrap();
+    let mut p1 = Party::new(1, b"abcd").unwrap();
+    p0.set_length_share(1);
+    p1.set_length_share(2);
+    let rejected = run(p0, p1).unwrap_err();
+    assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        rejected.to_string(),
+        "The parties disagree on the maximum hidden length: Some(3) here, Some(4) at the peer"
+    );
+}
+
+#[test]
+fn declared_concat_lengths_must_agree() {
+    //P1 holds 5 bytes but P0 expects 4
+    let mut p0 = Party::new(0, b"abc").unwrap();
+    let mut p1 = Party::new(1, b"defgh").unwrap();
+    p0.set_input_mode(InputMode::Concat { peer_len: 4 });
+    p1.set_input_mode(InputMode::Concat { peer_len: 3 });
+    let rejected = run(p0, p1).unwrap_err();
+    assert_eq!(rejected.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        rejected.to_string(),
+        "Declared input lengths disagree: this party holds 24 bits and expects 32 from the peer, \
+         the peer holds 40 bits and expects 24"
+    );
+}
+
+#[test]
+fn bit_lengths_around_the_padding_boundaries() {
+    //SHAVS-style bit-oriented messages: the 1 marker lands right before the length field (447),
+    //pushes it into a new block (448), ends a block (511) or opens one (513). The digests were
+    //computed with an independent FIPS 180-4 implementation
+    let message = hex::decode(
+        "0b30557a9fc4e90e33587da2c7ec11365b80a5caef14395e83a8cdf2173c6186\
+         abd0f51a3f6489aed3f81d42678cb1d6fb20456a8fb4d9fe23486d92b7dc01264b",
+    )
+    .unwrap();
+    let (x0, x1) = xor_shares(&message);
+    for (bits_cnt, expected) in [
+        (
+            447,
+            "debb15d7c8a946e6ab159390ebec6b1a166ebd214c0516fcb13506d66ec7adad",
+        ),
+        (
+            448,
+            "31454ff48ef36af2f08fd511bdc37d9d5855ac23e992e5ff5445cb6b7674a674",
+        ),
+        (
+            511,
+            "41c9cd8823c1fe7dda3fb8ea9430552b77a7161cd8bb9d8a6d5890af3edeac41",
+        ),
+        (
+            513,
+            "b0cc266c5fc2b4efd6690d4ebb4cf6cba7b3a4bc166d54e92279195d33173d68",
+        ),
+    ] {
+        let (p0, p1) = parties(&x0, &x1, |party| {
+            party.set_message_bits_cnt(bits_cnt).unwrap()
+        });
+        let digest = hex::decode(expected).unwrap();
+        assert_eq!(run(p0, p1).unwrap(), (Some(digest.clone()), Some(digest)));
+    }
+}
+
+#[test]
+fn sha512_family() {
+    //111 bytes fill one 1024-bit block, at 112 the 128-bit length field spills into a second
+    for len in [111, 112, 128] {
+        let message: Vec<u8> = (0..len).map(|_| rand::random()).collect();
+        let (x0, x1) = xor_shares(&message);
+        for (hash_function, digest) in [
+            (HashFunction::Sha512, Sha512::digest(&message).to_vec()),
+            (HashFunction::Sha384, Sha384::digest(&message).to_vec()),
+        ] {
+            let (p0, p1) = parties(&x0, &x1, |party| {
+                party.set_hash_function(hash_function).unwrap()
+            });
+            assert_eq!(run(p0, p1).unwrap(), (Some(digest.clone()), Some(digest)));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use sha256_gc::channel::{Channel, MemoryChannel};
+use sha256_gc::gc::{GarbledTables, WireLabel};
+use sha256_gc::party::{GarbledBlock, Party};
+
+use sha2::{Digest, Sha256};
+use std::io;
+use std::thread;
+
+//Garbled blocks are the only frames this large, everything else is at most a few KB
+const GARBLED_BLOCK_MIN_BYTES: usize = 100_000;
+
+//Flips every AND row of the first garbled block the wrapped party sends
+struct CorruptFirstBlock(MemoryChannel, bool);
+
+impl Channel for CorruptFirstBlock {
+    fn send_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.1 || data.len() < GARBLED_BLOCK_MIN_BYTES {
+            return self.0.send_bytes(data);
+        }
+        self.1 = true;
+        let mut block: GarbledBlock = bincode::deserialize(data).unwrap();
+        if let GarbledTables::HalfGates(tables) = &mut block.garbled_and {
+            for table in tables.iter_mut() {
+                table.t_g ^= WireLabel::from_data([1; 16]);
+                table.t_e ^= WireLabel::from_data([1; 16]);
+            }
+        }
+        self.0.send_bytes(&bincode::serialize(&block).unwrap())
+    }
+
+    fn recv_bytes(&mut self) -> io::Result<Vec<u8>> {
+        self.0.recv_bytes()
+    }
+}
+
+#[test]
+fn second_round_garbler_is_caught() {
+    //P1 garbles the second execution, its corrupted tables only reach P0
+    let (mut c0, c1) = MemoryChannel::pair();
+    let p1 = thread::spawn(move || {
+        let mut p1 = Party::new(1, b"xyz").unwrap();
+        p1.run_dual_execution(&mut CorruptFirstBlock(c1, false))
+    });
+    let mut p0 = Party::new(0, b"abc").unwrap();
+    let aborted = p0.run_dual_execution(&mut c0).unwrap_err();
+    assert_eq!(aborted.kind(), io::ErrorKind::InvalidData);
+    assert!(aborted.to_string().contains("equality check failed"));
+    assert!(p1.join().unwrap().is_err());
+}
+
+#[test]
+fn hidden_length() {
+    //a 13-byte message inside 40-byte inputs, no party learns the true length
+    const MAX_LEN: usize = 40;
+    let message = b"hidden length";
+    let mut padded = message.to_vec();
+    padded.resize(MAX_LEN, 0);
+    let x0: Vec<u8> = (0..MAX_LEN).map(|_| rand::random()).collect();
+    let x1: Vec<u8> = padded.iter().zip(&x0).map(|(m, s)| m ^ s).collect();
+    let share0: usize = rand::random::<usize>() % 64;
+    let share1 = share0 ^ message.len();
+
+    let (mut c0, mut c1) = MemoryChannel::pair();
+    let p1 = thread::spawn(move || {
+        let mut p1 = Party::new(1, &x1).unwrap();
+        p1.set_length_share(share1);
+        p1.run_dual_execution(&mut c1)
+    });
+    let mut p0 = Party::new(0, &x0).unwrap();
+    p0.set_length_share(share0);
+    let digest = Sha256::digest(message).to_vec();
+    assert_eq!(p0.run_dual_execution(&mut c0).unwrap(), digest);
+    assert_eq!(p1.join().unwrap().unwrap(), digest);
+}
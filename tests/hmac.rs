@@ -0,0 +1,91 @@
+use sha256_gc::channel::MemoryChannel;
+use sha256_gc::hmac::HmacMessage;
+use sha256_gc::party::Party;
+
+use std::thread;
+
+//Test cases 1 to 4, 6 and 7 of RFC 4231 (5 truncates the MAC): key, data, HMAC-SHA256
+fn rfc4231() -> Vec<(Vec<u8>, Vec<u8>, &'static str)> {
+    vec![
+        (
+            vec![0x0b; 20],
+            b"Hi There".to_vec(),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+        ),
+        (
+            b"Jefe".to_vec(),
+            b"what do ya want for nothing?".to_vec(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        ),
+        (
+            vec![0xaa; 20],
+            vec![0xdd; 50],
+            "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+        ),
+        (
+            (0x01..=0x19).collect(),
+            vec![0xcd; 50],
+            "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+        ),
+        //a key longer than a block is hashed inside the circuit first
+        (
+            vec![0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+        ),
+        (
+            vec![0xaa; 131],
+            b"This is a test using a larger than block-size key and a larger than block-size data. \
+              The key needs to be hashed before being used by the HMAC algorithm."
+                .to_vec(),
+            "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+        ),
+    ]
+}
+
+//Run HMAC over XOR shares of `key`, returns the MACs (P0, P1) learn
+fn run(key: &[u8], m0: HmacMessage, m1: HmacMessage) -> (Vec<u8>, Vec<u8>) {
+    let k0: Vec<u8> = (0..key.len()).map(|_| rand::random()).collect();
+    let k1: Vec<u8> = key.iter().zip(&k0).map(|(k, s)| k ^ s).collect();
+    let (mut c0, mut c1) = MemoryChannel::pair();
+    let p1 = thread::spawn(move || {
+        let mut p1 = Party::new(1, &k1).unwrap();
+        p1.run_hmac_evaluator(&m1, &mut c1).unwrap()
+    });
+    let mut p0 = Party::new(0, &k0).unwrap();
+    let mac = p0.run_hmac_garbler(&m0, &mut c0).unwrap();
+    (mac, p1.join().unwrap())
+}
+
+#[test]
+fn public_message() {
+    for (key, data, expected) in rfc4231() {
+        let (mac0, mac1) = run(
+            &key,
+            HmacMessage::Public(data.clone()),
+            HmacMessage::Public(data),
+        );
+        assert_eq!(hex::encode(&mac0), expected);
+        assert_eq!(mac1, mac0);
+    }
+}
+
+#[test]
+fn garbler_holds_the_message() {
+    for (key, data, expected) in rfc4231() {
+        let len = data.len();
+        let (mac0, mac1) = run(&key, HmacMessage::Own(data), HmacMessage::Peer(len));
+        assert_eq!(hex::encode(&mac0), expected);
+        assert_eq!(mac1, mac0);
+    }
+}
+
+#[test]
+fn evaluator_holds_the_message() {
+    for (key, data, expected) in rfc4231() {
+        let len = data.len();
+        let (mac0, mac1) = run(&key, HmacMessage::Peer(len), HmacMessage::Own(data));
+        assert_eq!(hex::encode(&mac0), expected);
+        assert_eq!(mac1, mac0);
+    }
+}